@@ -0,0 +1,85 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use gbc_emulator::gbc::bench_support;
+use tracing::Level;
+
+// Timer on, then increment every byte of 0xC000..=0xC0FF in a loop
+const PROGRAM: [u8; 14] = [
+    0x3E, 0x05, 0xEA, 0x07, 0xFF, // LD A,0x05; LD (TAC),A
+    0x21, 0x00, 0xC0, // LD HL,0xC000
+    0x7E, 0x3C, 0x77, 0x2C, 0x18, 0xFA, // loop: LD A,(HL); INC A; LD (HL),A; INC L; JR loop
+];
+
+fn instruction_dispatch(c: &mut Criterion) {
+    c.bench_function("map_instruction", |b| {
+        b.iter(|| {
+            for opcode in 0..=0xFF {
+                black_box(bench_support::map_instruction(black_box(opcode)));
+            }
+        })
+    });
+}
+
+fn memory(c: &mut Criterion) {
+    let mut state = bench_support::state_with_program(&PROGRAM);
+    // Plain memory goes through the page table, high RAM through the slow IO page path
+    let areas = [("wram", 0xC000), ("vram", 0x8000), ("high_ram", 0xFF80)];
+
+    let mut group = c.benchmark_group("virtual_memory");
+    for (name, base) in areas {
+        group.bench_function(format!("read_{}", name), |b| {
+            b.iter(|| {
+                for offset in 0..0x40 {
+                    black_box(bench_support::read(&state, black_box(base + offset)));
+                }
+            })
+        });
+        group.bench_function(format!("write_{}", name), |b| {
+            b.iter(|| {
+                for offset in 0..0x40 {
+                    bench_support::write(&mut state, black_box(base + offset), offset as u8);
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn pixel_fetcher(c: &mut Criterion) {
+    let mut state = bench_support::state_with_program(&PROGRAM);
+    c.bench_function("pixel_fetcher_tick", |b| {
+        b.iter(|| bench_support::tick_pixel_fetcher(&mut state))
+    });
+}
+
+fn frame(c: &mut Criterion) {
+    let mut state = bench_support::state_with_program(&PROGRAM);
+    c.bench_function("frame", |b| b.iter(|| bench_support::run_frame(&mut state)));
+}
+
+// Compare runs with and without --features trace-instrumentation to see what the hot path spans
+// cost at the app's default level and with RUST_LOG=debug
+fn frame_with_logging(c: &mut Criterion) {
+    let levels = [("info", Level::INFO), ("debug", Level::DEBUG)];
+    for (name, level) in levels {
+        let mut state = bench_support::state_with_program(&PROGRAM);
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(level)
+            .with_writer(std::io::sink)
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            c.bench_function(&format!("frame_with_{}_logging", name), |b| {
+                b.iter(|| bench_support::run_frame(&mut state))
+            });
+        });
+    }
+}
+
+criterion_group!(
+    benches,
+    instruction_dispatch,
+    memory,
+    pixel_fetcher,
+    frame,
+    frame_with_logging
+);
+criterion_main!(benches);
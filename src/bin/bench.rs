@@ -0,0 +1,43 @@
+use std::time::Instant;
+
+use color_eyre::eyre::{eyre, Result};
+use gbc_emulator::gbc::{HeadlessGBC, NATIVE_FRAMES_PER_SECOND};
+
+const DEFAULT_FRAMES: u64 = 3600;
+
+/**
+ * Run a ROM headless for a number of frames and report how fast it was emulated.
+ *
+ * Usage: bench <rom path> [frames]
+ */
+fn main() -> Result<()> {
+    color_eyre::install()?;
+
+    let mut args = std::env::args().skip(1);
+    let rom_path = args
+        .next()
+        .ok_or_else(|| eyre!("Usage: bench <rom path> [frames]"))?;
+    let frames = match args.next() {
+        Some(frames) => frames.parse()?,
+        None => DEFAULT_FRAMES,
+    };
+
+    let rom_data = std::fs::read(&rom_path)?;
+    let mut gbc = HeadlessGBC::new(rom_data)?;
+
+    let start = Instant::now();
+    gbc.run_frames(frames);
+    let elapsed = start.elapsed().as_secs_f64();
+
+    let fps = frames as f64 / elapsed;
+    println!("ROM:               {}", rom_path);
+    println!("Frames:            {}", frames);
+    println!("Time:              {:.3} s", elapsed);
+    println!(
+        "Emulated FPS:      {:.1} ({:.0}% of real hardware)",
+        fps,
+        100.0 * fps / NATIVE_FRAMES_PER_SECOND as f64
+    );
+    println!("Cycles per second: {:.0}", gbc.machine_cycles() as f64 / elapsed);
+    Ok(())
+}
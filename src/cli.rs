@@ -0,0 +1,284 @@
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::Arc,
+    time::{Instant, SystemTime},
+};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use color_eyre::eyre::Result;
+use tracing::warn;
+
+use gbc_emulator::gbc::{
+    screenshot_file_name, write_screenshot_png, CartridgeHeader, CheatList, GBCConfig, GDBStub,
+    HeadlessGBC, Model, Movie, RecordingFormat, SymbolTable, TestVerdict, DEFAULT_GDB_PORT,
+    MAX_SCREENSHOT_SCALE,
+};
+
+const DEFAULT_FRAMES: u64 = 600;
+// A minute of emulated time
+const DEFAULT_TEST_ROM_FRAMES: u64 = 3600;
+
+#[derive(Parser)]
+#[command(version, about = "Game Boy Color emulator")]
+pub struct Cli {
+    /// ROM to open in the window. One can be picked from the File menu if omitted
+    pub rom: Option<PathBuf>,
+    #[command(flatten)]
+    pub hardware: HardwareArgs,
+    #[command(flatten)]
+    pub window: WindowArgs,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Args, Clone)]
+pub struct HardwareArgs {
+    /// Hardware to emulate. Only the CGB is supported so far
+    #[arg(long, value_enum, default_value_t = ModelArg::Cgb, global = true)]
+    pub model: ModelArg,
+    /// Boot ROM to run before the cartridge
+    #[arg(long, global = true)]
+    pub boot_rom: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct WindowArgs {
+    /// Size of the screen as a multiple of 160x144
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u8).range(1..=8))]
+    pub scale: u8,
+    /// Directory battery backed cartridge RAM is saved in. Nothing is saved if omitted
+    #[arg(long)]
+    pub save_dir: Option<PathBuf>,
+    /// Start in fullscreen
+    #[arg(long)]
+    pub fullscreen: bool,
+    /// Open the ROM paused
+    #[arg(long)]
+    pub start_paused: bool,
+    /// Directory screenshots taken with F12 are saved in
+    #[arg(long, default_value = ".")]
+    pub screenshot_dir: PathBuf,
+    /// Size of screenshots as a multiple of 160x144
+    #[arg(
+        long,
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u8).range(1..=MAX_SCREENSHOT_SCALE as i64)
+    )]
+    pub screenshot_scale: u8,
+    /// Directory recordings started from the File menu are saved in
+    #[arg(long, default_value = ".")]
+    pub recording_dir: PathBuf,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+pub enum ModelArg {
+    Dmg,
+    Cgb,
+}
+
+impl From<ModelArg> for Model {
+    fn from(model: ModelArg) -> Self {
+        match model {
+            ModelArg::Dmg => Model::DMG,
+            ModelArg::Cgb => Model::CGB,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run a ROM without a window and report how long it took
+    Run {
+        rom: PathBuf,
+        #[arg(long, default_value_t = DEFAULT_FRAMES)]
+        frames: u64,
+        /// Count cycles per function and save them here as folded stacks for flame graph tools
+        #[arg(long)]
+        profile: Option<PathBuf>,
+        /// Record every frame here. An uncompressed AVI if it ends in .avi, otherwise a
+        /// directory of numbered PNGs and a frames.csv with the time of each
+        #[arg(long)]
+        record: Option<PathBuf>,
+    },
+    /// Run a ROM without a window and save the last frame as a PNG
+    Screenshot {
+        rom: PathBuf,
+        #[arg(long, default_value_t = DEFAULT_FRAMES)]
+        frames: u64,
+        /// Named after the ROM title and the current time if omitted
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Size of the PNG as a multiple of 160x144
+        #[arg(
+            long,
+            default_value_t = 1,
+            value_parser = clap::value_parser!(u8).range(1..=MAX_SCREENSHOT_SCALE as i64)
+        )]
+        scale: u8,
+    },
+    /// Run a test ROM until it reports a result and print the verdict. Exits with 1 unless it
+    /// passed
+    TestRom {
+        rom: PathBuf,
+        /// Give up after this many frames
+        #[arg(long, default_value_t = DEFAULT_TEST_ROM_FRAMES)]
+        max_frames: u64,
+    },
+    /// Play a movie recorded in the window without a window and check it stays in sync. Exits with
+    /// 1 if it desyncs
+    PlayMovie {
+        rom: PathBuf,
+        movie: PathBuf,
+    },
+    /// Print the cartridge header of a ROM
+    Header { rom: PathBuf },
+    /// Run a ROM without a window under the control of a debugger speaking the GDB remote protocol
+    Gdb {
+        rom: PathBuf,
+        /// Local port to wait for the debugger on
+        #[arg(long, default_value_t = DEFAULT_GDB_PORT)]
+        port: u16,
+    },
+}
+
+impl HardwareArgs {
+    /**
+     * Build the emulator config for a ROM. Cartridge RAM is saved in save_dir, named after the ROM.
+     * Symbols are loaded from the RGBDS .sym file next to the ROM if there is one, and so are the
+     * cheats saved for it. Either file is skipped with a warning if it can't be loaded
+     */
+    pub fn config_for(&self, rom_path: &Path, save_dir: Option<&Path>) -> Result<GBCConfig> {
+        let boot_rom = match self.boot_rom {
+            Some(ref path) => Some(fs::read(path)?),
+            None => None,
+        };
+        let save_file = save_dir.map(|dir| {
+            let rom_name = rom_path.file_stem().unwrap_or(rom_path.as_os_str());
+            dir.join(format!("{}.sav", rom_name.to_string_lossy()))
+        });
+        // Symbols and cheats are optional, so a broken file shouldn't stop the ROM from running
+        let symbols = SymbolTable::load_for_rom(rom_path).unwrap_or_else(|e| {
+            warn!("Ignoring the symbol file next to the ROM: {}", e);
+            SymbolTable::default()
+        });
+        let cheats = CheatList::load_for_rom(rom_path).unwrap_or_else(|e| {
+            warn!("Ignoring the cheat file next to the ROM: {}", e);
+            CheatList::default()
+        });
+        Ok(GBCConfig {
+            model: self.model.into(),
+            boot_rom,
+            save_file,
+            symbols: Arc::new(symbols),
+            cheats,
+            ..GBCConfig::default()
+        })
+    }
+}
+
+/**
+ * Run a subcommand without opening a window
+ */
+pub fn run_command(command: Command, hardware: &HardwareArgs) -> Result<ExitCode> {
+    match command {
+        Command::Run {
+            rom,
+            frames,
+            profile,
+            record,
+        } => {
+            let mut gbc = load_headless(&rom, hardware)?;
+            gbc.set_profiling(profile.is_some());
+            if let Some(ref path) = record {
+                gbc.start_recording(path, recording_format(path))?;
+            }
+            let start = Instant::now();
+            gbc.run_frames(frames);
+            println!(
+                "Ran {} frames ({} machine cycles) in {:.3} s",
+                frames,
+                gbc.machine_cycles(),
+                start.elapsed().as_secs_f64()
+            );
+            if let Some(path) = profile {
+                let file = BufWriter::new(File::create(&path)?);
+                gbc.profile_report().write_folded(file)?;
+                println!("Saved profile to {}", path.display());
+            }
+            if record.is_some() {
+                let path = gbc.stop_recording()?;
+                println!("Saved recording to {}", path.display());
+            }
+        }
+        Command::Screenshot {
+            rom,
+            frames,
+            output,
+            scale,
+        } => {
+            let output = match output {
+                Some(output) => output,
+                None => {
+                    let title = CartridgeHeader::parse(&fs::read(&rom)?)?.title;
+                    PathBuf::from(screenshot_file_name(&title, SystemTime::now()))
+                }
+            };
+            let mut gbc = load_headless(&rom, hardware)?;
+            gbc.run_frames(frames);
+            write_screenshot_png(&output, gbc.frame_buffer(), scale)?;
+            println!("Saved frame {} to {}", frames, output.display());
+        }
+        Command::TestRom { rom, max_frames } => {
+            let mut gbc = load_headless(&rom, hardware)?;
+            let verdict = gbc.run_test_rom(max_frames);
+            let output = String::from_utf8_lossy(gbc.serial_output());
+            if !output.is_empty() {
+                println!("{}", output.trim_end());
+            }
+            println!("{:?}", verdict);
+            if verdict != TestVerdict::Passed {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Command::PlayMovie { rom, movie } => {
+            let movie = Movie::load(&movie)?;
+            let frames = movie.frames.len();
+            let mut config = hardware.config_for(&rom, None)?;
+            // Play on the hardware it was recorded on
+            config.model = movie.sync.model;
+            let mut gbc = HeadlessGBC::with_config(fs::read(&rom)?, &config)?;
+            match gbc.play_movie(movie)? {
+                None => println!("Played {} frames in sync", frames),
+                Some(frame) => {
+                    println!("Desynced on frame {} of {}", frame, frames);
+                    return Ok(ExitCode::FAILURE);
+                }
+            }
+        }
+        Command::Header { rom } => {
+            let rom_data = fs::read(rom)?;
+            println!("{}", CartridgeHeader::parse(&rom_data)?);
+        }
+        Command::Gdb { rom, port } => {
+            let rom_data = fs::read(&rom)?;
+            let mut stub = GDBStub::new(rom_data, &hardware.config_for(&rom, None)?)?;
+            stub.serve(("127.0.0.1", port))?;
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn recording_format(path: &Path) -> RecordingFormat {
+    match path.extension().is_some_and(|extension| extension == "avi") {
+        true => RecordingFormat::Avi,
+        false => RecordingFormat::PngSequence,
+    }
+}
+
+fn load_headless(rom_path: &Path, hardware: &HardwareArgs) -> Result<HeadlessGBC> {
+    let rom_data = fs::read(rom_path)?;
+    HeadlessGBC::with_config(rom_data, &hardware.config_for(rom_path, None)?)
+}
@@ -1,69 +1,706 @@
+/**
+ * Entry points into emulator internals for the criterion benchmarks. Not a stable API
+ */
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod bench_support;
+mod boot_rom;
+mod cartridge_header;
+mod cheats;
 mod cpu;
-mod delay_action;
+mod debug_snapshot;
+mod disassembler;
 mod dma_controller;
+mod frame_pacer;
+mod gdb_stub;
 mod interrupt_controller;
+mod joypad_controller;
 mod lcd_controller;
+mod memory_search;
+mod movie;
+mod profiler;
 mod render_engine;
+mod rewind;
+mod scheduler;
+mod screenshot;
+mod serial_controller;
+mod snapshot;
+mod state_hash;
+mod symbols;
 mod timer_controller;
+mod video_recorder;
 mod virtual_memory;
 
-use std::sync::{Arc, Mutex};
+use std::{
+    fs, mem,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{Receiver, Sender, TryRecvError},
+        Arc, Mutex,
+    },
+    time::SystemTime,
+};
 
-use crate::gbc::cpu::CPU;
+use crate::gbc::cpu::{Register, CPU};
 use crate::gbc::virtual_memory::VirtualMemory;
 
-use self::delay_action::DelayedActions;
 use self::dma_controller::DMAController;
+use self::frame_pacer::FramePacer;
 use self::interrupt_controller::InterruptController;
+use self::joypad_controller::JoypadController;
 use self::lcd_controller::LCDController;
+use self::movie::{InputLog, PlaybackEvent};
+use self::profiler::Profiler;
 use self::render_engine::Renderer;
+use self::rewind::RewindBuffer;
+use self::scheduler::{Event, Scheduler};
+use self::serial_controller::SerialController;
 use self::timer_controller::TimerController;
+use self::video_recorder::VideoRecorder;
 
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{eyre, Result};
 use egui_extras::RetainedImage;
+use tracing::{info, warn};
+
+pub use self::cartridge_header::{CGBSupport, CartridgeHeader};
+pub use self::cheats::{Cheat, CheatCode, CheatList, CHEAT_FILE_EXTENSION};
+pub use self::cpu::CallKind;
+pub use self::debug_snapshot::{
+    CallStackEntry, DebugSnapshot, MemoryViewBanks, PaletteKind, TileMapEntry, PALETTES_PER_KIND,
+    TILES_PER_BANK, TILE_MAP_SIZE,
+};
+pub use self::disassembler::DisassembledInstruction;
+pub use self::frame_pacer::{EmulationSpeed, NATIVE_FRAMES_PER_SECOND};
+pub use self::gdb_stub::{GDBStub, DEFAULT_GDB_PORT};
+pub use self::joypad_controller::{Button, Buttons};
+pub use self::lcd_controller::{LCDControl, TileDataArea, TileMapArea, VRAMBank};
+pub use self::memory_search::{find_pattern, MemorySearch, SearchFilter};
+pub use self::movie::{Movie, SyncSettings, MOVIE_EXTENSION};
+pub use self::profiler::{FunctionProfile, InstructionProfile, ProfileReport};
+pub use self::render_engine::{
+    OAMEntry, TileAttributes, GBC_RESOLUTION_X, GBC_RESOLUTION_Y, MAX_OBJS_PER_LINE,
+    OAM_ENTRY_COUNT,
+};
+pub use self::screenshot::{
+    capture_name, screenshot_file_name, write_screenshot_png, MAX_SCREENSHOT_SCALE,
+};
+pub use self::symbols::SymbolTable;
+pub use self::video_recorder::RecordingFormat;
+pub use self::virtual_memory::{memory_area_at, MemoryAreaName};
 
 const MACHINE_CYCLES_PER_FRAME: u16 = 17556;
 
+// Registers B, C, D, E, H and L when a Mooneye test ROM passes or fails
+const MOONEYE_PASS_SIGNATURE: [u8; 6] = [3, 5, 8, 13, 21, 34];
+const MOONEYE_FAIL_SIGNATURE: [u8; 6] = [0x42; 6];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Model {
+    DMG,
+    CGB,
+}
+
+/**
+ * Hardware and cartridge options the emulator is started with
+ */
+#[derive(Debug, Clone)]
+pub struct GBCConfig {
+    pub model: Model,
+    // Run this before the cartridge instead of starting at the cartridge entry point
+    pub boot_rom: Option<Vec<u8>>,
+    // Battery backed cartridge RAM is loaded from here on start and saved on shutdown
+    pub save_file: Option<PathBuf>,
+    // Labels shown in traces and usable by the debugger
+    pub symbols: Arc<SymbolTable>,
+    // Where SaveScreenshot puts screenshots. The working directory if empty
+    pub screenshot_dir: PathBuf,
+    // Where StartRecording puts recordings. The working directory if empty
+    pub recording_dir: PathBuf,
+    // The enabled ones are used from power on. Replaced by SetCheats
+    pub cheats: CheatList,
+}
+
+impl Default for GBCConfig {
+    fn default() -> Self {
+        Self {
+            model: Model::CGB,
+            boot_rom: None,
+            save_file: None,
+            symbols: Arc::default(),
+            screenshot_dir: PathBuf::new(),
+            recording_dir: PathBuf::new(),
+            cheats: CheatList::default(),
+        }
+    }
+}
+
+/**
+ * Events published by the emulator for the frontend
+ */
+#[derive(Debug)]
+pub enum GBCEvent {
+    // An illegal opcode was executed and the CPU has locked up
+    CPULockedUp { pc: u16, opcode: u8 },
+    // Achieved frame rate, sent about once per second while running
+    FrameRate { fps: f32, speed_percent: f32 },
+    // Memory for the debug windows. Sent every frame while debug capture is enabled
+    DebugSnapshot(Box<DebugSnapshot>),
+    // Reply to RequestProfile
+    Profile(Box<ProfileReport>),
+    ScreenshotSaved(PathBuf),
+    ScreenshotFailed(String),
+    RecordingStarted(PathBuf),
+    // The recording was finished and can be played
+    RecordingStopped(PathBuf),
+    // Starting, writing or finishing a recording failed. Nothing more is recorded
+    RecordingFailed(String),
+    MovieRecordingStarted,
+    MovieSaved(PathBuf),
+    MoviePlaybackStarted { frames: u64 },
+    // The state stopped matching the one the movie was recorded with. Playback carries on
+    MovieDesynced { frame: u64 },
+    // The movie ran out of input or was stopped, and the joypad is the frontend's again
+    MoviePlaybackEnded { desynced: bool },
+    // A movie couldn't be saved or played
+    MovieFailed(String),
+}
+
+/**
+ * Commands sent by the frontend to control the emulator thread
+ */
+#[derive(Debug, Clone)]
+pub enum GBCCommand {
+    Pause,
+    Resume,
+    // Restart the cartridge but keep the contents of cartridge RAM
+    SoftReset,
+    // Power cycle. Everything is reinitialized from the ROM, except cartridge RAM kept by a battery
+    HardReset,
+    SetSpeed(EmulationSpeed),
+    // Play the game backwards until StopRewind is received
+    StartRewind,
+    StopRewind,
+    // Start or stop publishing DebugSnapshot events
+    SetDebugCapture(bool),
+    // Start or stop copying the whole address space into DebugSnapshot for the memory viewer
+    SetMemoryCapture(bool),
+    // Choose which banks the memory viewer in DebugSnapshot shows
+    SetMemoryViewBanks(MemoryViewBanks),
+    // Poke memory without running write triggers. Writes to the active bank if bank is None
+    WriteMemory {
+        addr: u16,
+        bank: Option<usize>,
+        val: u8,
+    },
+    // Overwrite one color in palette memory
+    SetPaletteColor {
+        kind: PaletteKind,
+        palette: u8,
+        color_idx: u8,
+        rgb555: u16,
+    },
+    // Start or stop counting cycles per instruction and call stack
+    SetProfiling(bool),
+    ResetProfile,
+    // Ask for a Profile event with the cycles counted so far
+    RequestProfile,
+    // Save the current frame as a PNG in the screenshot directory, scaled up by an integer factor
+    SaveScreenshot { scale: u8 },
+    // Record every published frame to a new file in the recording directory until StopRecording
+    StartRecording(RecordingFormat),
+    StopRecording,
+    // Buttons held on the joypad. Applied at the start of the next frame
+    SetButtons(Buttons),
+    // Log input to a movie until StopMovie, either after a reset or from the current state
+    StartMovieRecording { from_power_on: bool },
+    // Reset and replay a movie file. The frontend's input is ignored until it ends
+    PlayMovie(PathBuf),
+    // Save the movie being recorded in the recording directory, or stop playing one
+    StopMovie,
+    // Use the enabled cheats of this list from now on, including after resets
+    SetCheats(CheatList),
+    Shutdown,
+}
+
 pub struct GBC {
     state: GBCState,
+    // Everything needed to rebuild the state on reset
+    rom_data: Vec<u8>,
+    config: GBCConfig,
+    display_buffer: Arc<Mutex<RetainedImage>>,
+    gui_ctx: Option<eframe::egui::Context>,
+    events: Sender<GBCEvent>,
+    commands: Receiver<GBCCommand>,
+    paused: bool,
+    pacer: FramePacer,
+    rewind: RewindBuffer,
+    rewinding: bool,
+    // Cartridge RAM is only persisted if the cartridge has a battery
+    has_battery: bool,
+    // Set once a movie replaces cartridge RAM. The player's save file is left alone from then on
+    cartridge_ram_from_movie: bool,
+    // From the cartridge header. Screenshots are named after it
+    title: String,
+    // Whether a debug window is open in the frontend
+    debug_capture: bool,
+    // Whether the memory viewer is open. Copying all of memory every frame is slow
+    memory_capture: bool,
+    // Buttons the frontend holds
+    held_buttons: Buttons,
+    // Frame the movie being recorded starts on
+    movie_start_frame: Option<u64>,
+    memory_view_banks: MemoryViewBanks,
 }
 
 impl GBC {
     pub fn new(
         rom_data: Vec<u8>,
+        config: GBCConfig,
         display_buffer: Arc<Mutex<RetainedImage>>,
-        gui_ctx: eframe::egui::Context,
+        gui_ctx: Option<eframe::egui::Context>,
+        events: Sender<GBCEvent>,
+        commands: Receiver<GBCCommand>,
     ) -> Result<Self> {
-        Ok(Self {
-            state: GBCState::new(rom_data, display_buffer, gui_ctx)?,
-        })
+        let header = CartridgeHeader::parse(&rom_data)?;
+        let mut gbc = Self {
+            state: GBCState::new(
+                rom_data.clone(),
+                &config,
+                Arc::clone(&display_buffer),
+                gui_ctx.clone(),
+                events.clone(),
+            )?,
+            rom_data,
+            config,
+            display_buffer,
+            gui_ctx,
+            events,
+            commands,
+            paused: false,
+            pacer: FramePacer::new(EmulationSpeed::Multiplier(1.0)),
+            rewind: RewindBuffer::new(),
+            rewinding: false,
+            has_battery: header.has_battery(),
+            cartridge_ram_from_movie: false,
+            title: header.title,
+            debug_capture: false,
+            memory_capture: false,
+            held_buttons: Buttons::default(),
+            movie_start_frame: None,
+            memory_view_banks: MemoryViewBanks::default(),
+        };
+        gbc.load_cartridge_ram()?;
+        Ok(gbc)
+    }
+
+    /**
+     * Run until a shutdown command is received or the frontend goes away
+     */
+    pub fn run(&mut self) -> Result<()> {
+        loop {
+            if scheduler::frame_cycle(&self.state) == 0 {
+                self.end_frame();
+                if self.debug_capture {
+                    self.publish_debug_snapshot();
+                }
+                // Only check for commands once per frame
+                if !self.process_commands()? {
+                    self.stop_recording();
+                    self.stop_movie();
+                    return self.save_cartridge_ram();
+                }
+                if self.rewinding {
+                    self.rewind_frame();
+                    continue;
+                }
+                self.rewind.start_frame(&self.state);
+                self.start_frame_input();
+            }
+
+            run_until_next_event(&mut self.state);
+        }
+    }
+
+    fn start_frame_input(&mut self) {
+        let event = match movie::start_frame(&mut self.state, self.held_buttons) {
+            Some(PlaybackEvent::Desynced { frame }) => GBCEvent::MovieDesynced { frame },
+            Some(PlaybackEvent::Ended { desynced }) => GBCEvent::MoviePlaybackEnded { desynced },
+            None => return,
+        };
+        self.events.send(event).ok();
+    }
+
+    fn end_frame(&mut self) {
+        if let Some(fps) = self.pacer.end_frame() {
+            let speed_percent = fps / NATIVE_FRAMES_PER_SECOND * 100.0;
+            self.events
+                .send(GBCEvent::FrameRate { fps, speed_percent })
+                .ok();
+        }
+    }
+
+    fn publish_debug_snapshot(&self) {
+        let view_banks = self.memory_capture.then_some(&self.memory_view_banks);
+        let snapshot = DebugSnapshot::capture(&self.state, view_banks);
+        self.events
+            .send(GBCEvent::DebugSnapshot(Box::new(snapshot)))
+            .ok();
     }
 
-    pub fn run(&mut self) {
+    /**
+     * Step one snapshot back and show its frame instead of emulating forward
+     */
+    fn rewind_frame(&mut self) {
+        if self.rewind.pop_into(&mut self.state) {
+            render_engine::publish_frame(&mut self.state);
+        }
+    }
+
+    /**
+     * Handle all queued commands. Blocks while paused. Returns false when the emulator should stop
+     */
+    fn process_commands(&mut self) -> Result<bool> {
         loop {
-            // LCD controller should be first since it controls what mode
-            // everything following runs in.
-            lcd_controller::tick(&mut self.state);
-            // Interrupt controller should run before CPU so that interrupts flags get set in time
-            // for any PPU mode updates
-            interrupt_controller::tick(&mut self.state);
-            delay_action::tick(&mut self.state);
-            dma_controller::tick(&mut self.state);
-            timer_controller::tick(&mut self.state);
-            cpu::tick(&mut self.state);
-            cpu::tick(&mut self.state);
-            cpu::tick(&mut self.state);
-            cpu::tick(&mut self.state);
-            cpu::tick(&mut self.state);
-            cpu::tick(&mut self.state);
-            cpu::tick(&mut self.state);
-            cpu::tick(&mut self.state);
-            render_engine::tick(&mut self.state);
-            render_engine::tick(&mut self.state);
-            render_engine::tick(&mut self.state);
-            render_engine::tick(&mut self.state);
-            self.state.machine_cycle = (self.state.machine_cycle + 1) % MACHINE_CYCLES_PER_FRAME;
+            let command = if self.paused {
+                match self.commands.recv() {
+                    Ok(command) => command,
+                    Err(_) => return Ok(false),
+                }
+            } else {
+                match self.commands.try_recv() {
+                    Ok(command) => command,
+                    Err(TryRecvError::Empty) => return Ok(true),
+                    Err(TryRecvError::Disconnected) => return Ok(false),
+                }
+            };
+
+            info!("Received command {:?}", command);
+            match command {
+                GBCCommand::Pause => self.paused = true,
+                GBCCommand::Resume => {
+                    self.paused = false;
+                    self.pacer.reset();
+                }
+                GBCCommand::SoftReset => self.reset(true)?,
+                GBCCommand::HardReset => self.reset(self.has_battery)?,
+                GBCCommand::SetSpeed(speed) => self.pacer.set_speed(speed),
+                GBCCommand::StartRewind => self.rewinding = true,
+                GBCCommand::StopRewind => self.rewinding = false,
+                GBCCommand::SetDebugCapture(enabled) => {
+                    self.debug_capture = enabled;
+                    // Show something right away even if paused
+                    if enabled {
+                        self.publish_debug_snapshot();
+                    }
+                }
+                GBCCommand::SetMemoryCapture(enabled) => {
+                    self.memory_capture = enabled;
+                    if self.debug_capture {
+                        self.publish_debug_snapshot();
+                    }
+                }
+                GBCCommand::SetMemoryViewBanks(banks) => {
+                    self.memory_view_banks = banks;
+                    if self.debug_capture {
+                        self.publish_debug_snapshot();
+                    }
+                }
+                GBCCommand::WriteMemory { addr, bank, val } => {
+                    match bank {
+                        Some(bank) => {
+                            virtual_memory::write_override_bank(&mut self.state, addr, bank, val)
+                        }
+                        None => virtual_memory::write_without_triggers(&mut self.state, addr, val),
+                    }
+                    if self.debug_capture {
+                        self.publish_debug_snapshot();
+                    }
+                }
+                GBCCommand::SetPaletteColor {
+                    kind,
+                    palette,
+                    color_idx,
+                    rgb555,
+                } => {
+                    let palettes = match kind {
+                        PaletteKind::BG => virtual_memory::borrow_palette_mem_mut(&mut self.state),
+                        PaletteKind::OBJ => {
+                            virtual_memory::borrow_obj_palette_mem_mut(&mut self.state)
+                        }
+                    };
+                    render_engine::set_palette_color_rgb555(palettes, palette, color_idx, rgb555);
+                    // Let the debug windows see the edit even if paused
+                    if self.debug_capture {
+                        self.publish_debug_snapshot();
+                    }
+                }
+                GBCCommand::SetProfiling(enabled) => self.state.profiler.set_enabled(enabled),
+                GBCCommand::ResetProfile => self.state.profiler.reset(),
+                GBCCommand::RequestProfile => {
+                    let report = self.state.profiler.report(&self.state.symbols);
+                    self.events.send(GBCEvent::Profile(Box::new(report))).ok();
+                }
+                GBCCommand::SaveScreenshot { scale } => self.save_screenshot(scale),
+                GBCCommand::StartRecording(format) => self.start_recording(format),
+                GBCCommand::StopRecording => self.stop_recording(),
+                GBCCommand::SetButtons(buttons) => self.held_buttons = buttons,
+                GBCCommand::StartMovieRecording { from_power_on } => {
+                    self.start_movie_recording(from_power_on)?
+                }
+                GBCCommand::PlayMovie(path) => self.play_movie(&path)?,
+                GBCCommand::StopMovie => self.stop_movie(),
+                GBCCommand::SetCheats(list) => {
+                    cheats::set_cheats(&mut self.state, &list);
+                    self.config.cheats = list;
+                }
+                GBCCommand::Shutdown => return Ok(false),
+            }
+        }
+    }
+
+    fn save_screenshot(&self, scale: u8) {
+        let path = self
+            .config
+            .screenshot_dir
+            .join(screenshot_file_name(&self.title, SystemTime::now()));
+        let frame = render_engine::borrow_frame_buffer(&self.state);
+        let event = match write_screenshot_png(&path, frame, scale) {
+            Ok(()) => GBCEvent::ScreenshotSaved(path),
+            Err(e) => GBCEvent::ScreenshotFailed(e.to_string()),
+        };
+        self.events.send(event).ok();
+    }
+
+    fn start_recording(&mut self, format: RecordingFormat) {
+        let name = capture_name(&self.title, SystemTime::now());
+        let path = self
+            .config
+            .recording_dir
+            .join(format!("{}.{}", name, format.extension()));
+        let event = match video_recorder::start(&mut self.state, &path, format) {
+            Ok(()) => GBCEvent::RecordingStarted(path),
+            Err(e) => GBCEvent::RecordingFailed(format!("{}: {}", path.display(), e)),
+        };
+        self.events.send(event).ok();
+    }
+
+    fn stop_recording(&mut self) {
+        if let Some(path) = video_recorder::stop(&mut self.state) {
+            self.events.send(GBCEvent::RecordingStopped(path)).ok();
+        }
+    }
+
+    fn start_movie_recording(&mut self, from_power_on: bool) -> Result<()> {
+        self.stop_movie();
+        if from_power_on {
+            self.reset(true)?;
+        }
+        self.movie_start_frame = Some(movie::frame_index(&self.state));
+        self.events.send(GBCEvent::MovieRecordingStarted).ok();
+        Ok(())
+    }
+
+    /**
+     * Finish the movie being recorded or played, if any
+     */
+    fn stop_movie(&mut self) {
+        if let Some(desynced) = movie::stop_playback(&mut self.state) {
+            self.events
+                .send(GBCEvent::MoviePlaybackEnded { desynced })
+                .ok();
+        }
+        let Some(start_frame) = self.movie_start_frame.take() else {
+            return;
+        };
+        let movie = movie::record(&self.state, start_frame);
+        let name = capture_name(&self.title, SystemTime::now());
+        let path = self
+            .config
+            .recording_dir
+            .join(format!("{}.{}", name, MOVIE_EXTENSION));
+        let event = match movie.save(&path) {
+            Ok(()) => {
+                info!("Saved movie to {}", path.display());
+                GBCEvent::MovieSaved(path)
+            }
+            Err(e) => GBCEvent::MovieFailed(format!("{}: {}", path.display(), e)),
+        };
+        self.events.send(event).ok();
+    }
+
+    /**
+     * Power cycle and play a movie. Frames recorded before the movie's start frame are run as
+     * fast as possible to get to the state it was started from. The cartridge RAM so far is saved
+     * first, since it is the movie's from then on and is never saved. Nothing changes if the movie
+     * can't be played
+     */
+    fn play_movie(&mut self, path: &Path) -> Result<()> {
+        self.stop_movie();
+        let movie = match Movie::load(path) {
+            Ok(movie) => movie,
+            Err(e) => {
+                let message = format!("{}: {}", path.display(), e);
+                self.events.send(GBCEvent::MovieFailed(message)).ok();
+                return Ok(());
+            }
+        };
+        if movie.emulator_version != env!("CARGO_PKG_VERSION") {
+            warn!(
+                "Movie was recorded with version {} of the emulator and may not play back the same",
+                movie.emulator_version
+            );
+        }
+        let start_frame = movie.start_frame;
+        let frames = movie.frames.len() as u64;
+
+        let mut state = self.power_on_state()?;
+        let checked = movie::start_playback(&mut state, movie)
+            .and_then(|()| self.save_cartridge_ram());
+        if let Err(e) = checked {
+            self.events.send(GBCEvent::MovieFailed(e.to_string())).ok();
+            return Ok(());
+        }
+        self.cartridge_ram_from_movie = true;
+        self.replace_state(state);
+        self.events
+            .send(GBCEvent::MoviePlaybackStarted { frames })
+            .ok();
+        while movie::frame_index(&self.state) < start_frame {
+            self.start_frame_input();
+            run_frame(&mut self.state);
+        }
+        self.pacer.reset();
+        Ok(())
+    }
+
+    fn reset(&mut self, keep_cartridge_ram: bool) -> Result<()> {
+        let mut state = self.power_on_state()?;
+        if keep_cartridge_ram {
+            let cartridge_ram = virtual_memory::borrow_external_ram(&self.state);
+            virtual_memory::fill_external_ram(&mut state, cartridge_ram);
         }
+        self.replace_state(state);
+        Ok(())
+    }
+
+    fn power_on_state(&self) -> Result<GBCState> {
+        GBCState::new(
+            self.rom_data.clone(),
+            &self.config,
+            Arc::clone(&self.display_buffer),
+            self.gui_ctx.clone(),
+            self.events.clone(),
+        )
+    }
+
+    /**
+     * Carry on from a freshly powered on state
+     */
+    fn replace_state(&mut self, mut state: GBCState) {
+        // Movies can't continue past a reset since they only replay input
+        self.stop_movie();
+
+        // Keep profiling across resets
+        state.profiler = mem::take(&mut self.state.profiler);
+        // A recording carries on through the reset
+        video_recorder::carry_over_reset(&mut self.state, &mut state);
+        self.state = state;
+        self.rewind.clear();
+    }
+
+    /**
+     * Restore cartridge RAM saved by a previous session, if there is one
+     */
+    fn load_cartridge_ram(&mut self) -> Result<()> {
+        let Some(ref path) = self.config.save_file else {
+            return Ok(());
+        };
+        if !self.has_battery || !path.exists() {
+            return Ok(());
+        }
+
+        let data = fs::read(path)?;
+        if data.len() != virtual_memory::borrow_external_ram(&self.state).len() {
+            warn!(
+                "Ignoring save file {} because its size doesn't match cartridge RAM",
+                path.display()
+            );
+            return Ok(());
+        }
+        virtual_memory::fill_external_ram(&mut self.state, &data);
+        info!("Loaded cartridge RAM from {}", path.display());
+        Ok(())
+    }
+
+    fn save_cartridge_ram(&self) -> Result<()> {
+        let Some(ref path) = self.config.save_file else {
+            return Ok(());
+        };
+        let cartridge_ram = virtual_memory::borrow_external_ram(&self.state);
+        if !self.has_battery || cartridge_ram.is_empty() {
+            return Ok(());
+        }
+        if self.cartridge_ram_from_movie {
+            info!("Not saving cartridge RAM since it came from a movie");
+            return Ok(());
+        }
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, cartridge_ram)?;
+        info!("Saved cartridge RAM to {}", path.display());
+        Ok(())
+    }
+}
+
+/**
+ * Dispatch every event due on the current cycle, then let the CPU run until the next one
+ */
+fn run_until_next_event(state: &mut GBCState) {
+    while let Some(event) = scheduler::pop_due_event(state) {
+        dispatch_event(state, event);
+    }
+    cpu::run_until_next_event(state);
+    let next_event_cycle = scheduler::next_event_cycle(state);
+    scheduler::advance_to(state, next_event_cycle);
+}
+
+/**
+ * Execute one CPU instruction, or dispatch due events and skip to the next one if the CPU has to
+ * wait for it. Returns whether an instruction ran
+ */
+fn step_instruction(state: &mut GBCState) -> bool {
+    while let Some(event) = scheduler::pop_due_event(state) {
+        dispatch_event(state, event);
+    }
+    if cpu::try_step(state) {
+        return true;
+    }
+    let next_event_cycle = scheduler::next_event_cycle(state);
+    scheduler::advance_to(state, next_event_cycle);
+    false
+}
+
+/**
+ * Run until the start of the next frame
+ */
+fn run_frame(state: &mut GBCState) {
+    let frame_start = scheduler::now(state) - scheduler::frame_cycle(state) as u64;
+    let next_frame_start = frame_start + MACHINE_CYCLES_PER_FRAME as u64;
+    while scheduler::now(state) < next_frame_start {
+        run_until_next_event(state);
+    }
+}
+
+fn dispatch_event(state: &mut GBCState, event: Event) {
+    match event {
+        Event::RenderDots => render_engine::render_dots(state),
+        Event::LCDUpdate => lcd_controller::handle_lcd_update(state),
+        Event::OAMDMATransfer => dma_controller::process_oam_transfer(state),
+        Event::DividerIncrement => timer_controller::handle_divider(state),
+        Event::TimerIncrement => timer_controller::handle_timer(state),
+        Event::SerialTransferComplete => serial_controller::handle_transfer_complete(state),
     }
 }
 
@@ -74,27 +711,279 @@ pub struct GBCState {
     intr_ctrl: InterruptController,
     timer_ctrl: TimerController,
     dma_ctrl: DMAController,
-    delayed_actions: DelayedActions,
+    serial_ctrl: SerialController,
+    joypad_ctrl: JoypadController,
     render_engine: Renderer,
-    machine_cycle: u16,
+    scheduler: Scheduler,
+    events: Sender<GBCEvent>,
+    symbols: Arc<SymbolTable>,
+    profiler: Profiler,
+    // Set while published frames are being recorded
+    video_recorder: Option<VideoRecorder>,
+    input_log: InputLog,
+    // Written to RAM every VBlank. Game Genie codes are applied by virtual memory instead
+    game_shark_codes: Vec<CheatCode>,
 }
 
 impl GBCState {
     pub fn new(
         rom_data: Vec<u8>,
+        config: &GBCConfig,
         display_buffer: Arc<Mutex<RetainedImage>>,
-        gui_ctx: eframe::egui::Context,
+        gui_ctx: Option<eframe::egui::Context>,
+        events: Sender<GBCEvent>,
     ) -> Result<Self> {
-        Ok(Self {
+        if config.model == Model::DMG {
+            // The renderer only reads CGB palette RAM, so BGP, OBP0 and OBP1 would be ignored
+            return Err(eyre!("DMG emulation isn't supported yet. Use the CGB model"));
+        }
+        info!("Powering on as {:?}", config.model);
+        if let Some(ref boot_rom) = config.boot_rom {
+            boot_rom::validate(boot_rom, config.model)?;
+        }
+
+        let input_log = InputLog::new(&rom_data, config);
+        let mut state = Self {
             cpu: CPU::new(),
-            mem: VirtualMemory::new(rom_data)?,
+            mem: VirtualMemory::new(rom_data, config.boot_rom.clone())?,
             lcd_ctrl: LCDController::new(),
             intr_ctrl: InterruptController::new(),
             timer_ctrl: TimerController::new(),
             dma_ctrl: DMAController::new(),
-            delayed_actions: DelayedActions::new(),
+            serial_ctrl: SerialController::new(),
+            joypad_ctrl: JoypadController::new(),
             render_engine: Renderer::new(display_buffer, gui_ctx),
-            machine_cycle: 0,
+            scheduler: Scheduler::new(),
+            events,
+            symbols: Arc::clone(&config.symbols),
+            profiler: Profiler::new(),
+            video_recorder: None,
+            input_log,
+            game_shark_codes: Vec::new(),
+        };
+        cheats::set_cheats(&mut state, &config.cheats);
+        if config.boot_rom.is_none() {
+            boot_rom::skip(&mut state, config.model);
+        }
+        // The LCD and divider run from power on
+        scheduler::schedule_at(&mut state, Event::LCDUpdate, 0);
+        timer_controller::start_divider(&mut state);
+        Ok(state)
+    }
+
+    /**
+     * Build a state that isn't connected to a frontend. Frames are rendered but never shown
+     * and events are discarded.
+     */
+    fn new_headless(rom_data: Vec<u8>, config: &GBCConfig) -> Result<Self> {
+        let display_buffer = Arc::new(Mutex::new(RetainedImage::from_color_image(
+            "headless_frame",
+            eframe::epaint::ColorImage::example(),
+        )));
+        // Receiver is dropped so events are discarded
+        let (events, _) = std::sync::mpsc::channel();
+        Self::new(rom_data, config, display_buffer, None, events)
+    }
+
+    /**
+     * Build a state for a blank 32KiB ROM with `program` placed at the program start address
+     */
+    #[cfg(any(test, feature = "bench"))]
+    fn with_program(program: &[u8]) -> Self {
+        let mut rom_data = vec![0x00; 0x8000];
+        rom_data[0x0100..0x0100 + program.len()].copy_from_slice(program);
+        Self::new_headless(rom_data, &GBCConfig::default()).unwrap()
+    }
+}
+
+/**
+ * Test program that selects the buttons row, then keeps adding P1 to 0xC000 so every input changes
+ * the state
+ */
+#[cfg(test)]
+const INPUT_PROGRAM: [u8; 16] = [
+    0x3E, 0x10, // LD A, 0x10
+    0xE0, 0x00, // LDH (P1), A
+    0xF0, 0x00, // LDH A, (P1)
+    0x47, // LD B, A
+    0xFA, 0x00, 0xC0, // LD A, (0xC000)
+    0x80, // ADD A, B
+    0xEA, 0x00, 0xC0, // LD (0xC000), A
+    0x18, 0xF4, // JR -12
+];
+
+/**
+ * Runs a ROM as fast as possible without a frontend. Used for benchmarking and tooling
+ */
+pub struct HeadlessGBC {
+    state: GBCState,
+    // Buttons held on every frame run
+    held_buttons: Buttons,
+}
+
+/**
+ * Result reported by a test ROM
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestVerdict {
+    Passed,
+    Failed,
+    // Nothing was reported before the frame limit
+    TimedOut,
+}
+
+impl HeadlessGBC {
+    pub fn new(rom_data: Vec<u8>) -> Result<Self> {
+        Self::with_config(rom_data, &GBCConfig::default())
+    }
+
+    pub fn with_config(rom_data: Vec<u8>, config: &GBCConfig) -> Result<Self> {
+        Ok(Self {
+            state: GBCState::new_headless(rom_data, config)?,
+            held_buttons: Buttons::default(),
         })
     }
+
+    pub fn run_frames(&mut self, frames: u64) {
+        for _ in 0..frames {
+            movie::start_frame(&mut self.state, self.held_buttons);
+            run_frame(&mut self.state);
+        }
+    }
+
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        self.held_buttons = buttons;
+    }
+
+    /**
+     * Use the enabled cheats of a list from now on
+     */
+    pub fn set_cheats(&mut self, cheats: &CheatList) {
+        cheats::set_cheats(&mut self.state, cheats);
+    }
+
+    /**
+     * Input of every frame run so far as a movie
+     */
+    pub fn movie(&self) -> Movie {
+        movie::record(&self.state, 0)
+    }
+
+    /**
+     * Play a movie from power on to its end. Returns the first frame the state didn't match the
+     * movie on, if any
+     */
+    pub fn play_movie(&mut self, movie: Movie) -> Result<Option<u64>> {
+        movie::start_playback(&mut self.state, movie)?;
+        let mut desynced_frame = None;
+        loop {
+            match movie::start_frame(&mut self.state, self.held_buttons) {
+                Some(PlaybackEvent::Desynced { frame }) => desynced_frame = Some(frame),
+                Some(PlaybackEvent::Ended { .. }) => return Ok(desynced_frame),
+                None => {}
+            }
+            run_frame(&mut self.state);
+        }
+    }
+
+    /**
+     * Machine cycles emulated since power on
+     */
+    pub fn machine_cycles(&self) -> u64 {
+        scheduler::now(&self.state)
+    }
+
+    /**
+     * Hash of the emulation state. Runs given the same ROM, config and input have the same hash
+     * after every frame
+     */
+    pub fn state_hash(&self) -> u64 {
+        state_hash::state_hash(&self.state)
+    }
+
+    /**
+     * RGB pixels of the last completed frame, GBC_RESOLUTION_X by GBC_RESOLUTION_Y
+     */
+    pub fn frame_buffer(&self) -> &[u8] {
+        render_engine::borrow_frame_buffer(&self.state)
+    }
+
+    /**
+     * Bytes sent over the link port since power on
+     */
+    pub fn serial_output(&self) -> &[u8] {
+        serial_controller::borrow_output(&self.state)
+    }
+
+    /**
+     * Start or stop counting cycles per instruction and call stack
+     */
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.state.profiler.set_enabled(enabled);
+    }
+
+    pub fn profile_report(&self) -> ProfileReport {
+        self.state.profiler.report(&self.state.symbols)
+    }
+
+    /**
+     * Record every frame published from now on to `path`, a file for AVI or a directory for a
+     * PNG sequence
+     */
+    pub fn start_recording(&mut self, path: &Path, format: RecordingFormat) -> Result<()> {
+        video_recorder::start(&mut self.state, path, format)
+    }
+
+    /**
+     * Finish the recording so it can be played. Returns where it was written
+     */
+    pub fn stop_recording(&mut self) -> Result<PathBuf> {
+        let recorder = self
+            .state
+            .video_recorder
+            .take()
+            .ok_or_else(|| eyre!("Not recording"))?;
+        recorder.finish()
+    }
+
+    /**
+     * Run until the ROM reports a result or max_frames have passed. Understands Blargg's
+     * tests, which print the result over the link port, and Mooneye's, which load a
+     * signature into the registers.
+     */
+    pub fn run_test_rom(&mut self, max_frames: u64) -> TestVerdict {
+        for _ in 0..max_frames {
+            run_frame(&mut self.state);
+            if let Some(verdict) = self.test_verdict() {
+                return verdict;
+            }
+        }
+        TestVerdict::TimedOut
+    }
+
+    fn test_verdict(&self) -> Option<TestVerdict> {
+        let output = String::from_utf8_lossy(self.serial_output());
+        if output.contains("Passed") {
+            return Some(TestVerdict::Passed);
+        }
+        if output.contains("Failed") {
+            return Some(TestVerdict::Failed);
+        }
+
+        let registers = [
+            Register::B,
+            Register::C,
+            Register::D,
+            Register::E,
+            Register::H,
+            Register::L,
+        ]
+        .map(|register| cpu::read_register(&self.state, register));
+        match registers {
+            MOONEYE_PASS_SIGNATURE => Some(TestVerdict::Passed),
+            MOONEYE_FAIL_SIGNATURE => Some(TestVerdict::Failed),
+            _ => None,
+        }
+    }
 }
+
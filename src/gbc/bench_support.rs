@@ -0,0 +1,29 @@
+use super::{render_engine, virtual_memory, GBCState};
+
+pub use super::cpu::map_instruction;
+
+/**
+ * Build a state for a blank 32KiB ROM with `program` placed at the program start address
+ */
+pub fn state_with_program(program: &[u8]) -> GBCState {
+    GBCState::with_program(program)
+}
+
+pub fn read(state: &GBCState, addr: u16) -> u8 {
+    virtual_memory::read(state, addr)
+}
+
+pub fn write(state: &mut GBCState, addr: u16, val: u8) {
+    virtual_memory::write(state, addr, val)
+}
+
+/**
+ * Tick the pixel fetcher once. Fetched pixels are discarded so it never stalls
+ */
+pub fn tick_pixel_fetcher(state: &mut GBCState) {
+    render_engine::fetch_pixels(state)
+}
+
+pub fn run_frame(state: &mut GBCState) {
+    super::run_frame(state)
+}
@@ -0,0 +1,177 @@
+use color_eyre::eyre::{eyre, Result};
+
+use crate::util::index_bits;
+
+use super::{
+    cpu, render_engine, virtual_memory, GBCState, Model, PALETTES_PER_KIND,
+};
+
+const DMG_BOOT_ROM_SIZE: usize = 0x0100;
+// Covers 0x0000-0x08FF. The part behind the cartridge header is never visible
+const CGB_BOOT_ROM_SIZE: usize = 0x0900;
+
+const CGB_FLAG_ADDR: u16 = 0x0143;
+const HEADER_CHECKSUM_ADDR: u16 = 0x014D;
+
+const WHITE_RGB555: u16 = 0x7FFF;
+// Colors the CGB boot ROM gives cartridges made for the DMG when it has no palette for the title
+const DMG_COMPATIBILITY_BG: [u16; 4] = [0x7FFF, 0x1BEF, 0x6180, 0x0000];
+const DMG_COMPATIBILITY_OBJ: [u16; 4] = [0x7FFF, 0x421F, 0x1CF2, 0x0000];
+
+// IO registers the DMG boot ROM leaves behind. Registers not listed are left at 0
+const DMG_POST_BOOT_IO: [(u16, u8); 32] = [
+    (0xFF00, 0xCF), // P1
+    (0xFF02, 0x7E), // SC
+    (0xFF04, 0xAB), // DIV
+    (0xFF07, 0xF8), // TAC
+    (0xFF0F, 0xE1), // IF
+    (0xFF10, 0x80), // NR10
+    (0xFF11, 0xBF), // NR11
+    (0xFF12, 0xF3), // NR12
+    (0xFF13, 0xFF), // NR13
+    (0xFF14, 0xBF), // NR14
+    (0xFF16, 0x3F), // NR21
+    (0xFF18, 0xFF), // NR23
+    (0xFF19, 0xBF), // NR24
+    (0xFF1A, 0x7F), // NR30
+    (0xFF1B, 0xFF), // NR31
+    (0xFF1C, 0x9F), // NR32
+    (0xFF1D, 0xFF), // NR33
+    (0xFF1E, 0xBF), // NR34
+    (0xFF20, 0xFF), // NR41
+    (0xFF23, 0xBF), // NR44
+    (0xFF24, 0x77), // NR50
+    (0xFF25, 0xF3), // NR51
+    (0xFF26, 0xF1), // NR52
+    (0xFF40, 0x91), // LCDC
+    (0xFF41, 0x85), // STAT
+    (0xFF46, 0xFF), // DMA
+    (0xFF47, 0xFC), // BGP
+    // CGB registers aren't there on a DMG and read as 0xFF
+    (0xFF4D, 0xFF), // KEY1
+    (0xFF4F, 0xFF), // VBK
+    (0xFF55, 0xFF), // HDMA5
+    (0xFF56, 0xFF), // RP
+    (0xFF70, 0xFF), // SVBK
+];
+
+// IO registers the CGB boot ROM leaves behind. Registers not listed are left at 0
+const CGB_POST_BOOT_IO: [(u16, u8); 35] = [
+    (0xFF00, 0xCF), // P1
+    (0xFF02, 0x7F), // SC
+    (0xFF07, 0xF8), // TAC
+    (0xFF0F, 0xE1), // IF
+    (0xFF10, 0x80), // NR10
+    (0xFF11, 0xBF), // NR11
+    (0xFF12, 0xF3), // NR12
+    (0xFF13, 0xFF), // NR13
+    (0xFF14, 0xBF), // NR14
+    (0xFF16, 0x3F), // NR21
+    (0xFF18, 0xFF), // NR23
+    (0xFF19, 0xBF), // NR24
+    (0xFF1A, 0x7F), // NR30
+    (0xFF1B, 0xFF), // NR31
+    (0xFF1C, 0x9F), // NR32
+    (0xFF1D, 0xFF), // NR33
+    (0xFF1E, 0xBF), // NR34
+    (0xFF20, 0xFF), // NR41
+    (0xFF23, 0xBF), // NR44
+    (0xFF24, 0x77), // NR50
+    (0xFF25, 0xF3), // NR51
+    (0xFF26, 0xF1), // NR52
+    (0xFF40, 0x91), // LCDC
+    (0xFF41, 0x81), // STAT
+    (0xFF47, 0xFC), // BGP
+    (0xFF4D, 0x7E), // KEY1
+    (0xFF4F, 0xFE), // VBK
+    (0xFF51, 0xFF), // HDMA1
+    (0xFF52, 0xFF), // HDMA2
+    (0xFF53, 0xFF), // HDMA3
+    (0xFF54, 0xFF), // HDMA4
+    (0xFF55, 0xFF), // HDMA5
+    (0xFF56, 0x3E), // RP
+    (0xFF68, 0xC0), // BCPS
+    (0xFF70, 0xF8), // SVBK
+];
+
+/**
+ * Check a user provided boot ROM is the right size for the model
+ */
+pub fn validate(boot_rom: &[u8], model: Model) -> Result<()> {
+    let expected_size = match model {
+        Model::DMG => DMG_BOOT_ROM_SIZE,
+        Model::CGB => CGB_BOOT_ROM_SIZE,
+    };
+    if boot_rom.len() != expected_size {
+        return Err(eyre!(
+            "{:?} boot ROM must be {} bytes but is {} bytes",
+            model,
+            expected_size,
+            boot_rom.len()
+        ));
+    }
+    Ok(())
+}
+
+/**
+ * Put the CPU and IO registers in the state the boot ROM leaves them in, so the cartridge can
+ * be started directly at its entry point
+ */
+pub fn skip(state: &mut GBCState, model: Model) {
+    let cgb_cartridge = index_bits(virtual_memory::read(state, CGB_FLAG_ADDR), 7);
+    let header_checksum = virtual_memory::read(state, HEADER_CHECKSUM_ADDR);
+    cpu::set_post_boot_registers(state, model, cgb_cartridge, header_checksum);
+
+    let io_registers: &[(u16, u8)] = match model {
+        Model::DMG => &DMG_POST_BOOT_IO,
+        Model::CGB => &CGB_POST_BOOT_IO,
+    };
+    // Setting these has no side effects on real hardware, so skip the write triggers
+    for &(addr, val) in io_registers {
+        virtual_memory::write_without_triggers(state, addr, val);
+    }
+    set_post_boot_palettes(state, cgb_cartridge);
+}
+
+/**
+ * White for CGB cartridges. Cartridges made for the DMG get the compatibility palette in BG
+ * palette 0 and both of the OBJ palettes OBP0 and OBP1 map to
+ */
+fn set_post_boot_palettes(state: &mut GBCState, cgb_cartridge: bool) {
+    let (bg, obj) = match cgb_cartridge {
+        true => ([WHITE_RGB555; 4], [WHITE_RGB555; 4]),
+        false => (DMG_COMPATIBILITY_BG, DMG_COMPATIBILITY_OBJ),
+    };
+    for palette in 0..PALETTES_PER_KIND {
+        for color_idx in 0..4 {
+            let bg_color = match palette {
+                0 => bg[color_idx as usize],
+                _ => WHITE_RGB555,
+            };
+            let obj_color = match palette {
+                0 | 1 => obj[color_idx as usize],
+                _ => WHITE_RGB555,
+            };
+            let bg_palettes = virtual_memory::borrow_palette_mem_mut(state);
+            render_engine::set_palette_color_rgb555(bg_palettes, palette, color_idx, bg_color);
+            let obj_palettes = virtual_memory::borrow_obj_palette_mem_mut(state);
+            render_engine::set_palette_color_rgb555(obj_palettes, palette, color_idx, obj_color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dmg_cartridges_get_the_compatibility_palette() {
+        // with_program leaves the CGB flag clear
+        let state = GBCState::with_program(&[]);
+        let bg_palettes = virtual_memory::borrow_palette_mem(&state);
+        assert_eq!(render_engine::palette_color_rgb555(bg_palettes, 0, 1), 0x1BEF);
+        assert_eq!(render_engine::palette_color_rgb555(bg_palettes, 1, 3), WHITE_RGB555);
+        let obj_palettes = virtual_memory::borrow_obj_palette_mem(&state);
+        assert_eq!(render_engine::palette_color_rgb555(obj_palettes, 1, 2), 0x1CF2);
+    }
+}
@@ -0,0 +1,234 @@
+use std::fmt;
+
+use color_eyre::eyre::{eyre, Result};
+
+const TITLE_ADDR: usize = 0x0134;
+// Newer cartridges use the last bytes of the title for the manufacturer code and CGB flag
+const TITLE_ADDR_END: usize = 0x0143;
+const CGB_FLAG_ADDR: usize = 0x0143;
+const NEW_LICENSEE_CODE_ADDR: usize = 0x0144;
+const SGB_FLAG_ADDR: usize = 0x0146;
+const CARTRIDGE_TYPE_ADDR: usize = 0x0147;
+const ROM_SIZE_ADDR: usize = 0x0148;
+const RAM_SIZE_ADDR: usize = 0x0149;
+const DESTINATION_CODE_ADDR: usize = 0x014A;
+const OLD_LICENSEE_CODE_ADDR: usize = 0x014B;
+const VERSION_ADDR: usize = 0x014C;
+const HEADER_CHECKSUM_ADDR: usize = 0x014D;
+const GLOBAL_CHECKSUM_ADDR: usize = 0x014E;
+const HEADER_END_ADDR: usize = 0x014F;
+
+// Old licensee code meaning the new licensee code should be used instead
+const USE_NEW_LICENSEE_CODE: u8 = 0x33;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CGBSupport {
+    // Made before the CGB. Runs in DMG compatibility mode
+    None,
+    // Uses CGB features but also runs on a DMG
+    Enhanced,
+    // Refuses to run on a DMG
+    Only,
+}
+
+/**
+ * Metadata stored at 0x0100-0x014F of every cartridge
+ */
+#[derive(Debug, Clone)]
+pub struct CartridgeHeader {
+    pub title: String,
+    pub cgb_support: CGBSupport,
+    pub sgb_support: bool,
+    pub licensee_code: String,
+    pub cartridge_type: u8,
+    pub rom_size_bytes: usize,
+    pub ram_size_bytes: usize,
+    // True for cartridges sold in Japan
+    pub japanese: bool,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+    // Checksum of 0x0134-0x014C computed the same way the boot ROM does
+    pub computed_header_checksum: u8,
+}
+
+impl CartridgeHeader {
+    pub fn parse(rom_data: &[u8]) -> Result<Self> {
+        if rom_data.len() <= HEADER_END_ADDR {
+            return Err(eyre!(
+                "ROM is {} bytes, too small to contain a cartridge header",
+                rom_data.len()
+            ));
+        }
+
+        let cgb_flag = rom_data[CGB_FLAG_ADDR];
+        let cgb_support = match cgb_flag {
+            0xC0 => CGBSupport::Only,
+            0x80 => CGBSupport::Enhanced,
+            _ => CGBSupport::None,
+        };
+        // CGB cartridges only have 15 characters of title
+        let title_end = match cgb_support {
+            CGBSupport::None => TITLE_ADDR_END + 1,
+            _ => CGB_FLAG_ADDR,
+        };
+        let title = rom_data[TITLE_ADDR..title_end]
+            .iter()
+            .take_while(|&&c| c != 0)
+            .map(|&c| c as char)
+            .collect::<String>()
+            .trim_end()
+            .to_string();
+
+        let licensee_code = match rom_data[OLD_LICENSEE_CODE_ADDR] {
+            USE_NEW_LICENSEE_CODE => rom_data[NEW_LICENSEE_CODE_ADDR..NEW_LICENSEE_CODE_ADDR + 2]
+                .iter()
+                .map(|&c| c as char)
+                .collect(),
+            code => format!("{:02X}", code),
+        };
+
+        let computed_header_checksum = rom_data[TITLE_ADDR..HEADER_CHECKSUM_ADDR]
+            .iter()
+            .fold(0u8, |checksum, &byte| {
+                checksum.wrapping_sub(byte).wrapping_sub(1)
+            });
+
+        Ok(Self {
+            title,
+            cgb_support,
+            sgb_support: rom_data[SGB_FLAG_ADDR] == 0x03,
+            licensee_code,
+            cartridge_type: rom_data[CARTRIDGE_TYPE_ADDR],
+            rom_size_bytes: 0x8000usize
+                .checked_shl(rom_data[ROM_SIZE_ADDR].into())
+                .unwrap_or(0),
+            ram_size_bytes: match rom_data[RAM_SIZE_ADDR] {
+                0x02 => 0x2000,
+                0x03 => 0x8000,
+                0x04 => 0x20000,
+                0x05 => 0x10000,
+                _ => 0,
+            },
+            japanese: rom_data[DESTINATION_CODE_ADDR] == 0x00,
+            version: rom_data[VERSION_ADDR],
+            header_checksum: rom_data[HEADER_CHECKSUM_ADDR],
+            global_checksum: u16::from_be_bytes([
+                rom_data[GLOBAL_CHECKSUM_ADDR],
+                rom_data[GLOBAL_CHECKSUM_ADDR + 1],
+            ]),
+            computed_header_checksum,
+        })
+    }
+
+    pub fn cartridge_type_name(&self) -> &'static str {
+        match self.cartridge_type {
+            0x00 => "ROM ONLY",
+            0x01 => "MBC1",
+            0x02 => "MBC1+RAM",
+            0x03 => "MBC1+RAM+BATTERY",
+            0x05 => "MBC2",
+            0x06 => "MBC2+BATTERY",
+            0x08 => "ROM+RAM",
+            0x09 => "ROM+RAM+BATTERY",
+            0x0B => "MMM01",
+            0x0C => "MMM01+RAM",
+            0x0D => "MMM01+RAM+BATTERY",
+            0x0F => "MBC3+TIMER+BATTERY",
+            0x10 => "MBC3+TIMER+RAM+BATTERY",
+            0x11 => "MBC3",
+            0x12 => "MBC3+RAM",
+            0x13 => "MBC3+RAM+BATTERY",
+            0x19 => "MBC5",
+            0x1A => "MBC5+RAM",
+            0x1B => "MBC5+RAM+BATTERY",
+            0x1C => "MBC5+RUMBLE",
+            0x1D => "MBC5+RUMBLE+RAM",
+            0x1E => "MBC5+RUMBLE+RAM+BATTERY",
+            0x20 => "MBC6",
+            0x22 => "MBC7+SENSOR+RUMBLE+RAM+BATTERY",
+            0xFC => "POCKET CAMERA",
+            0xFD => "BANDAI TAMA5",
+            0xFE => "HuC3",
+            0xFF => "HuC1+RAM+BATTERY",
+            _ => "Unknown",
+        }
+    }
+
+    /**
+     * Whether cartridge RAM keeps its contents when the power is off
+     */
+    pub fn has_battery(&self) -> bool {
+        matches!(
+            self.cartridge_type,
+            0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFF
+        )
+    }
+
+    /**
+     * The boot ROM refuses to start the cartridge if this is false
+     */
+    pub fn header_checksum_valid(&self) -> bool {
+        self.header_checksum == self.computed_header_checksum
+    }
+}
+
+impl fmt::Display for CartridgeHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Title:           {}", self.title)?;
+        writeln!(f, "CGB support:     {:?}", self.cgb_support)?;
+        writeln!(f, "SGB support:     {}", self.sgb_support)?;
+        writeln!(f, "Licensee code:   {}", self.licensee_code)?;
+        writeln!(
+            f,
+            "Cartridge type:  {:#04x} ({})",
+            self.cartridge_type,
+            self.cartridge_type_name()
+        )?;
+        writeln!(f, "ROM size:        {} KiB", self.rom_size_bytes / 1024)?;
+        writeln!(f, "RAM size:        {} KiB", self.ram_size_bytes / 1024)?;
+        writeln!(
+            f,
+            "Destination:     {}",
+            if self.japanese { "Japan" } else { "Overseas" }
+        )?;
+        writeln!(f, "Version:         {}", self.version)?;
+        writeln!(
+            f,
+            "Header checksum: {:#04x} ({})",
+            self.header_checksum,
+            match self.header_checksum_valid() {
+                true => "valid".to_string(),
+                false => format!("invalid, expected {:#04x}", self.computed_header_checksum),
+            }
+        )?;
+        write!(f, "Global checksum: {:#06x}", self.global_checksum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cgb_header() {
+        let mut rom_data = vec![0x00; 0x8000];
+        rom_data[TITLE_ADDR..TITLE_ADDR + 4].copy_from_slice(b"TEST");
+        rom_data[CGB_FLAG_ADDR] = 0x80;
+        rom_data[CARTRIDGE_TYPE_ADDR] = 0x1B;
+        rom_data[ROM_SIZE_ADDR] = 0x02;
+        rom_data[RAM_SIZE_ADDR] = 0x03;
+        rom_data[OLD_LICENSEE_CODE_ADDR] = 0x01;
+        rom_data[HEADER_CHECKSUM_ADDR] = 0x06;
+
+        let header = CartridgeHeader::parse(&rom_data).unwrap();
+        assert_eq!(header.title, "TEST");
+        assert_eq!(header.cgb_support, CGBSupport::Enhanced);
+        assert_eq!(header.cartridge_type_name(), "MBC5+RAM+BATTERY");
+        assert!(header.has_battery());
+        assert_eq!(header.rom_size_bytes, 128 * 1024);
+        assert_eq!(header.ram_size_bytes, 32 * 1024);
+        assert_eq!(header.licensee_code, "01");
+        assert!(header.header_checksum_valid());
+    }
+}
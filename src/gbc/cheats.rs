@@ -0,0 +1,385 @@
+use std::{
+    fs,
+    io::{self, Write},
+    mem,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::{eyre, Result};
+use tracing::info;
+
+use super::{
+    movie,
+    virtual_memory::{self, RomPatch},
+    GBCState,
+};
+
+// Cheats for a ROM are saved next to it with this extension
+pub const CHEAT_FILE_EXTENSION: &str = "cht";
+const HEADER: &str = "# + or - for enabled, the codes joined by +, then the name";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CheatCode {
+    // Written to RAM every frame. To a switchable work RAM bank if there is one, otherwise to
+    // whatever is mapped at the address
+    GameShark {
+        addr: u16,
+        val: u8,
+        wram_bank: Option<u8>,
+    },
+    // Replaces reads of a ROM address. Only while the ROM holds the compare byte there if given
+    GameGenie {
+        addr: u16,
+        val: u8,
+        compare: Option<u8>,
+    },
+}
+
+impl CheatCode {
+    /**
+     * Parse a GameShark code, 01VVLLHH or 9BVVLLHH for work RAM bank B, or a Game Genie code,
+     * ABC-DEF or ABC-DEF-GHI
+     */
+    pub fn parse(code: &str) -> Result<Self> {
+        match code.contains('-') {
+            true => parse_game_genie(code),
+            false => parse_game_shark(code),
+        }
+        .map_err(|e| eyre!("{}: {}", code, e))
+    }
+}
+
+fn parse_game_shark(code: &str) -> Result<CheatCode> {
+    if code.len() != 8 {
+        return Err(eyre!("GameShark codes are 8 hex digits"));
+    }
+    let digits = hex_digits(code)?;
+    let byte = |i: usize| (digits[i * 2] << 4) | digits[i * 2 + 1];
+    // The address is stored low byte first
+    let addr = u16::from_le_bytes([byte(2), byte(3)]);
+    let wram_bank = match byte(0) {
+        0x01 => None,
+        kind @ 0x90..=0x97 => Some(kind & 0x07),
+        kind => return Err(eyre!("Unsupported GameShark code type {:02X}", kind)),
+    };
+    match wram_bank {
+        Some(_) if !matches!(addr, 0xD000..=0xDFFF) => {
+            return Err(eyre!("Work RAM bank codes can only write to D000-DFFF"))
+        }
+        None if !matches!(addr, 0xA000..=0xDFFF | 0xFF80..=0xFFFE) => {
+            return Err(eyre!("GameShark codes can only write to RAM"))
+        }
+        _ => {}
+    }
+    Ok(CheatCode::GameShark {
+        addr,
+        val: byte(1),
+        wram_bank,
+    })
+}
+
+fn parse_game_genie(code: &str) -> Result<CheatCode> {
+    let groups: Vec<&str> = code.split('-').collect();
+    if !matches!(groups.len(), 2 | 3) || groups.iter().any(|group| group.len() != 3) {
+        return Err(eyre!("Game Genie codes look like ABC-DEF or ABC-DEF-GHI"));
+    }
+    let digits = hex_digits(&groups.concat())?;
+    let nibble = |i: usize| digits[i] as u16;
+    // The top nibble of the address comes last and is inverted
+    let addr = ((nibble(5) ^ 0xF) << 12) | (nibble(2) << 8) | (nibble(3) << 4) | nibble(4);
+    if addr > 0x7FFF {
+        return Err(eyre!("Game Genie codes can only patch ROM"));
+    }
+    // G and I hold the compare byte XORed with 0xBA and rotated left by 2. H isn't used
+    let compare =
+        (digits.len() == 9).then(|| ((digits[6] << 4) | digits[8]).rotate_right(2) ^ 0xBA);
+    Ok(CheatCode::GameGenie {
+        addr,
+        val: (digits[0] << 4) | digits[1],
+        compare,
+    })
+}
+
+fn hex_digits(text: &str) -> Result<Vec<u8>> {
+    text.chars()
+        .map(|c| c.to_digit(16).map(|digit| digit as u8))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(|| eyre!("Codes are made of hex digits"))
+}
+
+/**
+ * One or more codes that are turned on and off together
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cheat {
+    pub name: String,
+    pub enabled: bool,
+    // As entered, joined by '+'
+    code: String,
+    codes: Vec<CheatCode>,
+}
+
+impl Cheat {
+    /**
+     * An enabled cheat. Several codes can be separated by '+', commas or whitespace
+     */
+    pub fn new(name: &str, code: &str) -> Result<Self> {
+        let parts: Vec<String> = code
+            .split(|c: char| c == '+' || c == ',' || c.is_whitespace())
+            .filter(|part| !part.is_empty())
+            .map(str::to_ascii_uppercase)
+            .collect();
+        if parts.is_empty() {
+            return Err(eyre!("No code given"));
+        }
+        let codes = parts
+            .iter()
+            .map(|part| CheatCode::parse(part))
+            .collect::<Result<Vec<CheatCode>>>()?;
+        Ok(Self {
+            name: name.trim().to_string(),
+            enabled: true,
+            code: parts.join("+"),
+            codes,
+        })
+    }
+
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+}
+
+/**
+ * The cheats saved for a game
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CheatList {
+    pub cheats: Vec<Cheat>,
+}
+
+impl CheatList {
+    /**
+     * Where the cheats for a ROM are saved
+     */
+    pub fn path_for_rom(rom_path: &Path) -> PathBuf {
+        rom_path.with_extension(CHEAT_FILE_EXTENSION)
+    }
+
+    /**
+     * Load the cheats saved next to a ROM. Empty if there are none
+     */
+    pub fn load_for_rom(rom_path: &Path) -> Result<Self> {
+        let path = Self::path_for_rom(rom_path);
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+        let cheats = Self::parse(&fs::read_to_string(&path)?)?;
+        info!(
+            "Loaded {} cheats from {}",
+            cheats.cheats.len(),
+            path.display()
+        );
+        Ok(cheats)
+    }
+
+    pub fn save_for_rom(&self, rom_path: &Path) -> Result<()> {
+        let mut file = io::BufWriter::new(fs::File::create(Self::path_for_rom(rom_path))?);
+        self.write(&mut file)?;
+        file.flush()?;
+        Ok(())
+    }
+
+    pub fn write(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "{}", HEADER)?;
+        for cheat in &self.cheats {
+            let enabled = match cheat.enabled {
+                true => '+',
+                false => '-',
+            };
+            writeln!(writer, "{} {} {}", enabled, cheat.code, cheat.name)?;
+        }
+        Ok(())
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut cheats = Vec::new();
+        for (line_idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let bad_line = || eyre!("Bad cheat on line {}: {}", line_idx + 1, line);
+            let (enabled, rest) = line.split_once(' ').ok_or_else(bad_line)?;
+            let enabled = match enabled {
+                "+" => true,
+                "-" => false,
+                _ => return Err(bad_line()),
+            };
+            let (code, name) = rest.split_once(' ').unwrap_or((rest, ""));
+            let mut cheat =
+                Cheat::new(name, code).map_err(|e| eyre!("Line {}: {}", line_idx + 1, e))?;
+            cheat.enabled = enabled;
+            cheats.push(cheat);
+        }
+        Ok(Self { cheats })
+    }
+
+    fn enabled_codes(&self) -> impl Iterator<Item = CheatCode> + '_ {
+        self.cheats
+            .iter()
+            .filter(|cheat| cheat.enabled)
+            .flat_map(|cheat| cheat.codes.iter().copied())
+    }
+}
+
+/**
+ * Use the enabled cheats of a list from now on instead of the ones used before
+ */
+pub fn set_cheats(state: &mut GBCState, cheats: &CheatList) {
+    let mut game_shark_codes = Vec::new();
+    let mut rom_patches = Vec::new();
+    for code in cheats.enabled_codes() {
+        match code {
+            CheatCode::GameShark { .. } => game_shark_codes.push(code),
+            CheatCode::GameGenie { addr, val, compare } => {
+                rom_patches.push(RomPatch { addr, val, compare })
+            }
+        }
+    }
+    state.game_shark_codes = game_shark_codes;
+    virtual_memory::set_rom_patches(state, rom_patches);
+    let entered = cheats
+        .cheats
+        .iter()
+        .filter(|cheat| cheat.enabled)
+        .flat_map(|cheat| cheat.code.split('+'))
+        .map(str::to_string)
+        .collect();
+    movie::set_cheats(state, entered);
+}
+
+/**
+ * Write the enabled GameShark codes to RAM. Called when VBlank starts, which is when the GameShark
+ * itself writes them
+ */
+pub fn apply_game_shark_codes(state: &mut GBCState) {
+    if state.game_shark_codes.is_empty() {
+        return;
+    }
+    let codes = mem::take(&mut state.game_shark_codes);
+    for &code in &codes {
+        if let CheatCode::GameShark {
+            addr,
+            val,
+            wram_bank,
+        } = code
+        {
+            match wram_bank {
+                // Both 0 and 1 mean the first switchable bank, like the bank register
+                Some(bank) => virtual_memory::write_override_bank(
+                    state,
+                    addr,
+                    (bank as usize).saturating_sub(1),
+                    val,
+                ),
+                None => virtual_memory::write_without_triggers(state, addr, val),
+            }
+        }
+    }
+    state.game_shark_codes = codes;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gbc::run_frame;
+
+    #[test]
+    fn parses_game_shark_and_game_genie_codes() {
+        assert_eq!(
+            CheatCode::parse("014223C1").unwrap(),
+            CheatCode::GameShark {
+                addr: 0xC123,
+                val: 0x42,
+                wram_bank: None
+            }
+        );
+        assert_eq!(
+            CheatCode::parse("939900D0").unwrap(),
+            CheatCode::GameShark {
+                addr: 0xD000,
+                val: 0x99,
+                wram_bank: Some(3)
+            }
+        );
+        assert_eq!(
+            CheatCode::parse("00A-17B-C49").unwrap(),
+            CheatCode::GameGenie {
+                addr: 0x4A17,
+                val: 0x00,
+                compare: Some(0xC8)
+            }
+        );
+        assert_eq!(
+            CheatCode::parse("3C1-50F").unwrap(),
+            CheatCode::GameGenie {
+                addr: 0x0150,
+                val: 0x3C,
+                compare: None
+            }
+        );
+        // VRAM, a bank code outside banked work RAM and a RAM address for Game Genie
+        assert!(CheatCode::parse("01420080").is_err());
+        assert!(CheatCode::parse("914200C0").is_err());
+        assert!(CheatCode::parse("3C1-507").is_err());
+    }
+
+    #[test]
+    fn cheat_file_round_trips() {
+        let mut cheats = CheatList {
+            cheats: vec![
+                Cheat::new("Max money", "014223c1, 939900D0").unwrap(),
+                Cheat::new("", "3C1-50F-EAA").unwrap(),
+            ],
+        };
+        cheats.cheats[1].enabled = false;
+        assert_eq!(cheats.cheats[0].code(), "014223C1+939900D0");
+
+        let mut text = Vec::new();
+        cheats.write(&mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert_eq!(CheatList::parse(&text).unwrap(), cheats);
+        assert!(CheatList::parse("* 014223C1 Bad").is_err());
+    }
+
+    #[test]
+    fn applies_enabled_codes() {
+        // JR -2
+        let mut state = GBCState::with_program(&[0x18, 0xFE]);
+        let mut cheats = CheatList {
+            cheats: vec![
+                Cheat::new("Write", "014223C1+939900D0").unwrap(),
+                // 0x3C at 0x0150 while the ROM holds 0x00 there
+                Cheat::new("Patch", "3C1-50F-EAA").unwrap(),
+                // 0x3C at 0x0151 while the ROM holds 0x12 there, which it doesn't
+                Cheat::new("Skipped", "3C1-51F-AA2").unwrap(),
+            ],
+        };
+        set_cheats(&mut state, &cheats);
+        assert_eq!(virtual_memory::read(&state, 0x0150), 0x3C);
+        assert_eq!(virtual_memory::read(&state, 0x0151), 0x00);
+        assert_eq!(virtual_memory::read(&state, 0x0100), 0x18);
+
+        run_frame(&mut state);
+        assert_eq!(virtual_memory::read(&state, 0xC123), 0x42);
+        assert_eq!(virtual_memory::read_override_bank(&state, 0xD000, 2), 0x99);
+
+        cheats.cheats[0].enabled = false;
+        cheats.cheats[1].enabled = false;
+        set_cheats(&mut state, &cheats);
+        assert_eq!(virtual_memory::read(&state, 0x0150), 0x00);
+        virtual_memory::write(&mut state, 0xC123, 0);
+        run_frame(&mut state);
+        assert_eq!(virtual_memory::read(&state, 0xC123), 0);
+    }
+}
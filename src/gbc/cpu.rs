@@ -1,49 +1,84 @@
+mod call_stack;
 mod cb_instruction_impl;
 mod instruction_impl;
 mod instructions;
 mod op_helpers;
 mod register;
 
-use tracing::{trace_span, trace, info_span, debug_span, debug};
+use std::hash::{Hash, Hasher};
 
-use crate::util::{combine_high_low, Bytes};
+use tracing::{debug, error};
 
-use self::instructions::map_instruction;
+use crate::util::{combine_high_low, hot_debug_span, hot_trace, Bytes};
+
+pub use self::call_stack::{CallFrame, CallKind};
+pub use self::instructions::map_instruction;
+pub use self::register::{Register, RegisterPair};
+use self::call_stack::CallStack;
 use self::register::{RegisterMap, RegisterMapMethods};
-use super::interrupt_controller::{
-    self, InterruptFlag, INTERRUPT_ENABLE_ADDR, INTERRUPT_REQUEST_ADDR,
-};
-use super::{virtual_memory, GBCState};
+use super::interrupt_controller::{self, InterruptFlag};
+use super::{scheduler, symbols, virtual_memory, GBCEvent, GBCState, Model};
 
 const PROGRAM_START_ADDR: u16 = 0x0100;
 const STACK_POINTER_START_ADDR: u16 = 0xFFFE;
+const TICKS_PER_MACHINE_CYCLE: u64 = 8;
 
+#[derive(Clone)]
 pub struct CPU {
     registers: RegisterMap,
     pc: u16,
     sp: u16,
     halted: bool,
+    // HALT was executed with IME disabled and an interrupt pending. The next
+    // opcode fetch fails to increment the PC, so that byte is read twice.
+    halt_bug: bool,
+    // An illegal opcode was executed. The CPU stops responding until reset
+    locked: bool,
     busy_t_cycles: u8,
+    // Tick the next instruction starts on. There are 8 ticks per machine cycle
+    next_tick: u64,
+    call_stack: CallStack,
 }
 
 impl CPU {
     pub fn new() -> Self {
         Self {
             registers: RegisterMap::new(),
-            // Start of user program
-            pc: PROGRAM_START_ADDR,
-            // End of stack RAM (stack starts at end)
-            sp: STACK_POINTER_START_ADDR,
+            // Power on state. The boot ROM starts at address 0 and sets up the stack itself
+            pc: 0x0000,
+            sp: 0x0000,
             halted: false,
+            halt_bug: false,
+            locked: false,
             busy_t_cycles: 0,
+            next_tick: 0,
+            call_stack: CallStack::default(),
         }
     }
 }
 
+// The call stack is only tracked for the debugger, so it's left out of state hashes
+impl Hash for CPU {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.registers.hash(state);
+        self.pc.hash(state);
+        self.sp.hash(state);
+        self.halted.hash(state);
+        self.halt_bug.hash(state);
+        self.locked.hash(state);
+        self.busy_t_cycles.hash(state);
+        self.next_tick.hash(state);
+    }
+}
+
 // Fetch next 8 bits at program counter
 fn fetch_and_incr_pc(state: &mut GBCState) -> u8 {
     let data = virtual_memory::read(state, state.cpu.pc);
-    trace!("Fetched value {:#04x} from PC {:#06x}", data, state.cpu.pc);
+    hot_trace!("Fetched value {:#04x} from PC {:#06x}", data, state.cpu.pc);
+    if state.cpu.halt_bug {
+        state.cpu.halt_bug = false;
+        return data;
+    }
     state.cpu.pc += 1;
     data
 }
@@ -59,9 +94,7 @@ fn fetch_and_incr_pc_16(state: &mut GBCState) -> u16 {
 fn cpu_should_wake(state: &GBCState) -> bool {
     // CPU only wakes up if there is an enabled and requested interrupt.
     // IME does not need to be set.
-    virtual_memory::read(state, INTERRUPT_ENABLE_ADDR)
-        & virtual_memory::read(state, INTERRUPT_REQUEST_ADDR)
-        > 0
+    interrupt_controller::is_interrupt_pending(state)
 }
 
 // Mark cpu as busy for n t-cycles
@@ -69,32 +102,180 @@ fn consume_cycles(state: &mut GBCState, t_cycles: u8) {
     debug_assert!(t_cycles != 0);
     // If something takes n cycles, include this cycle
     state.cpu.busy_t_cycles += t_cycles - 1;
+    state.profiler.add_cycles(t_cycles);
+}
+
+// Charge the cycles consumed from now on to the instruction at pc and the current call stack
+fn profile_instruction(state: &mut GBCState, pc: u16) {
+    if state.profiler.is_enabled() {
+        let bank = symbols::current_bank(state, pc);
+        state
+            .profiler
+            .start_instruction(bank, pc, state.cpu.call_stack.frames());
+    }
 }
 
 // Call a method by moving current PC to SP and setting PC
-fn call(state: &mut GBCState, new_pc: u16) {
+fn call(state: &mut GBCState, new_pc: u16, kind: CallKind) {
     state.cpu.sp -= 1;
     virtual_memory::write(state, state.cpu.sp, state.cpu.pc.high());
     state.cpu.sp -= 1;
     virtual_memory::write(state, state.cpu.sp, state.cpu.pc.low());
+    let bank = symbols::current_bank(state, new_pc);
+    state
+        .cpu
+        .call_stack
+        .push(kind, bank, new_pc, state.cpu.pc, state.cpu.sp);
     state.cpu.pc = new_pc;
 }
 
+// Lock up the CPU after executing an illegal opcode, the same way hardware does
+fn lock_up(state: &mut GBCState, opcode: u8) {
+    let pc = state.cpu.pc.wrapping_sub(1);
+    error!(
+        "Illegal opcode {:#04x} at PC {}. CPU locked up",
+        opcode,
+        symbols::describe(state, pc)
+    );
+    state.cpu.locked = true;
+    // Frontend may have gone away. Nothing to do about it here
+    state.events.send(GBCEvent::CPULockedUp { pc, opcode }).ok();
+}
+
 fn handle_interrupt(state: &mut GBCState, intr: InterruptFlag) {
     debug!("Handling {} interrupt", intr.to_string());
     interrupt_controller::reset_interrupt_request_flag(state, intr);
     interrupt_controller::disable_interrupts(state);
-    call(state, intr.handler_address());
+    // Servicing an interrupt always wakes the CPU. The handler's first byte is only read once
+    state.cpu.halted = false;
+    state.cpu.halt_bug = false;
+    call(state, intr.handler_address(), CallKind::Interrupt);
+    // Dispatch is charged to the handler
+    profile_instruction(state, intr.handler_address());
     consume_cycles(state, 20);
 }
 
-pub fn tick(state: &mut GBCState) {
-    if state.cpu.busy_t_cycles > 0 {
-        // CPU has been marked as already busy this cycle
-        state.cpu.busy_t_cycles = state.cpu.busy_t_cycles.saturating_sub(1);
+pub fn read_register(state: &GBCState, register: Register) -> u8 {
+    state.cpu.registers.read(register)
+}
+
+pub fn read_register_pair(state: &GBCState, pair: RegisterPair) -> u16 {
+    state.cpu.registers.read_pair(pair)
+}
+
+pub fn write_register_pair(state: &mut GBCState, pair: RegisterPair, val: u16) {
+    state.cpu.registers.write_pair(pair, val);
+}
+
+pub fn read_pc(state: &GBCState) -> u16 {
+    state.cpu.pc
+}
+
+pub fn write_pc(state: &mut GBCState, val: u16) {
+    state.cpu.pc = val;
+}
+
+pub fn read_sp(state: &GBCState) -> u16 {
+    state.cpu.sp
+}
+
+pub fn write_sp(state: &mut GBCState, val: u16) {
+    state.cpu.sp = val;
+}
+
+pub fn is_locked(state: &GBCState) -> bool {
+    state.cpu.locked
+}
+
+/**
+ * Functions the CPU is in, outermost first
+ */
+pub fn call_stack(state: &GBCState) -> &[CallFrame] {
+    state.cpu.call_stack.frames()
+}
+
+/**
+ * Set the registers to what the boot ROM leaves behind when it jumps to the cartridge. Games
+ * check A to tell which model they are running on
+ */
+pub fn set_post_boot_registers(
+    state: &mut GBCState,
+    model: Model,
+    cgb_cartridge: bool,
+    header_checksum: u8,
+) {
+    let values = match (model, cgb_cartridge) {
+        // H and C flags are only cleared if the header checksum is 0
+        (Model::DMG, _) => {
+            let flags = if header_checksum == 0 { 0x80 } else { 0xB0 };
+            [0x01, flags, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D]
+        }
+        (Model::CGB, true) => [0x11, 0x80, 0x00, 0x00, 0xFF, 0x56, 0x00, 0x0D],
+        // Compatibility mode. B, H and L depend on the palette picked for the game
+        (Model::CGB, false) => [0x11, 0x80, 0x00, 0x00, 0x00, 0x08, 0x00, 0x7C],
+    };
+    let registers = [
+        Register::A,
+        Register::F,
+        Register::B,
+        Register::C,
+        Register::D,
+        Register::E,
+        Register::H,
+        Register::L,
+    ];
+    for (register, val) in registers.into_iter().zip(values) {
+        state.cpu.registers.write(register, val);
+    }
+    state.cpu.pc = PROGRAM_START_ADDR;
+    // End of high RAM (stack grows down)
+    state.cpu.sp = STACK_POINTER_START_ADDR;
+}
+
+/**
+ * Execute instructions until the CPU reaches the cycle of the next scheduled event
+ */
+pub fn run_until_next_event(state: &mut GBCState) {
+    while try_step(state) {}
+}
+
+/**
+ * Execute one instruction or interrupt dispatch if the CPU can before the next scheduled event.
+ * Returns false if it has to wait for the event first
+ */
+pub fn try_step(state: &mut GBCState) -> bool {
+    // Instructions can schedule events, so check again every time
+    let next_event_tick =
+        scheduler::next_event_cycle(state).saturating_mul(TICKS_PER_MACHINE_CYCLE);
+    if state.cpu.next_tick >= next_event_tick {
+        return false;
+    }
+    scheduler::advance_to(state, state.cpu.next_tick / TICKS_PER_MACHINE_CYCLE);
+
+    if state.cpu.locked || (state.cpu.halted && !cpu_should_wake(state)) {
+        // Nothing can change until an event fires
+        state.cpu.next_tick = next_event_tick;
+        return false;
+    }
+    step(state);
+    true
+}
+
+/**
+ * Run a single instruction or interrupt dispatch and mark the ticks it takes
+ */
+fn step(state: &mut GBCState) {
+    if state.cpu.locked {
+        state.cpu.next_tick += 1;
         return;
     }
 
+    execute(state);
+    state.cpu.next_tick += 1 + state.cpu.busy_t_cycles as u64;
+    state.cpu.busy_t_cycles = 0;
+}
+
+fn execute(state: &mut GBCState) {
     if let Some(intr) = interrupt_controller::get_active_interrupt(state) {
         handle_interrupt(state, intr);
         return;
@@ -107,11 +288,189 @@ pub fn tick(state: &mut GBCState) {
         state.cpu.halted = false;
     }
 
+    // EI only takes effect after the instruction following it has executed
+    let enable_interrupts_after_instr = interrupt_controller::is_interrupt_enable_pending(state);
+
+    profile_instruction(state, state.cpu.pc);
     let instruction = fetch_and_incr_pc(state);
     let instruction_impl = map_instruction(instruction);
     
-    let span = debug_span!("CPU Instruction", instruction = format!("{:#04x}", instruction)).entered();
-    trace!("Starting instruction");
+    let span = hot_debug_span!(
+        "CPU Instruction",
+        instruction = format!("{:#04x}", instruction),
+        pc = %symbols::display(state, state.cpu.pc.wrapping_sub(1))
+    );
+    hot_trace!("Starting instruction");
+    // Only accesses made by the instruction itself trigger watchpoints
+    virtual_memory::arm_watchpoints(state, true);
     instruction_impl(state);
+    virtual_memory::arm_watchpoints(state, false);
     span.exit();
+
+    if enable_interrupts_after_instr {
+        interrupt_controller::apply_pending_interrupt_enable(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gbc::interrupt_controller::{INTERRUPT_ENABLE_ADDR, INTERRUPT_REQUEST_ADDR};
+    use crate::gbc::cpu::register::Register;
+
+    const NOP: u8 = 0x00;
+    const INC_A: u8 = 0x3C;
+    const HALT: u8 = 0x76;
+    const RETI: u8 = 0xD9;
+    const ILLEGAL: u8 = 0xD3;
+    const DI: u8 = 0xF3;
+    const EI: u8 = 0xFB;
+
+    fn request_vblank_interrupt(state: &mut GBCState) {
+        virtual_memory::write_without_triggers(state, INTERRUPT_ENABLE_ADDR, 0x01);
+        virtual_memory::write_without_triggers(state, INTERRUPT_REQUEST_ADDR, 0x01);
+    }
+
+    #[test]
+    fn di_takes_effect_immediately() {
+        let mut state = GBCState::with_program(&[DI, NOP]);
+        interrupt_controller::enable_interrupts_immediately(&mut state);
+
+        step(&mut state);
+        assert!(!interrupt_controller::is_interrupt_master_enabled(&state));
+        // Interrupt requested right after DI must not be serviced
+        request_vblank_interrupt(&mut state);
+        step(&mut state);
+        assert_eq!(state.cpu.pc, 0x0102);
+    }
+
+    #[test]
+    fn ei_takes_effect_after_next_instruction() {
+        let mut state = GBCState::with_program(&[EI, NOP, NOP]);
+        request_vblank_interrupt(&mut state);
+
+        step(&mut state);
+        assert!(!interrupt_controller::is_interrupt_master_enabled(&state));
+        // The instruction following EI still runs before the interrupt
+        step(&mut state);
+        assert_eq!(state.cpu.pc, 0x0102);
+        assert!(interrupt_controller::is_interrupt_master_enabled(&state));
+        step(&mut state);
+        assert_eq!(state.cpu.pc, InterruptFlag::VerticalBlanking.handler_address());
+    }
+
+    #[test]
+    fn di_cancels_pending_ei() {
+        let mut state = GBCState::with_program(&[EI, DI, NOP]);
+        request_vblank_interrupt(&mut state);
+
+        step(&mut state);
+        step(&mut state);
+        step(&mut state);
+        assert!(!interrupt_controller::is_interrupt_master_enabled(&state));
+        assert_eq!(state.cpu.pc, 0x0103);
+    }
+
+    #[test]
+    fn reti_enables_interrupts_immediately() {
+        let mut state = GBCState::with_program(&[RETI]);
+        // Return to 0x0150
+        state.cpu.sp = 0xFFFC;
+        virtual_memory::write(&mut state, 0xFFFC, 0x50);
+        virtual_memory::write(&mut state, 0xFFFD, 0x01);
+        request_vblank_interrupt(&mut state);
+
+        step(&mut state);
+        assert_eq!(state.cpu.pc, 0x0150);
+        assert!(interrupt_controller::is_interrupt_master_enabled(&state));
+        step(&mut state);
+        assert_eq!(state.cpu.pc, InterruptFlag::VerticalBlanking.handler_address());
+    }
+
+    #[test]
+    fn halt_bug_repeats_next_byte() {
+        let mut state = GBCState::with_program(&[HALT, INC_A, NOP]);
+        request_vblank_interrupt(&mut state);
+        let a = state.cpu.registers.read(Register::A);
+
+        step(&mut state);
+        assert!(!state.cpu.halted);
+        step(&mut state);
+        assert_eq!(state.cpu.pc, 0x0101);
+        step(&mut state);
+        assert_eq!(state.cpu.pc, 0x0102);
+        assert_eq!(state.cpu.registers.read(Register::A), a.wrapping_add(2));
+    }
+
+    #[test]
+    fn ei_before_halt_services_interrupt_without_halt_bug() {
+        let mut state = GBCState::with_program(&[EI, HALT, INC_A]);
+        request_vblank_interrupt(&mut state);
+
+        step(&mut state);
+        step(&mut state);
+        assert!(!state.cpu.halt_bug);
+        step(&mut state);
+        let handler_addr = InterruptFlag::VerticalBlanking.handler_address();
+        assert_eq!(state.cpu.pc, handler_addr);
+        assert_eq!(virtual_memory::read(&state, state.cpu.sp), 0x02);
+        // The handler's first byte runs once
+        step(&mut state);
+        assert_eq!(state.cpu.pc, handler_addr + 1);
+    }
+
+    #[test]
+    fn halt_wakes_without_ime_and_continues() {
+        let mut state = GBCState::with_program(&[HALT, INC_A]);
+        let a = state.cpu.registers.read(Register::A);
+
+        step(&mut state);
+        assert!(state.cpu.halted);
+        step(&mut state);
+        assert!(state.cpu.halted);
+
+        request_vblank_interrupt(&mut state);
+        step(&mut state);
+        assert!(!state.cpu.halted);
+        assert_eq!(state.cpu.pc, 0x0102);
+        assert_eq!(state.cpu.registers.read(Register::A), a.wrapping_add(1));
+    }
+
+    #[test]
+    fn interrupt_dispatch_exits_halt() {
+        let mut state = GBCState::with_program(&[EI, HALT, NOP]);
+
+        step(&mut state);
+        step(&mut state);
+        assert!(state.cpu.halted);
+
+        request_vblank_interrupt(&mut state);
+        step(&mut state);
+        assert!(!state.cpu.halted);
+        assert!(!interrupt_controller::is_interrupt_master_enabled(&state));
+        assert_eq!(state.cpu.pc, InterruptFlag::VerticalBlanking.handler_address());
+    }
+
+    #[test]
+    fn illegal_opcode_locks_up_cpu() {
+        let mut state = GBCState::with_program(&[NOP, ILLEGAL, INC_A]);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        state.events = sender;
+        let a = state.cpu.registers.read(Register::A);
+
+        step(&mut state);
+        step(&mut state);
+        assert!(state.cpu.locked);
+        assert!(matches!(
+            receiver.try_recv(),
+            Ok(GBCEvent::CPULockedUp { pc: 0x0101, opcode: ILLEGAL })
+        ));
+
+        // Interrupts can't wake a locked up CPU either
+        interrupt_controller::enable_interrupts_immediately(&mut state);
+        request_vblank_interrupt(&mut state);
+        step(&mut state);
+        assert_eq!(state.cpu.pc, 0x0102);
+        assert_eq!(state.cpu.registers.read(Register::A), a);
+    }
 }
@@ -0,0 +1,80 @@
+// Deepest the shadow stack gets. Code that leaves functions without returning would grow it forever
+const MAX_DEPTH: usize = 256;
+
+/**
+ * How a function was entered
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallKind {
+    Call,
+    Rst,
+    Interrupt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallFrame {
+    pub kind: CallKind,
+    // Entry point of the function and the bank it is in, numbered the way RGBDS does
+    pub bank: u16,
+    pub target: u16,
+    pub return_addr: u16,
+    // Where the return address was pushed
+    sp: u16,
+}
+
+/**
+ * Functions the CPU is in, outermost first. Rebuilt from calls and returns since the real stack
+ * only holds return addresses mixed with everything else pushed on it
+ */
+#[derive(Debug, Clone, Default)]
+pub struct CallStack {
+    frames: Vec<CallFrame>,
+}
+
+impl CallStack {
+    pub fn frames(&self) -> &[CallFrame] {
+        &self.frames
+    }
+
+    pub fn push(&mut self, kind: CallKind, bank: u16, target: u16, return_addr: u16, sp: u16) {
+        if self.frames.len() == MAX_DEPTH {
+            self.frames.remove(0);
+        }
+        self.frames.push(CallFrame {
+            kind,
+            bank,
+            target,
+            return_addr,
+            sp,
+        });
+    }
+
+    /**
+     * A return address is about to be popped from sp. Frames whose return address was pushed
+     * below it were left without returning, so they go too. Returns that don't match a call, like
+     * jumping by pushing an address and returning to it, leave the stack alone
+     */
+    pub fn pop(&mut self, sp: u16) {
+        while self.frames.last().is_some_and(|frame| frame.sp <= sp) {
+            self.frames.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_frames_that_never_returned() {
+        let mut stack = CallStack::default();
+        stack.push(CallKind::Call, 0, 0x0200, 0x0153, 0xFFFC);
+        stack.push(CallKind::Call, 0, 0x0300, 0x0203, 0xFFFA);
+        // Return address pushed without a call
+        stack.pop(0xFFF8);
+        assert_eq!(stack.frames().len(), 2);
+        // The inner function discarded its return address and returned from the outer one
+        stack.pop(0xFFFC);
+        assert!(stack.frames().is_empty());
+    }
+}
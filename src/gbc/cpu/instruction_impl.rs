@@ -6,8 +6,11 @@ use crate::{
 };
 
 use super::{
-    call, consume_cycles,
+    call,
+    call_stack::CallKind,
+    consume_cycles,
     instructions::map_CB_prefix_instruction,
+    lock_up,
     op_helpers::*,
     register::{FlagRegister, Register, RegisterMapMethods, RegisterPair},
 };
@@ -843,7 +846,15 @@ pub(super) fn instr_0x75(state: &mut GBCState) {
 
 // HALT
 pub(super) fn instr_0x76(state: &mut GBCState) {
-    state.cpu.halted = true;
+    // EI right before HALT counts as IME set. The interrupt is serviced instead
+    let ime = interrupt_controller::is_interrupt_master_enabled(state)
+        || interrupt_controller::is_interrupt_enable_pending(state);
+    if !ime && interrupt_controller::is_interrupt_pending(state) {
+        // HALT bug. The CPU doesn't halt and the next byte is read twice
+        state.cpu.halt_bug = true;
+    } else {
+        state.cpu.halted = true;
+    }
     consume_cycles(state, 4);
 }
 
@@ -1338,7 +1349,7 @@ pub(super) fn instr_0xC3(state: &mut GBCState) {
 pub(super) fn instr_0xC4(state: &mut GBCState) {
     let new_pc = super::fetch_and_incr_pc_16(state);
     if !state.cpu.registers.get_flags().z {
-        call(state, new_pc);
+        call(state, new_pc, CallKind::Call);
         consume_cycles(state, 24);
         return;
     }
@@ -1402,7 +1413,7 @@ pub(super) fn instr_0xCB(state: &mut GBCState) {
 pub(super) fn instr_0xCC(state: &mut GBCState) {
     let new_pc = super::fetch_and_incr_pc_16(state);
     if state.cpu.registers.get_flags().z {
-        call(state, new_pc);
+        call(state, new_pc, CallKind::Call);
         consume_cycles(state, 24);
         return;
     }
@@ -1412,7 +1423,7 @@ pub(super) fn instr_0xCC(state: &mut GBCState) {
 // CALL u16
 pub(super) fn instr_0xCD(state: &mut GBCState) {
     let new_pc = super::fetch_and_incr_pc_16(state);
-    call(state, new_pc);
+    call(state, new_pc, CallKind::Call);
     consume_cycles(state, 24);
 }
 
@@ -1457,15 +1468,15 @@ pub(super) fn instr_0xD2(state: &mut GBCState) {
 }
 
 // Invalid Opcode
-pub(super) fn instr_0xD3(_state: &mut GBCState) {
-    unimplemented!();
+pub(super) fn instr_0xD3(state: &mut GBCState) {
+    lock_up(state, 0xD3);
 }
 
 // CALL NC, u16
 pub(super) fn instr_0xD4(state: &mut GBCState) {
     let new_pc = super::fetch_and_incr_pc_16(state);
     if !state.cpu.registers.get_flags().cy {
-        call(state, new_pc);
+        call(state, new_pc, CallKind::Call);
         consume_cycles(state, 24);
         return;
     }
@@ -1504,7 +1515,7 @@ pub(super) fn instr_0xD8(state: &mut GBCState) {
 // RETI
 pub(super) fn instr_0xD9(state: &mut GBCState) {
     op_RET(state);
-    interrupt_controller::enable_interrupts(state);
+    interrupt_controller::enable_interrupts_immediately(state);
     consume_cycles(state, 16);
 }
 
@@ -1520,15 +1531,15 @@ pub(super) fn instr_0xDA(state: &mut GBCState) {
 }
 
 // Invalid Opcode
-pub(super) fn instr_0xDB(_state: &mut GBCState) {
-    unimplemented!();
+pub(super) fn instr_0xDB(state: &mut GBCState) {
+    lock_up(state, 0xDB);
 }
 
 // CALL C, u16
 pub(super) fn instr_0xDC(state: &mut GBCState) {
     let new_pc = super::fetch_and_incr_pc_16(state);
     if state.cpu.registers.get_flags().cy {
-        call(state, new_pc);
+        call(state, new_pc, CallKind::Call);
         consume_cycles(state, 24);
         return;
     }
@@ -1536,8 +1547,8 @@ pub(super) fn instr_0xDC(state: &mut GBCState) {
 }
 
 // Invalid Opcode
-pub(super) fn instr_0xDD(_state: &mut GBCState) {
-    unimplemented!();
+pub(super) fn instr_0xDD(state: &mut GBCState) {
+    lock_up(state, 0xDD);
 }
 
 // SBC A, u8
@@ -1572,13 +1583,13 @@ pub(super) fn instr_0xE2(state: &mut GBCState) {
 }
 
 // Invalid Opcode
-pub(super) fn instr_0xE3(_state: &mut GBCState) {
-    unimplemented!();
+pub(super) fn instr_0xE3(state: &mut GBCState) {
+    lock_up(state, 0xE3);
 }
 
 // Invalid Opcode
-pub(super) fn instr_0xE4(_state: &mut GBCState) {
-    unimplemented!();
+pub(super) fn instr_0xE4(state: &mut GBCState) {
+    lock_up(state, 0xE4);
 }
 
 // PUSH HL
@@ -1628,18 +1639,18 @@ pub(super) fn instr_0xEA(state: &mut GBCState) {
 }
 
 // Invalid Opcode
-pub(super) fn instr_0xEB(_state: &mut GBCState) {
-    unimplemented!();
+pub(super) fn instr_0xEB(state: &mut GBCState) {
+    lock_up(state, 0xEB);
 }
 
 // Invalid Opcode
-pub(super) fn instr_0xEC(_state: &mut GBCState) {
-    unimplemented!();
+pub(super) fn instr_0xEC(state: &mut GBCState) {
+    lock_up(state, 0xEC);
 }
 
 // Invalid Opcode
-pub(super) fn instr_0xED(_state: &mut GBCState) {
-    unimplemented!();
+pub(super) fn instr_0xED(state: &mut GBCState) {
+    lock_up(state, 0xED);
 }
 
 // XOR u8
@@ -1680,8 +1691,8 @@ pub(super) fn instr_0xF3(state: &mut GBCState) {
 }
 
 // Invalid Opcode
-pub(super) fn instr_0xF4(_state: &mut GBCState) {
-    unimplemented!();
+pub(super) fn instr_0xF4(state: &mut GBCState) {
+    lock_up(state, 0xF4);
 }
 
 // PUSH AF
@@ -1737,13 +1748,13 @@ pub(super) fn instr_0xFB(state: &mut GBCState) {
 }
 
 // Invalid Opcode
-pub(super) fn instr_0xFC(_state: &mut GBCState) {
-    unimplemented!();
+pub(super) fn instr_0xFC(state: &mut GBCState) {
+    lock_up(state, 0xFC);
 }
 
 // Invalid Opcode
-pub(super) fn instr_0xFD(_state: &mut GBCState) {
-    unimplemented!();
+pub(super) fn instr_0xFD(state: &mut GBCState) {
+    lock_up(state, 0xFD);
 }
 
 // CP u8
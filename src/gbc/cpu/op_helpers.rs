@@ -6,10 +6,11 @@ use crate::{
     gbc::{virtual_memory, GBCState},
     util::{
         add_and_get_carries, combine_high_low, index_bits, reset_bit, set_bit,
-        subtract_and_get_borrows, Bytes,
+        subtract_and_get_borrows,
     },
 };
 
+use super::call_stack::CallKind;
 use super::register::{FlagRegister, Register, RegisterMapMethods, RegisterPair};
 
 /**
@@ -520,6 +521,7 @@ pub(super) fn op_RES_from_HLptr(state: &mut GBCState, bit: usize) {
 }
 
 pub(super) fn op_RET(state: &mut GBCState) {
+    state.cpu.call_stack.pop(state.cpu.sp);
     let pc_low = virtual_memory::read(state, state.cpu.sp);
     state.cpu.sp += 1;
     let pc_high = virtual_memory::read(state, state.cpu.sp);
@@ -528,9 +530,5 @@ pub(super) fn op_RET(state: &mut GBCState) {
 }
 
 pub(super) fn op_RST(state: &mut GBCState, new_pc: u16) {
-    state.cpu.sp -= 1;
-    virtual_memory::write(state, state.cpu.sp, state.cpu.pc.high());
-    state.cpu.sp -= 1;
-    virtual_memory::write(state, state.cpu.sp, state.cpu.pc.low());
-    state.cpu.pc = new_pc;
+    super::call(state, new_pc, CallKind::Rst);
 }
@@ -1,9 +1,7 @@
 use std::fmt;
 
 use enum_map::{enum_map, Enum, EnumMap};
-use tracing::trace;
-
-use crate::util::{index_bits, Bytes, combine_high_low};
+use crate::util::{index_bits, hot_trace, Bytes, combine_high_low};
 
 pub type RegisterMap = EnumMap<Register, u8>;
 
@@ -20,8 +18,7 @@ pub trait RegisterMapMethods {
 impl RegisterMapMethods for RegisterMap {
     fn new() -> Self {
         enum_map! {
-            // Initial value of 0x11 indicates this is CGB hardware
-            Register::A => 0x11,
+            Register::A => 0x00,
             Register::F => 0x00,
             Register::B => 0x00,
             Register::C => 0x00,
@@ -41,7 +38,7 @@ impl RegisterMapMethods for RegisterMap {
             // Register F is 4 bits only
             val = val & 0xF0;
         }
-        trace!("Wrote {:#04x} ({}) to CPU register {}", val, val, register.to_string());
+        hot_trace!("Wrote {:#04x} ({}) to CPU register {}", val, val, register.to_string());
         self[register] = val;
     }
 
@@ -0,0 +1,339 @@
+use enum_map::{enum_map, EnumMap};
+use int_enum::IntEnum;
+
+use crate::util::index_bits;
+
+use super::{
+    cpu::{self, CallKind},
+    disassembler::{self, DisassembledInstruction},
+    interrupt_controller::{self, INTERRUPT_ENABLE_ADDR},
+    lcd_controller::{
+        LCDControl, TileMapArea, LCD_CONTROL_REGISTER, SCROLL_X_REGISTER, SCROLL_Y_REGISTER,
+        WINDOW_X_REGISTER, WINDOW_Y_REGISTER,
+    },
+    render_engine::{self, OAMEntry, TileAttributes},
+    symbols,
+    virtual_memory::{self, MemoryAreaName},
+    GBCState,
+};
+
+const VRAM_ADDR: u16 = 0x8000;
+const IO_REGISTERS_ADDR: u16 = 0xFF00;
+const VRAM_BANK_SIZE: usize = 0x2000;
+const BYTES_PER_TILE: usize = 16;
+// Instructions disassembled from PC on
+const DISASSEMBLY_LENGTH: usize = 32;
+
+pub const TILES_PER_BANK: usize = 384;
+pub const PALETTES_PER_KIND: u8 = 8;
+pub const TILE_MAP_SIZE: usize = 32;
+
+// Bank the memory viewer shows for each memory area. None follows the active bank
+pub type MemoryViewBanks = EnumMap<MemoryAreaName, Option<usize>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaletteKind {
+    BG,
+    OBJ,
+}
+
+/**
+ * Copy of the memory shown by the debug windows. Published at the start of every frame while
+ * debug capture is enabled, so the frontend never has to touch the emulator state directly
+ */
+#[derive(Debug, Clone)]
+pub struct DebugSnapshot {
+    // Both banks of 0x8000-0x9FFF, bank 0 first
+    pub vram: Vec<u8>,
+    pub bg_palettes: Vec<u8>,
+    pub obj_palettes: Vec<u8>,
+    // 0xFF00-0xFF7F
+    pub io_registers: Vec<u8>,
+    pub interrupt_enable: u8,
+    pub interrupt_master_enable: bool,
+    pub oam: Vec<u8>,
+    // Bitmask of the OAM entries picked by the OAM scan, one per scanline
+    pub oam_scan_lines: Vec<u64>,
+    // The whole address space, read with the banks the memory viewer asked for. Only captured
+    // while the memory viewer is open
+    pub memory: Option<Vec<u8>>,
+    // (active bank, number of banks) of every memory area
+    pub area_banks: EnumMap<MemoryAreaName, (usize, usize)>,
+    // Outermost call first
+    pub call_stack: Vec<CallStackEntry>,
+    // Starts at PC
+    pub disassembly: Vec<DisassembledInstruction>,
+}
+
+/**
+ * A function on the shadow call stack with its addresses already labeled
+ */
+#[derive(Debug, Clone)]
+pub struct CallStackEntry {
+    pub kind: CallKind,
+    pub function: String,
+    pub return_addr: String,
+}
+
+/**
+ * One entry of a BG or window tile map, resolved the same way the pixel fetcher does
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct TileMapEntry {
+    pub map_addr: u16,
+    pub tile_id: u8,
+    pub attributes: TileAttributes,
+    // Where the tile data is with the tile data area currently selected in LCDC
+    pub data_addr: u16,
+}
+
+impl DebugSnapshot {
+    /**
+     * Memory is only copied if view_banks is given
+     */
+    pub fn capture(state: &GBCState, view_banks: Option<&MemoryViewBanks>) -> Self {
+        Self {
+            vram: virtual_memory::borrow_vram(state).to_vec(),
+            bg_palettes: virtual_memory::borrow_palette_mem(state).to_vec(),
+            obj_palettes: virtual_memory::borrow_obj_palette_mem(state).to_vec(),
+            io_registers: virtual_memory::borrow_io_registers(state).to_vec(),
+            interrupt_enable: virtual_memory::read(state, INTERRUPT_ENABLE_ADDR),
+            interrupt_master_enable: interrupt_controller::is_interrupt_master_enabled(state),
+            oam: virtual_memory::borrow_oam(state).to_vec(),
+            oam_scan_lines: render_engine::borrow_oam_scan_lines(state).to_vec(),
+            memory: view_banks.map(|view_banks| capture_memory(state, view_banks)),
+            area_banks: enum_map! { area => virtual_memory::area_banks(state, area) },
+            call_stack: capture_call_stack(state),
+            disassembly: disassembler::disassemble(
+                state,
+                cpu::read_pc(state),
+                DISASSEMBLY_LENGTH,
+            ),
+        }
+    }
+
+    pub fn io_register(&self, addr: u16) -> u8 {
+        self.io_registers[(addr - IO_REGISTERS_ADDR) as usize]
+    }
+
+    pub fn lcd_control(&self) -> LCDControl {
+        LCDControl::from(self.io_register(LCD_CONTROL_REGISTER))
+    }
+
+    /**
+     * (SCX, SCY)
+     */
+    pub fn scroll(&self) -> (u8, u8) {
+        (
+            self.io_register(SCROLL_X_REGISTER),
+            self.io_register(SCROLL_Y_REGISTER),
+        )
+    }
+
+    /**
+     * (WX, WY). WX is 7 more than the screen coordinate the window starts at
+     */
+    pub fn window_position(&self) -> (u8, u8) {
+        (
+            self.io_register(WINDOW_X_REGISTER),
+            self.io_register(WINDOW_Y_REGISTER),
+        )
+    }
+
+    /**
+     * Look up the tile at (x, y) of a 32x32 tile map. Attributes come from VRAM bank 1
+     */
+    pub fn tile_map_entry(&self, map_area: TileMapArea, x: usize, y: usize) -> TileMapEntry {
+        let map_addr = render_engine::get_tile_map_addr(map_area) + (y * TILE_MAP_SIZE + x) as u16;
+        let offset = (map_addr - VRAM_ADDR) as usize;
+        let tile_id = self.vram[offset];
+        let data_area = self.lcd_control().bg_and_window_tile_data_area;
+        TileMapEntry {
+            map_addr,
+            tile_id,
+            attributes: TileAttributes::from(self.vram[VRAM_BANK_SIZE + offset]),
+            data_addr: render_engine::get_tile_data_addr(tile_id, data_area),
+        }
+    }
+
+    /**
+     * Color indices of the tile a map entry points to, flipped the way its attributes say
+     */
+    pub fn tile_map_entry_color_indices(&self, entry: &TileMapEntry) -> [u8; 64] {
+        let bank = entry.attributes.vram_bank.int_value() as usize;
+        let tile_idx = (entry.data_addr - VRAM_ADDR) as usize / BYTES_PER_TILE;
+        let pixels = self.tile_color_indices(bank, tile_idx);
+        core::array::from_fn(|i| {
+            let (row, col) = flip(i / 8, i % 8, 8, &entry.attributes);
+            pixels[row * 8 + col]
+        })
+    }
+
+    pub fn oam_entry(&self, idx: usize) -> OAMEntry {
+        render_engine::read_oam_entry(&self.oam, idx)
+    }
+
+    /**
+     * 8 or 16 depending on the object size selected in LCDC
+     */
+    pub fn obj_height(&self) -> u8 {
+        render_engine::obj_height(&self.lcd_control())
+    }
+
+    /**
+     * Whether the OAM scan picked OAM entry `idx` for scanline `ly`
+     */
+    pub fn obj_selected_on_line(&self, idx: usize, ly: usize) -> bool {
+        self.oam_scan_lines[ly] & (1 << idx) != 0
+    }
+
+    /**
+     * Color indices of an object, row by row. 8x16 objects are 128 pixels long, with the top
+     * half from the even tile and the bottom half from the odd one
+     */
+    pub fn obj_color_indices(&self, entry: &OAMEntry) -> Vec<u8> {
+        let height = self.obj_height() as usize;
+        let bank = entry.attributes.vram_bank.int_value() as usize;
+        let first_tile = match height {
+            16 => entry.tile_id & 0xFE,
+            _ => entry.tile_id,
+        } as usize;
+        let tiles: Vec<[u8; 64]> = (0..height / 8)
+            .map(|i| self.tile_color_indices(bank, first_tile + i))
+            .collect();
+        (0..8 * height)
+            .map(|i| {
+                let (row, col) = flip(i / 8, i % 8, height, &entry.attributes);
+                tiles[row / 8][(row % 8) * 8 + col]
+            })
+            .collect()
+    }
+
+    pub fn tile_addr(tile_idx: usize) -> u16 {
+        VRAM_ADDR + (tile_idx * BYTES_PER_TILE) as u16
+    }
+
+    /**
+     * Color indices of the 8x8 pixels of a tile, row by row
+     */
+    pub fn tile_color_indices(&self, bank: usize, tile_idx: usize) -> [u8; 64] {
+        let tile_start = bank * VRAM_BANK_SIZE + tile_idx * BYTES_PER_TILE;
+        let tile = &self.vram[tile_start..tile_start + BYTES_PER_TILE];
+        core::array::from_fn(|i| {
+            let (row, col) = (i / 8, i % 8);
+            let (low, high) = (tile[row * 2], tile[row * 2 + 1]);
+            (index_bits(high, 7 - col) as u8) << 1 | index_bits(low, 7 - col) as u8
+        })
+    }
+
+    fn palettes(&self, kind: PaletteKind) -> &[u8] {
+        match kind {
+            PaletteKind::BG => &self.bg_palettes,
+            PaletteKind::OBJ => &self.obj_palettes,
+        }
+    }
+
+    pub fn palette_color(&self, kind: PaletteKind, palette: u8, color_idx: u8) -> [u8; 3] {
+        render_engine::palette_color_to_rgb(self.palettes(kind), palette, color_idx)
+    }
+
+    pub fn palette_color_rgb555(&self, kind: PaletteKind, palette: u8, color_idx: u8) -> u16 {
+        render_engine::palette_color_rgb555(self.palettes(kind), palette, color_idx)
+    }
+}
+
+fn capture_memory(state: &GBCState, view_banks: &MemoryViewBanks) -> Vec<u8> {
+    // The frontend may still ask for a bank of a previously loaded cartridge
+    let view_banks: MemoryViewBanks = enum_map! {
+        area => view_banks[area].filter(|&bank| bank < virtual_memory::area_banks(state, area).1)
+    };
+    (0..=u16::MAX)
+        .map(|addr| match virtual_memory::memory_area_at(addr) {
+            Some(area) => match view_banks[area] {
+                Some(bank) => virtual_memory::read_override_bank(state, addr, bank),
+                None => virtual_memory::read(state, addr),
+            },
+            None => 0xFF,
+        })
+        .collect()
+}
+
+fn capture_call_stack(state: &GBCState) -> Vec<CallStackEntry> {
+    cpu::call_stack(state)
+        .iter()
+        .map(|frame| CallStackEntry {
+            kind: frame.kind,
+            function: symbols::describe_in_bank(state, frame.bank, frame.target),
+            return_addr: symbols::describe(state, frame.return_addr),
+        })
+        .collect()
+}
+
+/**
+ * Map a (row, col) pixel position to the one it comes from once the flip attributes are applied
+ */
+fn flip(row: usize, col: usize, height: usize, attributes: &TileAttributes) -> (usize, usize) {
+    let row = match attributes.vertical_flip {
+        true => height - 1 - row,
+        false => row,
+    };
+    let col = match attributes.horizontal_flip {
+        true => 7 - col,
+        false => col,
+    };
+    (row, col)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_tile_rows_from_both_bit_planes() {
+        let mut state = GBCState::with_program(&[]);
+        // First row of tile 1: low plane 0b1010_0000, high plane 0b1100_0000
+        virtual_memory::write(&mut state, DebugSnapshot::tile_addr(1), 0xA0);
+        virtual_memory::write(&mut state, DebugSnapshot::tile_addr(1) + 1, 0xC0);
+
+        let snapshot = DebugSnapshot::capture(&state, None);
+        let pixels = snapshot.tile_color_indices(0, 1);
+        assert_eq!(pixels[..4], [3, 2, 1, 0]);
+        assert_eq!(snapshot.tile_color_indices(1, 1), [0; 64]);
+    }
+
+    #[test]
+    fn resolves_tile_map_entries_with_attributes() {
+        let mut snapshot = DebugSnapshot {
+            vram: vec![0; 2 * VRAM_BANK_SIZE],
+            bg_palettes: vec![0; 64],
+            obj_palettes: vec![0; 64],
+            io_registers: vec![0; 0x80],
+            interrupt_enable: 0,
+            interrupt_master_enable: false,
+            oam: vec![0; 0xA0],
+            oam_scan_lines: vec![0; 144],
+            memory: None,
+            area_banks: EnumMap::default(),
+            call_stack: Vec::new(),
+            disassembly: Vec::new(),
+        };
+        // Signed tile data area at 0x9000, BG map at 0x9800
+        snapshot.io_registers[(LCD_CONTROL_REGISTER - IO_REGISTERS_ADDR) as usize] = 0x81;
+        let map_offset = 0x1800 + 2 * TILE_MAP_SIZE + 1;
+        snapshot.vram[map_offset] = 0xFF;
+        // Both flips, VRAM bank 1, palette 3
+        snapshot.vram[VRAM_BANK_SIZE + map_offset] = 0b0110_1011;
+        // Top left pixel of tile -1 in bank 1
+        snapshot.vram[VRAM_BANK_SIZE + 0x0FF0] = 0x80;
+
+        let entry = snapshot.tile_map_entry(TileMapArea::Map0, 1, 2);
+        assert_eq!(entry.map_addr, 0x9841);
+        assert_eq!(entry.tile_id, 0xFF);
+        assert_eq!(entry.data_addr, 0x8FF0);
+        assert_eq!(entry.attributes.palette, 3);
+
+        let pixels = snapshot.tile_map_entry_color_indices(&entry);
+        assert_eq!(pixels[0], 0);
+        assert_eq!(pixels[63], 1);
+    }
+}
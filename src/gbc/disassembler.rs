@@ -0,0 +1,235 @@
+use super::{symbols, virtual_memory, GBCState};
+
+const R8: [&str; 8] = ["B", "C", "D", "E", "H", "L", "(HL)", "A"];
+const R16: [&str; 4] = ["BC", "DE", "HL", "SP"];
+// PUSH and POP use AF in place of SP
+const R16_STACK: [&str; 4] = ["BC", "DE", "HL", "AF"];
+const R16_MEM: [&str; 4] = ["(BC)", "(DE)", "(HL+)", "(HL-)"];
+const CONDITIONS: [&str; 4] = ["NZ", "Z", "NC", "C"];
+const ALU: [&str; 8] = [
+    "ADD A,", "ADC A,", "SUB", "SBC A,", "AND", "XOR", "OR", "CP",
+];
+const ROTATES: [&str; 8] = ["RLC", "RRC", "RL", "RR", "SLA", "SRA", "SWAP", "SRL"];
+const ACCUMULATOR_OPS: [&str; 8] = ["RLCA", "RRCA", "RLA", "RRA", "DAA", "CPL", "SCF", "CCF"];
+
+/**
+ * An instruction decoded from the mapped memory, with the addresses it uses labeled
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisassembledInstruction {
+    pub addr: u16,
+    // Label at exactly this address, if there is one
+    pub label: Option<String>,
+    pub bytes: Vec<u8>,
+    pub text: String,
+}
+
+/**
+ * Decode `count` instructions starting at addr
+ */
+pub fn disassemble(state: &GBCState, addr: u16, count: usize) -> Vec<DisassembledInstruction> {
+    let mut instructions = Vec::with_capacity(count);
+    let mut addr = addr;
+    for _ in 0..count {
+        let instruction = disassemble_one(state, addr);
+        addr = addr.wrapping_add(instruction.bytes.len() as u16);
+        instructions.push(instruction);
+    }
+    instructions
+}
+
+fn disassemble_one(state: &GBCState, addr: u16) -> DisassembledInstruction {
+    let bytes: [u8; 3] = core::array::from_fn(|i| {
+        let addr = addr.wrapping_add(i as u16);
+        // Echo RAM isn't emulated. Read like the memory viewer shows it
+        match virtual_memory::memory_area_at(addr) {
+            Some(_) => virtual_memory::read(state, addr),
+            None => 0xFF,
+        }
+    });
+    let (text, len) = decode(bytes, addr, |target| symbols::describe(state, target));
+    let bank = symbols::current_bank(state, addr);
+    DisassembledInstruction {
+        addr,
+        label: match state.symbols.nearest(bank, addr) {
+            Some((label, 0)) => Some(label.to_string()),
+            _ => None,
+        },
+        bytes: bytes[..len].to_vec(),
+        text,
+    }
+}
+
+/**
+ * Text and length of the instruction at the start of bytes. Addresses the instruction jumps to or
+ * accesses are formatted with describe
+ */
+fn decode(bytes: [u8; 3], addr: u16, describe: impl Fn(u16) -> String) -> (String, usize) {
+    let [opcode, n8, _] = bytes;
+    let n16 = u16::from_le_bytes([bytes[1], bytes[2]]);
+    // JR is relative to the instruction after it
+    let relative = addr.wrapping_add(2).wrapping_add(n8 as i8 as u16);
+    let (x, y, z) = (
+        opcode >> 6,
+        (opcode >> 3 & 7) as usize,
+        (opcode & 7) as usize,
+    );
+    let (p, q) = (y >> 1, y & 1);
+
+    match (x, z) {
+        (0, 0) => match y {
+            0 => ("NOP".to_string(), 1),
+            1 => (format!("LD ({}), SP", describe(n16)), 3),
+            2 => ("STOP".to_string(), 2),
+            3 => (format!("JR {}", describe(relative)), 2),
+            _ => (
+                format!("JR {}, {}", CONDITIONS[y - 4], describe(relative)),
+                2,
+            ),
+        },
+        (0, 1) => match q {
+            0 => (format!("LD {}, {:#06x}", R16[p], n16), 3),
+            _ => (format!("ADD HL, {}", R16[p]), 1),
+        },
+        (0, 2) => match q {
+            0 => (format!("LD {}, A", R16_MEM[p]), 1),
+            _ => (format!("LD A, {}", R16_MEM[p]), 1),
+        },
+        (0, 3) => match q {
+            0 => (format!("INC {}", R16[p]), 1),
+            _ => (format!("DEC {}", R16[p]), 1),
+        },
+        (0, 4) => (format!("INC {}", R8[y]), 1),
+        (0, 5) => (format!("DEC {}", R8[y]), 1),
+        (0, 6) => (format!("LD {}, {:#04x}", R8[y], n8), 2),
+        (0, _) => (ACCUMULATOR_OPS[y].to_string(), 1),
+        (1, _) if opcode == 0x76 => ("HALT".to_string(), 1),
+        (1, _) => (format!("LD {}, {}", R8[y], R8[z]), 1),
+        (2, _) => (format!("{} {}", ALU[y], R8[z]), 1),
+        (_, 0) => match y {
+            0..=3 => (format!("RET {}", CONDITIONS[y]), 1),
+            4 => (format!("LDH ({}), A", describe(0xFF00 | n8 as u16)), 2),
+            5 => (format!("ADD SP, {}", n8 as i8), 2),
+            6 => (format!("LDH A, ({})", describe(0xFF00 | n8 as u16)), 2),
+            _ => (format!("LD HL, SP{:+}", n8 as i8), 2),
+        },
+        (_, 1) => match (q, p) {
+            (0, _) => (format!("POP {}", R16_STACK[p]), 1),
+            (_, 0) => ("RET".to_string(), 1),
+            (_, 1) => ("RETI".to_string(), 1),
+            (_, 2) => ("JP HL".to_string(), 1),
+            _ => ("LD SP, HL".to_string(), 1),
+        },
+        (_, 2) => match y {
+            0..=3 => (format!("JP {}, {}", CONDITIONS[y], describe(n16)), 3),
+            4 => ("LD (C), A".to_string(), 1),
+            5 => (format!("LD ({}), A", describe(n16)), 3),
+            6 => ("LD A, (C)".to_string(), 1),
+            _ => (format!("LD A, ({})", describe(n16)), 3),
+        },
+        (_, 3) => match y {
+            0 => (format!("JP {}", describe(n16)), 3),
+            1 => (decode_cb(n8), 2),
+            6 => ("DI".to_string(), 1),
+            7 => ("EI".to_string(), 1),
+            _ => illegal(opcode),
+        },
+        (_, 4) => match y {
+            0..=3 => (format!("CALL {}, {}", CONDITIONS[y], describe(n16)), 3),
+            _ => illegal(opcode),
+        },
+        (_, 5) => match (q, p) {
+            (0, _) => (format!("PUSH {}", R16_STACK[p]), 1),
+            (_, 0) => (format!("CALL {}", describe(n16)), 3),
+            _ => illegal(opcode),
+        },
+        (_, 6) => (format!("{} {:#04x}", ALU[y], n8), 2),
+        _ => (format!("RST {}", describe(y as u16 * 8)), 1),
+    }
+}
+
+/**
+ * Instruction following the 0xCB prefix
+ */
+fn decode_cb(opcode: u8) -> String {
+    let (y, z) = ((opcode >> 3 & 7) as usize, (opcode & 7) as usize);
+    match opcode >> 6 {
+        0 => format!("{} {}", ROTATES[y], R8[z]),
+        1 => format!("BIT {}, {}", y, R8[z]),
+        2 => format!("RES {}, {}", y, R8[z]),
+        _ => format!("SET {}, {}", y, R8[z]),
+    }
+}
+
+// Opcodes that lock the CPU up are shown as data
+fn illegal(opcode: u8) -> (String, usize) {
+    (format!("DB {:#04x}", opcode), 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::gbc::SymbolTable;
+
+    fn decode_text(bytes: &[u8]) -> (String, usize) {
+        let mut padded = [0; 3];
+        padded[..bytes.len()].copy_from_slice(bytes);
+        decode(padded, 0x0150, |addr| format!("{:#06x}", addr))
+    }
+
+    #[test]
+    fn decodes_operands_and_lengths() {
+        assert_eq!(decode_text(&[0x00]), ("NOP".to_string(), 1));
+        assert_eq!(
+            decode_text(&[0x21, 0x34, 0x12]),
+            ("LD HL, 0x1234".to_string(), 3)
+        );
+        assert_eq!(decode_text(&[0x32]), ("LD (HL-), A".to_string(), 1));
+        assert_eq!(decode_text(&[0x7E]), ("LD A, (HL)".to_string(), 1));
+        assert_eq!(decode_text(&[0x76]), ("HALT".to_string(), 1));
+        assert_eq!(decode_text(&[0x98]), ("SBC A, B".to_string(), 1));
+        assert_eq!(decode_text(&[0xFE, 0x90]), ("CP 0x90".to_string(), 2));
+        assert_eq!(decode_text(&[0xF8, 0xFE]), ("LD HL, SP-2".to_string(), 2));
+        assert_eq!(decode_text(&[0xF1]), ("POP AF".to_string(), 1));
+        assert_eq!(decode_text(&[0xCB, 0x7C]), ("BIT 7, H".to_string(), 2));
+        assert_eq!(decode_text(&[0xCB, 0x37]), ("SWAP A".to_string(), 2));
+        assert_eq!(decode_text(&[0xD3]), ("DB 0xd3".to_string(), 1));
+        // Relative to the instruction after the JR
+        assert_eq!(decode_text(&[0x20, 0xFC]), ("JR NZ, 0x014e".to_string(), 2));
+        assert_eq!(
+            decode_text(&[0xE0, 0x40]),
+            ("LDH (0xff40), A".to_string(), 2)
+        );
+        assert_eq!(decode_text(&[0xFF]), ("RST 0x0038".to_string(), 1));
+    }
+
+    #[test]
+    fn labels_instructions_and_targets() {
+        let mut state = GBCState::with_program(&[
+            0xCD, 0x50, 0x01, // CALL Main
+            0xEA, 0x00, 0xC0, // LD (wPlayerX), A
+        ]);
+        state.symbols = Arc::new(
+            SymbolTable::parse("00:0100 Start\n00:0150 Main\n00:c000 wPlayerX\n").unwrap(),
+        );
+
+        let instructions = disassemble(&state, 0x0100, 3);
+        assert_eq!(instructions[0].label.as_deref(), Some("Start"));
+        assert_eq!(instructions[0].bytes, [0xCD, 0x50, 0x01]);
+        assert_eq!(instructions[0].text, "CALL 0x0150 <Main>");
+        assert_eq!(instructions[1].addr, 0x0103);
+        assert_eq!(instructions[1].label, None);
+        assert_eq!(instructions[1].text, "LD (0xc000 <wPlayerX>), A");
+        assert_eq!(instructions[2].text, "NOP");
+    }
+
+    #[test]
+    fn shows_echo_ram_as_unmapped() {
+        let state = GBCState::with_program(&[]);
+        let instructions = disassemble(&state, 0xDFFE, 4);
+        assert_eq!(instructions[2].addr, 0xE000);
+        assert_eq!(instructions[2].bytes, [0xFF]);
+    }
+}
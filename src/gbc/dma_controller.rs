@@ -1,13 +1,15 @@
 use std::{
+    hash::{Hash, Hasher},
     iter::{Peekable, StepBy, Zip},
     ops::Range,
 };
 
-use tracing::{debug_span, trace, error};
+use tracing::error;
 
-use crate::util::{combine_high_low, index_bits};
+use crate::util::{combine_high_low, hot_debug_span, hot_trace, index_bits};
 
 use super::{
+    scheduler::{self, Event},
     virtual_memory::{self, OAM_ADDR, VRAM_DMA_REGISTER},
     GBCState,
 };
@@ -15,6 +17,7 @@ use super::{
 const OAM_TRANSFER_BYTES: usize = 160;
 const HDMA_REG_ADDR: u16 = 0xFF51;
 
+#[derive(Clone)]
 struct DMATransfer {
     // Iterator for getting the src and dest address for next write
     iterator: Peekable<StepBy<Zip<Range<u16>, Range<u16>>>>,
@@ -35,6 +38,17 @@ impl DMATransfer {
     }
 }
 
+// Iterators don't implement Hash. The next addresses and the number of writes left are enough
+// since the step only depends on the kind of transfer
+impl Hash for DMATransfer {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut iterator = self.iterator.clone();
+        iterator.len().hash(state);
+        iterator.peek().hash(state);
+    }
+}
+
+#[derive(Clone, Hash)]
 pub struct DMAController {
     oam_transfer: DMATransfer,
     hblank_transfer: DMATransfer,
@@ -49,10 +63,6 @@ impl DMAController {
     }
 }
 
-pub fn tick(state: &mut GBCState) {
-    process_oam_transfer(state);
-}
-
 /**
  * Trigger oam transfer when register 0xFF46 is written to
  */
@@ -65,7 +75,11 @@ pub fn trigger_oam_transfer(state: &mut GBCState, val: u8) {
         return; // Invalid start address
     }
 
+    let already_running = state.dma_ctrl.oam_transfer.iterator.peek().is_some();
     state.dma_ctrl.oam_transfer = DMATransfer::new(src, OAM_ADDR, OAM_TRANSFER_BYTES, 1);
+    if !already_running {
+        scheduler::schedule_in(state, Event::OAMDMATransfer, 1);
+    }
 }
 
 /**
@@ -110,21 +124,24 @@ pub fn trigger_vram_transfer(state: &mut GBCState, val: u8) {
 /**
  * Write one byte per machine cycle to OAM
  */
-fn process_oam_transfer(state: &mut GBCState) {
+pub fn process_oam_transfer(state: &mut GBCState) {
     let next = state.dma_ctrl.oam_transfer.iterator.next();
 
     if let Some((src, dest)) = next {
-        let span = debug_span!(
+        let span = hot_debug_span!(
             "OAM DMA Transfer",
             src = format!("{:#06x}", src),
             dest = format!("{:#06x}", dest)
-        )
-        .entered();
-        trace!("Processing DMA transfer");
+        );
+        hot_trace!("Processing DMA transfer");
         let val = virtual_memory::read(state, src);
         virtual_memory::write(state, dest, val);
         span.exit();
     }
+
+    if state.dma_ctrl.oam_transfer.iterator.peek().is_some() {
+        scheduler::schedule_in(state, Event::OAMDMATransfer, 1);
+    }
 }
 
 /**
@@ -137,13 +154,12 @@ pub fn process_hblank_transfer(state: &mut GBCState) {
     }
 
     let (src, dest) = next.unwrap();
-    let span = debug_span!(
+    let span = hot_debug_span!(
         "HBlank DMA Transfer",
         src = format!("{:#06x}", src),
         dest = format!("{:#06x}", dest)
-    )
-    .entered();
-    trace!("Processing DMA transfer");
+    );
+    hot_trace!("Processing DMA transfer");
 
     let vals = virtual_memory::read_bytes(state, src, 16).into_owned();
     virtual_memory::write_bytes(state, dest, &vals);
@@ -169,14 +185,13 @@ fn process_general_purpose_transfer(
     dest_addr: u16,
     length_bytes: usize,
 ) {
-    let span = debug_span!(
+    let span = hot_debug_span!(
         "General Purpose DMA Transfer",
         src = format!("{:#06x}", src_addr),
         dest = format!("{:#06x}", dest_addr),
         length_bytes = length_bytes,
-    )
-    .entered();
-    trace!("Processing DMA transfer");
+    );
+    hot_trace!("Processing DMA transfer");
     
     let vals = virtual_memory::read_bytes(state, src_addr, length_bytes).into_owned();
     virtual_memory::write_bytes(state, dest_addr, &vals);
@@ -0,0 +1,87 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::MACHINE_CYCLES_PER_FRAME;
+
+pub(super) const MACHINE_CYCLES_PER_SECOND: f32 = 1_048_576.0;
+pub const NATIVE_FRAMES_PER_SECOND: f32 =
+    MACHINE_CYCLES_PER_SECOND / MACHINE_CYCLES_PER_FRAME as f32;
+
+// How often the achieved frame rate is measured
+const MEASUREMENT_PERIOD: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EmulationSpeed {
+    // Multiple of native speed. 1.0 runs at ~59.7 FPS
+    Multiplier(f32),
+    // Run as fast as the host allows
+    Uncapped,
+}
+
+/**
+ * Keeps emulation running at the selected speed by sleeping between frames. This is host timing
+ * and lives outside of GBCState.
+ */
+pub struct FramePacer {
+    speed: EmulationSpeed,
+    // When the next frame is allowed to start
+    next_frame_deadline: Instant,
+    measurement_start: Instant,
+    frames_since_measurement: u32,
+}
+
+impl FramePacer {
+    pub fn new(speed: EmulationSpeed) -> Self {
+        let now = Instant::now();
+        Self {
+            speed,
+            next_frame_deadline: now,
+            measurement_start: now,
+            frames_since_measurement: 0,
+        }
+    }
+
+    pub fn set_speed(&mut self, speed: EmulationSpeed) {
+        self.speed = speed;
+        self.reset();
+    }
+
+    /**
+     * Forget about past frames, e.g. after the emulator was paused
+     */
+    pub fn reset(&mut self) {
+        *self = Self::new(self.speed);
+    }
+
+    /**
+     * Called at the end of every frame. Sleeps until the next frame is due and returns the
+     * achieved frame rate once per measurement period.
+     */
+    pub fn end_frame(&mut self) -> Option<f32> {
+        self.frames_since_measurement += 1;
+
+        if let EmulationSpeed::Multiplier(multiplier) = self.speed {
+            let frame_period =
+                Duration::from_secs_f32(1.0 / (NATIVE_FRAMES_PER_SECOND * multiplier));
+            self.next_frame_deadline += frame_period;
+            let now = Instant::now();
+            if self.next_frame_deadline > now {
+                thread::sleep(self.next_frame_deadline - now);
+            } else if now - self.next_frame_deadline > frame_period {
+                // Fell too far behind. Don't run fast afterwards to catch up
+                self.next_frame_deadline = now;
+            }
+        }
+
+        let elapsed = self.measurement_start.elapsed();
+        if elapsed < MEASUREMENT_PERIOD {
+            return None;
+        }
+        let fps = self.frames_since_measurement as f32 / elapsed.as_secs_f32();
+        self.measurement_start = Instant::now();
+        self.frames_since_measurement = 0;
+        Some(fps)
+    }
+}
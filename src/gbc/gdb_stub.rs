@@ -0,0 +1,716 @@
+use std::{
+    collections::HashSet,
+    io::{self, BufReader, ErrorKind, Read, Write},
+    mem,
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+};
+
+use color_eyre::eyre::Result;
+use tracing::{debug, info};
+
+use super::cpu::{self, RegisterPair};
+use super::virtual_memory::{self, WatchHit, WatchKind, Watchpoint};
+use super::{memory_area_at, step_instruction, symbols, GBCConfig, GBCState};
+
+// Port IANA registered for GDB remote debugging
+pub const DEFAULT_GDB_PORT: u16 = 2159;
+const MAX_PACKET_SIZE: usize = 0x1000;
+// Check whether the debugger wants to interrupt after this many steps while running
+const INTERRUPT_POLL_INTERVAL: u32 = 10_000;
+// Ctrl-C, sent outside of a packet
+const INTERRUPT_BYTE: u8 = 0x03;
+// Echo RAM mirrors work RAM this far below it
+const ECHO_RAM_OFFSET: u16 = 0x2000;
+
+// Signals reported in stop replies
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+
+// AF, BC, DE, HL, SP and PC, each 16 bits little endian. Same order as target.xml
+const NUM_REGISTERS: usize = 6;
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.gnu.gdb.sm83.core">
+    <reg name="af" bitsize="16" type="uint16" regnum="0"/>
+    <reg name="bc" bitsize="16" type="uint16"/>
+    <reg name="de" bitsize="16" type="uint16"/>
+    <reg name="hl" bitsize="16" type="uint16"/>
+    <reg name="sp" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+/**
+ * Why execution stopped. Reported to the debugger after resuming and when it asks with '?'
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StopReason {
+    // A single step finished or a breakpoint was reached
+    Trap,
+    Watchpoint(WatchHit),
+    Interrupted,
+    // An illegal opcode was executed
+    LockedUp,
+}
+
+impl StopReason {
+    fn reply(&self) -> String {
+        match self {
+            StopReason::Trap => format!("S{:02x}", SIGTRAP),
+            StopReason::Watchpoint(hit) => {
+                let name = match hit.kind {
+                    WatchKind::Write => "watch",
+                    WatchKind::Read => "rwatch",
+                    WatchKind::Access => "awatch",
+                };
+                format!("T{:02x}{}:{:04x};", SIGTRAP, name, hit.addr)
+            }
+            StopReason::Interrupted => format!("S{:02x}", SIGINT),
+            StopReason::LockedUp => format!("S{:02x}", SIGILL),
+        }
+    }
+}
+
+/**
+ * What to do after handling a packet
+ */
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    Reply(String),
+    Resume { single_step: bool },
+    // Stop serving. Detach is acknowledged, kill isn't
+    Detach,
+    Kill,
+}
+
+/**
+ * Something received from the debugger
+ */
+#[derive(Debug, PartialEq, Eq)]
+enum Input {
+    Packet(Vec<u8>),
+    // The packet was corrupted and has to be sent again
+    BadChecksum,
+    Interrupt,
+}
+
+#[derive(Default)]
+enum ParseState {
+    // Between packets. Acks from the debugger are skipped here
+    #[default]
+    Idle,
+    Data,
+    Checksum,
+}
+
+/**
+ * Splits the bytes sent by the debugger into packets of the form $data#checksum
+ */
+#[derive(Default)]
+struct PacketParser {
+    state: ParseState,
+    data: Vec<u8>,
+    checksum_digits: Vec<u8>,
+}
+
+impl PacketParser {
+    fn push(&mut self, byte: u8) -> Option<Input> {
+        match self.state {
+            ParseState::Idle => match byte {
+                b'$' => {
+                    self.data.clear();
+                    self.state = ParseState::Data;
+                    None
+                }
+                INTERRUPT_BYTE => Some(Input::Interrupt),
+                _ => None,
+            },
+            ParseState::Data => {
+                match byte {
+                    b'#' => {
+                        self.checksum_digits.clear();
+                        self.state = ParseState::Checksum;
+                    }
+                    _ => self.data.push(byte),
+                }
+                None
+            }
+            ParseState::Checksum => {
+                self.checksum_digits.push(byte);
+                if self.checksum_digits.len() < 2 {
+                    return None;
+                }
+                self.state = ParseState::Idle;
+                let expected = std::str::from_utf8(&self.checksum_digits)
+                    .ok()
+                    .and_then(|digits| u8::from_str_radix(digits, 16).ok());
+                match expected == Some(checksum(&self.data)) {
+                    true => Some(Input::Packet(mem::take(&mut self.data))),
+                    false => Some(Input::BadChecksum),
+                }
+            }
+        }
+    }
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &byte| sum.wrapping_add(byte))
+}
+
+/**
+ * Connection to the debugger. Replies are never resent, so acks from the debugger are ignored
+ */
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    parser: PacketParser,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> Result<Self> {
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            parser: PacketParser::default(),
+        })
+    }
+
+    /**
+     * None once the debugger has disconnected
+     */
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        match self.reader.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    /**
+     * Block until the next packet arrives. Returns None once the debugger has disconnected
+     */
+    fn read_packet(&mut self) -> Result<Option<String>> {
+        while let Some(byte) = self.read_byte()? {
+            match self.parser.push(byte) {
+                Some(Input::Packet(data)) => {
+                    self.writer.write_all(b"+")?;
+                    let packet = String::from_utf8_lossy(&data).into_owned();
+                    debug!("<- {}", packet);
+                    return Ok(Some(packet));
+                }
+                Some(Input::BadChecksum) => self.writer.write_all(b"-")?,
+                // Already stopped
+                Some(Input::Interrupt) | None => {}
+            }
+        }
+        Ok(None)
+    }
+
+    fn send_packet(&mut self, data: &str) -> Result<()> {
+        debug!("-> {}", data);
+        write!(self.writer, "${}#{:02x}", data, checksum(data.as_bytes()))?;
+        Ok(())
+    }
+
+    /**
+     * Check for an interrupt without blocking. Disconnecting also counts as one so the next
+     * read_packet notices it
+     */
+    fn poll_interrupt(&mut self) -> Result<bool> {
+        self.writer.set_nonblocking(true)?;
+        let interrupted = loop {
+            match self.read_byte() {
+                Ok(Some(byte)) => {
+                    if self.parser.push(byte) == Some(Input::Interrupt) {
+                        break Ok(true);
+                    }
+                }
+                Ok(None) => break Ok(true),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break Ok(false),
+                Err(err) => break Err(err),
+            }
+        };
+        self.writer.set_nonblocking(false)?;
+        Ok(interrupted?)
+    }
+}
+
+/**
+ * Lets a debugger like GDB control the emulator over the remote serial protocol. Runs without a
+ * frontend, so frames are rendered but never shown
+ */
+pub struct GDBStub {
+    state: GBCState,
+    // Software and hardware breakpoints are the same thing here. Both are checked against the PC
+    // before each instruction. Keyed by (address, bank). Breakpoints set on a label only stop in
+    // the bank of the label
+    breakpoints: HashSet<(u16, Option<u16>)>,
+    last_stop: StopReason,
+}
+
+impl GDBStub {
+    pub fn new(rom_data: Vec<u8>, config: &GBCConfig) -> Result<Self> {
+        Ok(Self {
+            state: GBCState::new_headless(rom_data, config)?,
+            breakpoints: HashSet::new(),
+            last_stop: StopReason::Trap,
+        })
+    }
+
+    /**
+     * Wait for a debugger to connect and serve it until it detaches or disconnects. The emulator
+     * starts stopped at the first instruction
+     */
+    pub fn serve(&mut self, addr: impl ToSocketAddrs) -> Result<()> {
+        let listener = TcpListener::bind(addr)?;
+        info!("Waiting for a debugger on {}", listener.local_addr()?);
+        let (stream, peer) = listener.accept()?;
+        info!("Debugger connected from {}", peer);
+
+        let mut conn = Connection::new(stream)?;
+        while let Some(packet) = conn.read_packet()? {
+            match self.handle_packet(&packet) {
+                Action::Reply(reply) => conn.send_packet(&reply)?,
+                Action::Resume { single_step } => {
+                    self.last_stop = self.resume(single_step, || conn.poll_interrupt())?;
+                    self.log_stop();
+                    conn.send_packet(&self.last_stop.reply())?;
+                }
+                Action::Detach => {
+                    conn.send_packet("OK")?;
+                    break;
+                }
+                Action::Kill => break,
+            }
+        }
+        info!("Debugger disconnected");
+        Ok(())
+    }
+
+    /**
+     * Run until a breakpoint or watchpoint is hit, the CPU locks up or interrupted returns true.
+     * Stops after the first instruction if single_step is set
+     */
+    fn resume(
+        &mut self,
+        single_step: bool,
+        mut interrupted: impl FnMut() -> Result<bool>,
+    ) -> Result<StopReason> {
+        let mut steps: u32 = 0;
+        loop {
+            if cpu::is_locked(&self.state) {
+                return Ok(StopReason::LockedUp);
+            }
+            if step_instruction(&mut self.state) {
+                if let Some(hit) = virtual_memory::take_watch_hit(&mut self.state) {
+                    return Ok(StopReason::Watchpoint(hit));
+                }
+                if single_step || self.at_breakpoint() {
+                    return Ok(StopReason::Trap);
+                }
+            }
+            steps = steps.wrapping_add(1);
+            if steps.is_multiple_of(INTERRUPT_POLL_INTERVAL) && interrupted()? {
+                return Ok(StopReason::Interrupted);
+            }
+        }
+    }
+
+    fn at_breakpoint(&self) -> bool {
+        let pc = cpu::read_pc(&self.state);
+        let bank = symbols::current_bank(&self.state, pc);
+        self.breakpoints.contains(&(pc, None)) || self.breakpoints.contains(&(pc, Some(bank)))
+    }
+
+    fn log_stop(&self) {
+        let pc = symbols::describe(&self.state, cpu::read_pc(&self.state));
+        match self.last_stop {
+            StopReason::Watchpoint(hit) => info!(
+                "Watchpoint on {} hit at PC {}",
+                symbols::describe(&self.state, hit.addr),
+                pc
+            ),
+            StopReason::Trap => debug!("Stopped at PC {}", pc),
+            reason => info!("Stopped at PC {}: {:?}", pc, reason),
+        }
+    }
+
+    fn handle_packet(&mut self, packet: &str) -> Action {
+        let Some(command) = packet.chars().next() else {
+            return Action::Reply(String::new());
+        };
+        let args = &packet[command.len_utf8()..];
+        let reply = match command {
+            '?' => Some(self.last_stop.reply()),
+            'g' => Some(
+                (0..NUM_REGISTERS)
+                    .filter_map(|idx| self.read_register(idx))
+                    .map(|val| hex_bytes(&val.to_le_bytes()))
+                    .collect(),
+            ),
+            'G' => self.write_registers(args),
+            'p' => usize::from_str_radix(args, 16)
+                .ok()
+                .and_then(|idx| self.read_register(idx))
+                .map(|val| hex_bytes(&val.to_le_bytes())),
+            'P' => self.write_register_packet(args),
+            'm' => self.read_memory(args),
+            'M' => self.write_memory(args),
+            'c' | 's' => {
+                // Optionally resume somewhere else
+                if let Ok(addr) = u16::from_str_radix(args, 16) {
+                    cpu::write_pc(&mut self.state, addr);
+                }
+                return Action::Resume {
+                    single_step: command == 's',
+                };
+            }
+            'Z' | 'z' => return self.update_breakpoint(args, command == 'Z'),
+            // There is only one thread
+            'H' => Some("OK".to_string()),
+            'q' => match args.strip_prefix("Rcmd,") {
+                Some(command) => self.monitor_command(command),
+                None => return Action::Reply(query(args)),
+            },
+            'D' => return Action::Detach,
+            'k' => return Action::Kill,
+            // An empty reply means the packet isn't supported
+            _ => return Action::Reply(String::new()),
+        };
+        Action::Reply(reply.unwrap_or_else(|| "E01".to_string()))
+    }
+
+    fn read_register(&self, idx: usize) -> Option<u16> {
+        let state = &self.state;
+        Some(match idx {
+            0 => cpu::read_register_pair(state, RegisterPair::AF),
+            1 => cpu::read_register_pair(state, RegisterPair::BC),
+            2 => cpu::read_register_pair(state, RegisterPair::DE),
+            3 => cpu::read_register_pair(state, RegisterPair::HL),
+            4 => cpu::read_sp(state),
+            5 => cpu::read_pc(state),
+            _ => return None,
+        })
+    }
+
+    fn write_register(&mut self, idx: usize, val: u16) -> Option<()> {
+        let state = &mut self.state;
+        match idx {
+            0 => cpu::write_register_pair(state, RegisterPair::AF, val),
+            1 => cpu::write_register_pair(state, RegisterPair::BC, val),
+            2 => cpu::write_register_pair(state, RegisterPair::DE, val),
+            3 => cpu::write_register_pair(state, RegisterPair::HL, val),
+            4 => cpu::write_sp(state, val),
+            5 => cpu::write_pc(state, val),
+            _ => return None,
+        }
+        Some(())
+    }
+
+    fn write_registers(&mut self, args: &str) -> Option<String> {
+        let bytes = parse_hex_bytes(args)?;
+        for (idx, val) in bytes.chunks_exact(2).take(NUM_REGISTERS).enumerate() {
+            self.write_register(idx, u16::from_le_bytes([val[0], val[1]]))?;
+        }
+        Some("OK".to_string())
+    }
+
+    /**
+     * P<register>=<value>
+     */
+    fn write_register_packet(&mut self, args: &str) -> Option<String> {
+        let (idx, val) = args.split_once('=')?;
+        let idx = usize::from_str_radix(idx, 16).ok()?;
+        let bytes: [u8; 2] = parse_hex_bytes(val)?.try_into().ok()?;
+        self.write_register(idx, u16::from_le_bytes(bytes))?;
+        Some("OK".to_string())
+    }
+
+    /**
+     * m<addr>,<length>
+     */
+    fn read_memory(&self, args: &str) -> Option<String> {
+        let (addr, len) = parse_addr_len(args)?;
+        let bytes: Vec<u8> = (0..len)
+            .map(|offset| {
+                let addr = addr.wrapping_add(offset as u16);
+                virtual_memory::read(&self.state, mirror_echo_ram(addr))
+            })
+            .collect();
+        Some(hex_bytes(&bytes))
+    }
+
+    /**
+     * M<addr>,<length>:<bytes>
+     */
+    fn write_memory(&mut self, args: &str) -> Option<String> {
+        let (addr_len, data) = args.split_once(':')?;
+        let (addr, len) = parse_addr_len(addr_len)?;
+        let bytes = parse_hex_bytes(data)?;
+        if bytes.len() != len {
+            return None;
+        }
+        for (offset, val) in bytes.into_iter().enumerate() {
+            let addr = addr.wrapping_add(offset as u16);
+            virtual_memory::write_without_triggers(&mut self.state, mirror_echo_ram(addr), val);
+        }
+        Some("OK".to_string())
+    }
+
+    /**
+     * Z<type>,<addr>,<kind> inserts and z<type>,<addr>,<kind> removes. For watchpoints kind is the
+     * number of bytes watched
+     */
+    fn update_breakpoint(&mut self, args: &str, insert: bool) -> Action {
+        let mut parts = args.split(',');
+        let (Some(kind), Some(addr), Some(len)) = (parts.next(), parts.next(), parts.next()) else {
+            return Action::Reply("E01".to_string());
+        };
+        let (Ok(addr), Ok(len)) = (u16::from_str_radix(addr, 16), u16::from_str_radix(len, 16))
+        else {
+            return Action::Reply("E01".to_string());
+        };
+
+        let watch_kind = match kind {
+            // Software and hardware breakpoints
+            "0" | "1" => {
+                match insert {
+                    true => self.breakpoints.insert((addr, None)),
+                    false => self.breakpoints.remove(&(addr, None)),
+                };
+                return Action::Reply("OK".to_string());
+            }
+            "2" => WatchKind::Write,
+            "3" => WatchKind::Read,
+            "4" => WatchKind::Access,
+            _ => return Action::Reply(String::new()),
+        };
+        let watchpoint = Watchpoint {
+            addrs: addr..=addr.saturating_add(len.max(1) - 1),
+            kind: watch_kind,
+        };
+        match insert {
+            true => virtual_memory::add_watchpoint(&mut self.state, watchpoint),
+            false => {
+                virtual_memory::remove_watchpoint(&mut self.state, &watchpoint);
+            }
+        }
+        Action::Reply("OK".to_string())
+    }
+
+    /**
+     * Run a `monitor` command (qRcmd packet). The command and the output are hex encoded
+     */
+    fn monitor_command(&mut self, hex_command: &str) -> Option<String> {
+        let command = String::from_utf8(parse_hex_bytes(hex_command)?).ok()?;
+        let mut words = command.split_whitespace();
+        let output = match (words.next(), words.next(), words.next()) {
+            (Some(name @ ("break" | "delete")), Some(location), None) => {
+                match self.parse_location(location) {
+                    Some(breakpoint) => {
+                        match name == "break" {
+                            true => self.breakpoints.insert(breakpoint),
+                            false => self.breakpoints.remove(&breakpoint),
+                        };
+                        format!("{} {}", name, symbols::describe(&self.state, breakpoint.0))
+                    }
+                    None => format!("No symbol {}", location),
+                }
+            }
+            (Some(name @ ("watch" | "rwatch" | "awatch")), Some(location), len) => {
+                let kind = match name {
+                    "watch" => WatchKind::Write,
+                    "rwatch" => WatchKind::Read,
+                    _ => WatchKind::Access,
+                };
+                let len = match len {
+                    Some(len) => len.parse().ok()?,
+                    None => 1,
+                };
+                match self.parse_location(location) {
+                    Some((addr, _)) => {
+                        virtual_memory::add_watchpoint(
+                            &mut self.state,
+                            Watchpoint {
+                                addrs: addr..=addr.saturating_add(u16::max(len, 1) - 1),
+                                kind,
+                            },
+                        );
+                        format!("{} {}", name, symbols::describe(&self.state, addr))
+                    }
+                    None => format!("No symbol {}", location),
+                }
+            }
+            (Some("symbol"), Some(location), None) => match self.parse_location(location) {
+                Some((addr, _)) => symbols::describe(&self.state, addr),
+                None => format!("No symbol {}", location),
+            },
+            _ => "Commands: break <location>, delete <location>, watch|rwatch|awatch <location> \
+                [length], symbol <location>. A location is a label or an address like 0xc000"
+                .to_string(),
+        };
+        Some(hex_bytes(format!("{}\n", output).as_bytes()))
+    }
+
+    /**
+     * A label or an address starting with 0x or $. Labels come with their bank
+     */
+    fn parse_location(&self, text: &str) -> Option<(u16, Option<u16>)> {
+        let hex_addr = text.strip_prefix("0x").or_else(|| text.strip_prefix('$'));
+        if let Some(addr) = hex_addr.and_then(|hex| u16::from_str_radix(hex, 16).ok()) {
+            return Some((addr, None));
+        }
+        let (bank, addr) = self.state.symbols.resolve(text)?;
+        Some((addr, Some(bank)))
+    }
+}
+
+/**
+ * Answer a general query (q packet)
+ */
+fn query(args: &str) -> String {
+    if args.starts_with("Supported") {
+        return format!("PacketSize={:x};qXfer:features:read+", MAX_PACKET_SIZE);
+    }
+    if let Some(range) = args.strip_prefix("Xfer:features:read:target.xml:") {
+        return match parse_addr_len(range) {
+            Some((offset, len)) => xfer_chunk(TARGET_XML, offset as usize, len),
+            None => "E01".to_string(),
+        };
+    }
+    match args {
+        // Attached to an existing process, so it is left running when the debugger quits
+        "Attached" => "1",
+        "C" => "QC1",
+        "fThreadInfo" => "m1",
+        "sThreadInfo" => "l",
+        _ => "",
+    }
+    .to_string()
+}
+
+/**
+ * Part of a document sent through qXfer. 'l' marks the last chunk
+ */
+fn xfer_chunk(document: &str, offset: usize, len: usize) -> String {
+    let rest = document.get(offset..).unwrap_or_default();
+    match rest.len() > len {
+        true => format!("m{}", &rest[..len]),
+        false => format!("l{}", rest),
+    }
+}
+
+/**
+ * Echo RAM isn't emulated, so read and write the work RAM it mirrors instead
+ */
+fn mirror_echo_ram(addr: u16) -> u16 {
+    match memory_area_at(addr) {
+        Some(_) => addr,
+        None => addr - ECHO_RAM_OFFSET,
+    }
+}
+
+/**
+ * <addr>,<length> in hex
+ */
+fn parse_addr_len(args: &str) -> Option<(u16, usize)> {
+    let (addr, len) = args.split_once(',')?;
+    let addr = u16::from_str_radix(addr, 16).ok()?;
+    let len = usize::from_str_radix(len, 16).ok()?;
+    Some((addr, len.min(MAX_PACKET_SIZE / 2)))
+}
+
+fn parse_hex_bytes(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::gbc::SymbolTable;
+
+    fn parse_all(parser: &mut PacketParser, bytes: &[u8]) -> Vec<Input> {
+        bytes.iter().filter_map(|&byte| parser.push(byte)).collect()
+    }
+
+    #[test]
+    fn splits_packets_and_checks_checksums() {
+        let mut parser = PacketParser::default();
+        assert_eq!(
+            parse_all(&mut parser, b"+$g#67+$m0,2#fb\x03$g#00"),
+            vec![
+                Input::Packet(b"g".to_vec()),
+                Input::Packet(b"m0,2".to_vec()),
+                Input::Interrupt,
+                Input::BadChecksum,
+            ]
+        );
+    }
+
+    #[test]
+    fn stops_at_watchpoints_and_breakpoints() {
+        // LD A, 0x42; LD (0xC000), A; NOP; NOP
+        let program = [0x3E, 0x42, 0xEA, 0x00, 0xC0, 0x00, 0x00];
+        let mut stub = GDBStub {
+            state: GBCState::with_program(&program),
+            breakpoints: HashSet::new(),
+            last_stop: StopReason::Trap,
+        };
+        let ok = Action::Reply("OK".to_string());
+        assert_eq!(stub.handle_packet("Z2,c000,1"), ok);
+        assert_eq!(stub.handle_packet("Z0,106,1"), ok);
+
+        let stop = stub.resume(false, || Ok(false)).unwrap();
+        assert_eq!(stop.reply(), "T05watch:c000;");
+        assert_eq!(cpu::read_pc(&stub.state), 0x0105);
+        assert_eq!(
+            stub.handle_packet("mc000,1"),
+            Action::Reply("42".to_string())
+        );
+
+        assert_eq!(stub.resume(false, || Ok(false)).unwrap(), StopReason::Trap);
+        assert_eq!(cpu::read_pc(&stub.state), 0x0106);
+
+        assert_eq!(
+            stub.handle_packet("s"),
+            Action::Resume { single_step: true }
+        );
+        stub.resume(true, || Ok(false)).unwrap();
+        assert_eq!(cpu::read_pc(&stub.state), 0x0107);
+    }
+
+    #[test]
+    fn sets_breakpoints_by_label() {
+        // NOP; NOP; NOP
+        let mut stub = GDBStub {
+            state: GBCState::with_program(&[0x00, 0x00, 0x00]),
+            breakpoints: HashSet::new(),
+            last_stop: StopReason::Trap,
+        };
+        stub.state.symbols = Arc::new(SymbolTable::parse("00:0102 Main.done").unwrap());
+
+        let command = hex_bytes(b"break Main.done");
+        assert_eq!(
+            stub.handle_packet(&format!("qRcmd,{}", command)),
+            Action::Reply(hex_bytes(b"break 0x0102 <Main.done>\n"))
+        );
+        assert_eq!(stub.resume(false, || Ok(false)).unwrap(), StopReason::Trap);
+        assert_eq!(cpu::read_pc(&stub.state), 0x0102);
+    }
+}
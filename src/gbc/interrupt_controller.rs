@@ -5,7 +5,6 @@ use int_enum::IntEnum;
 use crate::util::{index_bits, reset_bit, set_bit};
 
 use super::{
-    delay_action,
     lcd_controller::{self, PPUMode},
     virtual_memory, GBCState,
 };
@@ -13,8 +12,11 @@ use super::{
 pub const INTERRUPT_REQUEST_ADDR: u16 = 0xFF0F;
 pub const INTERRUPT_ENABLE_ADDR: u16 = 0xFFFF;
 
+#[derive(Clone, Hash)]
 pub struct InterruptController {
     interrupt_master_enable: bool,
+    // Set by EI. IME only becomes enabled once the instruction after EI has finished
+    interrupt_enable_pending: bool,
     // Keep track of stat interrupt signal so we can
     // trigger interrupt only on rising edge
     stat_interrupt_line: bool,
@@ -24,6 +26,7 @@ impl InterruptController {
     pub fn new() -> Self {
         Self {
             interrupt_master_enable: false,
+            interrupt_enable_pending: false,
             stat_interrupt_line: false,
         }
     }
@@ -58,7 +61,11 @@ impl InterruptFlag {
     }
 }
 
-pub fn tick(state: &mut GBCState) {
+/**
+ * Recompute the STAT interrupt line. Called whenever the PPU mode, the LY=LYC flag or the STAT
+ * interrupt sources change.
+ */
+pub fn update_stat_interrupt_line(state: &mut GBCState) {
     let old_stat_interrupt_line = state.intr_ctrl.stat_interrupt_line;
     let stat = lcd_controller::get_lcd_status_register(state);
     state.intr_ctrl.stat_interrupt_line = (stat.ppu_mode == PPUMode::HBlank
@@ -73,20 +80,54 @@ pub fn tick(state: &mut GBCState) {
     }
 }
 
+/**
+ * Enable interrupts after the next instruction has executed (EI)
+ */
 pub fn enable_interrupts(state: &mut GBCState) {
-    delay_action::schedule(
-        state,
-        |state| state.intr_ctrl.interrupt_master_enable = true,
-        1,
-    );
+    state.intr_ctrl.interrupt_enable_pending = true;
+}
+
+/**
+ * Enable interrupts without any delay (RETI)
+ */
+pub fn enable_interrupts_immediately(state: &mut GBCState) {
+    state.intr_ctrl.interrupt_enable_pending = false;
+    state.intr_ctrl.interrupt_master_enable = true;
 }
 
+/**
+ * Disable interrupts without any delay (DI and interrupt dispatch). Also cancels a pending EI.
+ */
 pub fn disable_interrupts(state: &mut GBCState) {
-    delay_action::schedule(
-        state,
-        |state| state.intr_ctrl.interrupt_master_enable = false,
-        1,
-    );
+    state.intr_ctrl.interrupt_enable_pending = false;
+    state.intr_ctrl.interrupt_master_enable = false;
+}
+
+pub fn is_interrupt_enable_pending(state: &GBCState) -> bool {
+    state.intr_ctrl.interrupt_enable_pending
+}
+
+/**
+ * Called by the CPU once the instruction following EI has finished executing
+ */
+pub fn apply_pending_interrupt_enable(state: &mut GBCState) {
+    if state.intr_ctrl.interrupt_enable_pending {
+        enable_interrupts_immediately(state);
+    }
+}
+
+pub fn is_interrupt_master_enabled(state: &GBCState) -> bool {
+    state.intr_ctrl.interrupt_master_enable
+}
+
+/**
+ * Whether any interrupt is both enabled and requested, regardless of IME
+ */
+pub fn is_interrupt_pending(state: &GBCState) -> bool {
+    virtual_memory::read(state, INTERRUPT_ENABLE_ADDR)
+        & virtual_memory::read(state, INTERRUPT_REQUEST_ADDR)
+        & 0x1F
+        > 0
 }
 
 pub fn set_interrupt_request_flag(state: &mut GBCState, flag: InterruptFlag) {
@@ -0,0 +1,150 @@
+use crate::util::index_bits;
+
+use super::{
+    interrupt_controller::{self, InterruptFlag},
+    virtual_memory, GBCState,
+};
+
+pub const JOYPAD_REGISTER: u16 = 0xFF00;
+
+// Bits 4 and 5 of P1 select the buttons and d-pad rows. A row is selected when its bit is 0
+const SELECT_BITS: u8 = 0x30;
+// The pressed buttons of the selected rows read as 0 in the low nibble
+const BUTTON_BITS: u8 = 0x0F;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    pub const ALL: [Button; 8] = [
+        Button::Right,
+        Button::Left,
+        Button::Up,
+        Button::Down,
+        Button::A,
+        Button::B,
+        Button::Select,
+        Button::Start,
+    ];
+
+    // Bits 0-3 are the d-pad and bits 4-7 the buttons, each in the order P1 reads them
+    fn mask(self) -> u8 {
+        1 << Button::ALL.iter().position(|&b| b == self).unwrap()
+    }
+}
+
+/**
+ * The buttons held down
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Buttons(u8);
+
+impl Buttons {
+    pub fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+
+    pub fn is_pressed(self, button: Button) -> bool {
+        self.0 & button.mask() != 0
+    }
+
+    pub fn set(&mut self, button: Button, pressed: bool) {
+        match pressed {
+            true => self.0 |= button.mask(),
+            false => self.0 &= !button.mask(),
+        }
+    }
+}
+
+#[derive(Clone, Hash)]
+pub struct JoypadController {
+    pressed: Buttons,
+}
+impl JoypadController {
+    pub fn new() -> Self {
+        Self {
+            pressed: Buttons::default(),
+        }
+    }
+}
+
+pub fn set_buttons(state: &mut GBCState, buttons: Buttons) {
+    state.joypad_ctrl.pressed = buttons;
+    update_joypad_register(state);
+}
+
+pub fn pressed_buttons(state: &GBCState) -> Buttons {
+    state.joypad_ctrl.pressed
+}
+
+/**
+ * Only the row select bits of P1 can be written. The rest is kept for update_joypad_register
+ */
+pub fn preprocess_joypad_register(state: &GBCState, val: u8) -> u8 {
+    let current = virtual_memory::read(state, JOYPAD_REGISTER);
+    (val & SELECT_BITS) | (current & !SELECT_BITS)
+}
+
+/**
+ * Recompute the button bits of P1 after the selected rows or the pressed buttons changed. A button
+ * bit going from 1 to 0 requests the joypad interrupt
+ */
+pub fn update_joypad_register(state: &mut GBCState) {
+    let current = virtual_memory::read(state, JOYPAD_REGISTER);
+    let pressed = state.joypad_ctrl.pressed.bits();
+    let mut selected = 0;
+    if !index_bits(current, 4) {
+        selected |= pressed & BUTTON_BITS;
+    }
+    if !index_bits(current, 5) {
+        selected |= pressed >> 4;
+    }
+    // The unused top bits read as 1
+    let new = 0xC0 | (current & SELECT_BITS) | (BUTTON_BITS & !selected);
+
+    if current & !new & BUTTON_BITS != 0 {
+        interrupt_controller::set_interrupt_request_flag(state, InterruptFlag::Joypad);
+    }
+    virtual_memory::write_without_triggers(state, JOYPAD_REGISTER, new);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gbc::interrupt_controller::INTERRUPT_REQUEST_ADDR;
+
+    #[test]
+    fn reads_the_selected_row_and_interrupts_on_press() {
+        let mut state = GBCState::with_program(&[]);
+        // Select the buttons row
+        virtual_memory::write(&mut state, JOYPAD_REGISTER, 0x10);
+        virtual_memory::write_without_triggers(&mut state, INTERRUPT_REQUEST_ADDR, 0);
+
+        let mut buttons = Buttons::default();
+        buttons.set(Button::Start, true);
+        buttons.set(Button::Up, true);
+        set_buttons(&mut state, buttons);
+        assert_eq!(virtual_memory::read(&state, JOYPAD_REGISTER), 0xD7);
+        assert!(index_bits(
+            virtual_memory::read(&state, INTERRUPT_REQUEST_ADDR),
+            4
+        ));
+
+        // Select the d-pad row instead. Writes can't change the button bits
+        virtual_memory::write(&mut state, JOYPAD_REGISTER, 0x20);
+        assert_eq!(virtual_memory::read(&state, JOYPAD_REGISTER), 0xEB);
+    }
+}
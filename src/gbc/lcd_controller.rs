@@ -9,37 +9,41 @@ use crate::{
     util::index_bits,
 };
 
-use super::{virtual_memory, GBCState};
+use super::{
+    cheats, render_engine,
+    scheduler::{self, Event},
+    virtual_memory, GBCState,
+};
 
-const LCD_CONTROL_REGISTER: u16 = 0xFF40;
+pub const LCD_CONTROL_REGISTER: u16 = 0xFF40;
 pub const LCD_STATUS_REGISTER: u16 = 0xFF41;
-const SCROLL_Y_REGISTER: u16 = 0xFF42;
-const SCROLL_X_REGISTER: u16 = 0xFF43;
+pub const SCROLL_Y_REGISTER: u16 = 0xFF42;
+pub const SCROLL_X_REGISTER: u16 = 0xFF43;
 pub const LCD_Y_COORDINATE_REGISTER: u16 = 0xFF44;
 pub const LY_COMPARE_REGISTER: u16 = 0xFF45;
-const WINDOW_Y_REGISTER: u16 = 0xFF4A;
-const WINDOW_X_REGISTER: u16 = 0xFF4B;
+pub const WINDOW_Y_REGISTER: u16 = 0xFF4A;
+pub const WINDOW_X_REGISTER: u16 = 0xFF4B;
 
 const CYCLES_PER_SCANLINE: u16 = 114;
 const CYCLES_BEFORE_DRAWING: u16 = 20;
 const VERTICAL_BLANK_BEGIN_CYCLE: u16 = 16416;
 
 #[repr(u8)]
-#[derive(Clone, Copy, IntEnum)]
+#[derive(Clone, Copy, IntEnum, Debug, PartialEq)]
 pub enum TileMapArea {
     Map0 = 0,
     Map1 = 1,
 }
 
 #[repr(u8)]
-#[derive(Clone, Copy, IntEnum)]
+#[derive(Clone, Copy, IntEnum, Debug)]
 pub enum TileDataArea {
     Upper = 0,
     Lower = 1,
 }
 
 #[repr(u8)]
-#[derive(Clone, Copy, IntEnum)]
+#[derive(Clone, Copy, IntEnum, Debug, Hash)]
 pub enum VRAMBank {
     Bank0 = 0,
     Bank1 = 1,
@@ -107,6 +111,7 @@ impl From<u8> for LCDStatus {
     }
 }
 
+#[derive(Clone, Hash)]
 pub struct LCDController {
     // Whether we have triggered the y coordinate requirement for drawing window
     pub window_y_triggered: bool,
@@ -164,37 +169,44 @@ pub fn read_from_vram_bank(state: &mut GBCState, addr: u16, bank: VRAMBank) -> u
  * What cycle within the scanline we are at
  */
 fn get_scanline_cycle_idx(state: &GBCState) -> u16 {
-    state.machine_cycle % CYCLES_PER_SCANLINE
+    scheduler::frame_cycle(state) % CYCLES_PER_SCANLINE
 }
 
-pub fn tick(state: &mut GBCState) {
+/**
+ * Handle a scanline start or the switch to drawing partway through a scanline, then schedule
+ * the next one
+ */
+pub fn handle_lcd_update(state: &mut GBCState) {
     let span = debug_span!("LCD Controller").entered();
 
+    let frame_cycle = scheduler::frame_cycle(state);
     let scanline_idx = get_scanline_cycle_idx(state);
     if scanline_idx == 0 {
         // Beginning of scanline
         state.lcd_ctrl.window_x_triggered = false;
-        set_lcd_y_coordinate(state, (state.machine_cycle / CYCLES_PER_SCANLINE) as u8)
+        set_lcd_y_coordinate(state, (frame_cycle / CYCLES_PER_SCANLINE) as u8)
     }
-    if state.machine_cycle == 0 {
+    if frame_cycle == 0 {
         // Beginning of frame
         state.lcd_ctrl.window_y_triggered = false;
     }
 
     match scanline_idx {
-        0 if state.machine_cycle == VERTICAL_BLANK_BEGIN_CYCLE => {
-            update_ppu_mode(state, PPUMode::VBlank)
-        }
-        0 if state.machine_cycle < VERTICAL_BLANK_BEGIN_CYCLE => {
-            update_ppu_mode(state, PPUMode::OAMScan)
-        }
-        CYCLES_BEFORE_DRAWING if state.machine_cycle < VERTICAL_BLANK_BEGIN_CYCLE => {
+        0 if frame_cycle == VERTICAL_BLANK_BEGIN_CYCLE => update_ppu_mode(state, PPUMode::VBlank),
+        0 if frame_cycle < VERTICAL_BLANK_BEGIN_CYCLE => update_ppu_mode(state, PPUMode::OAMScan),
+        CYCLES_BEFORE_DRAWING if frame_cycle < VERTICAL_BLANK_BEGIN_CYCLE => {
             update_ppu_mode(state, PPUMode::Drawing);
         }
         // HBlank mode is manually triggered by render engine
         _ => {}
     };
 
+    let cycles_until_next_update = match scanline_idx {
+        0 if frame_cycle < VERTICAL_BLANK_BEGIN_CYCLE => CYCLES_BEFORE_DRAWING,
+        _ => CYCLES_PER_SCANLINE - scanline_idx,
+    };
+    scheduler::schedule_in(state, Event::LCDUpdate, cycles_until_next_update as u64);
+
     span.exit();
 }
 
@@ -205,17 +217,23 @@ pub fn update_ppu_mode(state: &mut GBCState, new_mode: PPUMode) {
     virtual_memory::write_without_triggers(state, LCD_STATUS_REGISTER, val);
 
     match new_mode {
+        PPUMode::Drawing => scheduler::schedule_in(state, Event::RenderDots, 1),
         PPUMode::OAMScan => {
             // Check if window has met y coordinate condition at start of OAMScan
             state.lcd_ctrl.window_y_triggered = state.lcd_ctrl.window_y_triggered
                 || (get_lcd_y_coordinate(state) == get_window_y_coordinate(state));
+            render_engine::scan_oam(state);
         }
         PPUMode::HBlank => dma_controller::process_hblank_transfer(state),
         PPUMode::VBlank => {
-            interrupt_controller::set_interrupt_request_flag(state, InterruptFlag::VerticalBlanking)
+            interrupt_controller::set_interrupt_request_flag(
+                state,
+                InterruptFlag::VerticalBlanking,
+            );
+            cheats::apply_game_shark_codes(state);
         }
-        _ => {}
     }
+    interrupt_controller::update_stat_interrupt_line(state);
 }
 
 /**
@@ -231,6 +249,7 @@ pub fn update_lyc_match_ly_check(state: &mut GBCState) {
     // TODO (Should this be write_without_triggers?). GBC behaves different when making
     // the change for some reason.
     virtual_memory::write_without_triggers(state, LCD_STATUS_REGISTER, stat);
+    interrupt_controller::update_stat_interrupt_line(state);
 }
 
 // Should only be set internally by lcd controller
@@ -0,0 +1,102 @@
+/**
+ * Addresses where `pattern` starts in `memory`
+ */
+pub fn find_pattern(memory: &[u8], pattern: &[u8]) -> Vec<u16> {
+    if pattern.is_empty() {
+        return Vec::new();
+    }
+    memory
+        .windows(pattern.len())
+        .enumerate()
+        .filter(|(_, window)| *window == pattern)
+        .map(|(addr, _)| addr as u16)
+        .collect()
+}
+
+/**
+ * How a candidate's current value has to compare to its value at the last search step
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchFilter {
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+    EqualTo(u8),
+}
+
+impl SearchFilter {
+    fn matches(&self, old: u8, new: u8) -> bool {
+        match self {
+            SearchFilter::Changed => new != old,
+            SearchFilter::Unchanged => new == old,
+            SearchFilter::Increased => new > old,
+            SearchFilter::Decreased => new < old,
+            SearchFilter::EqualTo(val) => new == *val,
+        }
+    }
+}
+
+/**
+ * Narrows down which address holds a value (lives, health...) by comparing memory between frames
+ */
+#[derive(Debug, Clone)]
+pub struct MemorySearch {
+    // (address, value at the last search step)
+    candidates: Vec<(u16, u8)>,
+}
+
+impl MemorySearch {
+    /**
+     * Start a search with every address in `addrs` as a candidate
+     */
+    pub fn new(memory: &[u8], addrs: impl Iterator<Item = u16>) -> Self {
+        Self {
+            candidates: addrs.map(|addr| (addr, memory[addr as usize])).collect(),
+        }
+    }
+
+    /**
+     * Drop the candidates that don't match and remember the current values for the next step
+     */
+    pub fn filter(&mut self, memory: &[u8], filter: SearchFilter) {
+        self.candidates.retain_mut(|(addr, old)| {
+            let new = memory[*addr as usize];
+            let keep = filter.matches(*old, new);
+            *old = new;
+            keep
+        });
+    }
+
+    pub fn candidates(&self) -> &[(u16, u8)] {
+        &self.candidates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_every_occurrence_of_a_pattern() {
+        let memory = [0x00, 0xDE, 0xAD, 0xDE, 0xAD, 0xBE];
+        assert_eq!(find_pattern(&memory, &[0xDE, 0xAD]), vec![1, 3]);
+        assert_eq!(find_pattern(&memory, &[0xAD, 0xBE]), vec![4]);
+        assert!(find_pattern(&memory, &[]).is_empty());
+    }
+
+    #[test]
+    fn narrows_down_candidates_between_steps() {
+        let mut memory = vec![3, 3, 3, 3];
+        let mut search = MemorySearch::new(&memory, 0..4);
+
+        memory[1] = 2;
+        memory[2] = 4;
+        search.filter(&memory, SearchFilter::Changed);
+        assert_eq!(search.candidates(), [(1, 2), (2, 4)]);
+
+        memory[1] = 1;
+        search.filter(&memory, SearchFilter::Decreased);
+        assert_eq!(search.candidates(), [(1, 1)]);
+    }
+}
@@ -0,0 +1,473 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use color_eyre::eyre::{eyre, Result};
+
+use super::{
+    cartridge_header::CartridgeHeader,
+    joypad_controller::{self, Button, Buttons},
+    scheduler,
+    state_hash::state_hash,
+    virtual_memory, GBCConfig, GBCState, Model, MACHINE_CYCLES_PER_FRAME,
+};
+
+pub const MOVIE_EXTENSION: &str = "gbm";
+const MAGIC: &str = "GBCMOVIE 1";
+// Playback compares the state against the movie this often to notice a desync
+const HASH_INTERVAL_FRAMES: u64 = 60;
+// One character per button in Button::ALL order. Released buttons are written as '.'
+const BUTTON_CHARS: [char; 8] = ['R', 'L', 'U', 'D', 'A', 'B', 's', 'S'];
+
+/**
+ * Emulator options that change how a movie plays back
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncSettings {
+    pub model: Model,
+    // None if the cartridge was started without running a boot ROM
+    pub boot_rom_crc32: Option<u32>,
+    // Codes of the cheats enabled at power on, as entered
+    pub cheats: Vec<String>,
+}
+
+/**
+ * Joypad input for every frame since power on. Playing it back from power on reproduces the run
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub emulator_version: String,
+    pub rom_title: String,
+    pub rom_crc32: u32,
+    pub sync: SyncSettings,
+    // Battery backed cartridge RAM at power on
+    pub cartridge_ram: Vec<u8>,
+    // Frame recording started on. Earlier frames replay how the state it started from was reached
+    pub start_frame: u64,
+    // Buttons held during each frame
+    pub frames: Vec<Buttons>,
+    // State hash at the start of some frames
+    pub hashes: BTreeMap<u64, u64>,
+}
+
+impl Movie {
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut file = io::BufWriter::new(fs::File::create(path)?);
+        self.write(&mut file)?;
+        file.flush()?;
+        Ok(())
+    }
+
+    /**
+     * Write the movie as text: a header of `key value` lines, then a line per frame with the
+     * buttons held and, every so often, the state hash
+     */
+    pub fn write(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "{}", MAGIC)?;
+        writeln!(writer, "emulator {}", self.emulator_version)?;
+        writeln!(writer, "rom_title {}", self.rom_title)?;
+        writeln!(writer, "rom_crc32 {:08x}", self.rom_crc32)?;
+        writeln!(writer, "model {:?}", self.sync.model)?;
+        match self.sync.boot_rom_crc32 {
+            Some(crc) => writeln!(writer, "boot_rom {:08x}", crc)?,
+            None => writeln!(writer, "boot_rom none")?,
+        }
+        match self.sync.cheats.is_empty() {
+            true => writeln!(writer, "cheats none")?,
+            false => writeln!(writer, "cheats {}", self.sync.cheats.join("+"))?,
+        }
+        writeln!(writer, "start_frame {}", self.start_frame)?;
+        let mut ram = String::with_capacity(self.cartridge_ram.len() * 2);
+        for byte in &self.cartridge_ram {
+            write!(ram, "{:02x}", byte).unwrap();
+        }
+        writeln!(writer, "cartridge_ram {}", ram)?;
+        writeln!(writer, "frames")?;
+        for (frame, &buttons) in self.frames.iter().enumerate() {
+            let line: String = Button::ALL
+                .iter()
+                .zip(BUTTON_CHARS)
+                .map(|(&button, c)| match buttons.is_pressed(button) {
+                    true => c,
+                    false => '.',
+                })
+                .collect();
+            match self.hashes.get(&(frame as u64)) {
+                Some(hash) => writeln!(writer, "{} {:016x}", line, hash)?,
+                None => writeln!(writer, "{}", line)?,
+            }
+        }
+        Ok(())
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut lines = text.lines();
+        if lines.next() != Some(MAGIC) {
+            return Err(eyre!("Not a movie file"));
+        }
+
+        let mut header = BTreeMap::new();
+        for line in lines.by_ref() {
+            if line == "frames" {
+                break;
+            }
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            header.insert(key, value);
+        }
+        let field = |key: &str| {
+            header
+                .get(key)
+                .copied()
+                .ok_or_else(|| eyre!("Movie header is missing {}", key))
+        };
+        let hex_u32 = |key: &str| -> Result<u32> {
+            u32::from_str_radix(field(key)?, 16).map_err(|_| eyre!("Invalid {} in movie", key))
+        };
+
+        let model = match field("model")? {
+            "DMG" => Model::DMG,
+            "CGB" => Model::CGB,
+            model => return Err(eyre!("Unknown model {}", model)),
+        };
+        let boot_rom_crc32 = match field("boot_rom")? {
+            "none" => None,
+            _ => Some(hex_u32("boot_rom")?),
+        };
+        let cheats = match field("cheats")? {
+            "none" => Vec::new(),
+            codes => codes.split('+').map(str::to_string).collect(),
+        };
+        let ram = field("cartridge_ram")?;
+        let cartridge_ram = (0..ram.len())
+            .step_by(2)
+            .map(|i| {
+                ram.get(i..i + 2)
+                    .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+            })
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(|| eyre!("Invalid cartridge RAM in movie"))?;
+
+        let mut frames = Vec::new();
+        let mut hashes = BTreeMap::new();
+        for line in lines {
+            let (input, hash) = line.split_once(' ').unwrap_or((line, ""));
+            if input.chars().count() != BUTTON_CHARS.len() {
+                return Err(eyre!("Invalid input on frame {}", frames.len()));
+            }
+            let mut buttons = Buttons::default();
+            for (&button, c) in Button::ALL.iter().zip(input.chars()) {
+                buttons.set(button, c != '.');
+            }
+            if !hash.is_empty() {
+                let hash = u64::from_str_radix(hash, 16)
+                    .map_err(|_| eyre!("Invalid state hash on frame {}", frames.len()))?;
+                hashes.insert(frames.len() as u64, hash);
+            }
+            frames.push(buttons);
+        }
+
+        Ok(Self {
+            emulator_version: field("emulator")?.to_string(),
+            rom_title: field("rom_title")?.to_string(),
+            rom_crc32: hex_u32("rom_crc32")?,
+            sync: SyncSettings {
+                model,
+                boot_rom_crc32,
+                cheats,
+            },
+            cartridge_ram,
+            start_frame: field("start_frame")?
+                .parse()
+                .map_err(|_| eyre!("Invalid start_frame in movie"))?,
+            frames,
+            hashes,
+        })
+    }
+}
+
+/**
+ * What happened to the movie being played on a frame
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaybackEvent {
+    // The state stopped matching the movie. Only reported for the first mismatch
+    Desynced { frame: u64 },
+    // The movie ran out of input and the frontend has the joypad again
+    Ended { desynced: bool },
+}
+
+struct Playback {
+    movie: Movie,
+    desynced: bool,
+}
+
+/**
+ * Input applied on every frame since power on, so a movie can be saved at any time. Rewinding
+ * drops the frames rewound over
+ */
+pub struct InputLog {
+    rom_title: String,
+    rom_crc32: u32,
+    sync: SyncSettings,
+    cartridge_ram: Vec<u8>,
+    frames: Vec<Buttons>,
+    hashes: BTreeMap<u64, u64>,
+    // Movie whose input replaces the frontend's
+    playback: Option<Playback>,
+}
+
+impl InputLog {
+    pub fn new(rom_data: &[u8], config: &GBCConfig) -> Self {
+        Self {
+            rom_title: CartridgeHeader::parse(rom_data)
+                .map(|header| header.title)
+                .unwrap_or_default(),
+            rom_crc32: crc32(rom_data),
+            sync: SyncSettings {
+                model: config.model,
+                boot_rom_crc32: config.boot_rom.as_deref().map(crc32),
+                cheats: Vec::new(),
+            },
+            cartridge_ram: Vec::new(),
+            frames: Vec::new(),
+            hashes: BTreeMap::new(),
+            playback: None,
+        }
+    }
+}
+
+/**
+ * Frames started since power on
+ */
+pub fn frame_index(state: &GBCState) -> u64 {
+    scheduler::now(state) / MACHINE_CYCLES_PER_FRAME as u64
+}
+
+/**
+ * Called at the start of every frame. Applies the buttons the frontend holds, or the movie's if
+ * one is playing, and logs them
+ */
+pub fn start_frame(state: &mut GBCState, held: Buttons) -> Option<PlaybackEvent> {
+    let frame = frame_index(state);
+    if frame == 0 {
+        state.input_log.cartridge_ram = virtual_memory::borrow_external_ram(state).to_vec();
+    }
+    let pressed = joypad_controller::pressed_buttons(state);
+    let log = &mut state.input_log;
+    // Frames that ran without going through here, like while debugging, keep the last input
+    log.frames.resize(frame as usize, pressed);
+    log.hashes.retain(|&hashed_frame, _| hashed_frame < frame);
+
+    let expected_hash = log
+        .playback
+        .as_ref()
+        .and_then(|playback| playback.movie.hashes.get(&frame).copied());
+    if frame.is_multiple_of(HASH_INTERVAL_FRAMES) || expected_hash.is_some() {
+        let hash = state_hash(state);
+        state.input_log.hashes.insert(frame, hash);
+    }
+
+    let log = &mut state.input_log;
+    let mut event = None;
+    let mut buttons = held;
+    if let Some(ref mut playback) = log.playback {
+        let hash = log.hashes.get(&frame);
+        if !playback.desynced && expected_hash.is_some_and(|expected| Some(&expected) != hash) {
+            playback.desynced = true;
+            event = Some(PlaybackEvent::Desynced { frame });
+        }
+        match playback.movie.frames.get(frame as usize) {
+            Some(&movie_buttons) => buttons = movie_buttons,
+            None => {
+                event = Some(PlaybackEvent::Ended {
+                    desynced: playback.desynced,
+                })
+            }
+        }
+    }
+    if let Some(PlaybackEvent::Ended { .. }) = event {
+        log.playback = None;
+    }
+    log.frames.push(buttons);
+    joypad_controller::set_buttons(state, buttons);
+    event
+}
+
+/**
+ * A movie of everything since power on, starting to record at start_frame
+ */
+pub fn record(state: &GBCState, start_frame: u64) -> Movie {
+    let log = &state.input_log;
+    Movie {
+        emulator_version: env!("CARGO_PKG_VERSION").to_string(),
+        rom_title: log.rom_title.clone(),
+        rom_crc32: log.rom_crc32,
+        sync: log.sync.clone(),
+        cartridge_ram: log.cartridge_ram.clone(),
+        start_frame: start_frame.min(log.frames.len() as u64),
+        frames: log.frames.clone(),
+        hashes: log.hashes.clone(),
+    }
+}
+
+/**
+ * Play a movie from power on. Its input replaces the frontend's until it runs out
+ */
+pub fn start_playback(state: &mut GBCState, movie: Movie) -> Result<()> {
+    let log = &state.input_log;
+    if frame_index(state) != 0 {
+        return Err(eyre!("Movies can only be played from power on"));
+    }
+    if movie.rom_crc32 != log.rom_crc32 {
+        return Err(eyre!(
+            "The movie was recorded with a different ROM ({})",
+            movie.rom_title
+        ));
+    }
+    if movie.sync.cheats != log.sync.cheats {
+        return Err(eyre!(
+            "The movie was recorded with different cheats enabled ({})",
+            match movie.sync.cheats.is_empty() {
+                true => "none".to_string(),
+                false => movie.sync.cheats.join(", "),
+            }
+        ));
+    }
+    if movie.sync != log.sync {
+        return Err(eyre!(
+            "The movie was recorded on {:?} {} a boot ROM, or with a different boot ROM",
+            movie.sync.model,
+            match movie.sync.boot_rom_crc32 {
+                Some(_) => "with",
+                None => "without",
+            }
+        ));
+    }
+    if movie.cartridge_ram.len() != virtual_memory::borrow_external_ram(state).len() {
+        return Err(eyre!("The movie's cartridge RAM doesn't fit the cartridge"));
+    }
+
+    virtual_memory::fill_external_ram(state, &movie.cartridge_ram);
+    state.input_log.playback = Some(Playback {
+        movie,
+        desynced: false,
+    });
+    Ok(())
+}
+
+/**
+ * Note the codes of the enabled cheats. Movies start from power on, so only the cheats enabled
+ * then are recorded. Changing them later shows up as a desync when the movie is played
+ */
+pub fn set_cheats(state: &mut GBCState, codes: Vec<String>) {
+    if frame_index(state) == 0 {
+        state.input_log.sync.cheats = codes;
+    }
+}
+
+/**
+ * Give the joypad back to the frontend. Returns whether the movie had desynced, or None if none was
+ * playing
+ */
+pub fn stop_playback(state: &mut GBCState) -> Option<bool> {
+    let playback = state.input_log.playback.take()?;
+    Some(playback.desynced)
+}
+
+/**
+ * CRC-32 as used by zip and No-Intro ROM databases
+ */
+fn crc32(data: &[u8]) -> u32 {
+    let mut table = [0u32; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        *entry = (0..8).fold(i as u32, |crc, _| match crc & 1 {
+            1 => (crc >> 1) ^ 0xEDB8_8320,
+            _ => crc >> 1,
+        });
+    }
+    !data.iter().fold(!0, |crc, &byte| {
+        table[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gbc::{
+        cheats::{self, Cheat, CheatList},
+        run_frame, INPUT_PROGRAM,
+    };
+
+    fn run(state: &mut GBCState, input: &[Buttons]) -> Vec<PlaybackEvent> {
+        let mut events = Vec::new();
+        for &held in input {
+            events.extend(start_frame(state, held));
+            run_frame(state);
+        }
+        events
+    }
+
+    #[test]
+    fn crc32_matches_reference() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn plays_back_recorded_input_and_detects_desync() {
+        let input: Vec<Buttons> = (0..=HASH_INTERVAL_FRAMES)
+            .map(|frame| Buttons::from_bits(((frame / 7) as u8) << 4))
+            .collect();
+        let mut state = GBCState::with_program(&INPUT_PROGRAM);
+        run(&mut state, &input);
+        let movie = record(&state, 0);
+        assert_eq!(movie.hashes.len(), 2);
+
+        let mut text = Vec::new();
+        movie.write(&mut text).unwrap();
+        let movie = Movie::parse(std::str::from_utf8(&text).unwrap()).unwrap();
+        assert_eq!(movie, record(&state, 0));
+
+        // The frontend's input is ignored while the movie plays
+        let mut state = GBCState::with_program(&INPUT_PROGRAM);
+        start_playback(&mut state, movie.clone()).unwrap();
+        let events = run(&mut state, &vec![Buttons::from_bits(0xFF); input.len() + 1]);
+        assert_eq!(events, [PlaybackEvent::Ended { desynced: false }]);
+
+        let mut tampered = movie;
+        tampered.frames[30] = Buttons::from_bits(0x80);
+        let mut state = GBCState::with_program(&INPUT_PROGRAM);
+        start_playback(&mut state, tampered).unwrap();
+        let events = run(&mut state, &input);
+        assert_eq!(
+            events,
+            [PlaybackEvent::Desynced {
+                frame: HASH_INTERVAL_FRAMES
+            }]
+        );
+    }
+
+    #[test]
+    fn refuses_to_play_without_the_recorded_cheats() {
+        let cheats = CheatList {
+            cheats: vec![Cheat::new("Infinite lives", "010399C0+3C1-50F").unwrap()],
+        };
+        let mut state = GBCState::with_program(&INPUT_PROGRAM);
+        cheats::set_cheats(&mut state, &cheats);
+        run(&mut state, &[Buttons::default(); 2]);
+        let movie = record(&state, 0);
+        assert_eq!(movie.sync.cheats, ["010399C0", "3C1-50F"]);
+
+        let mut state = GBCState::with_program(&INPUT_PROGRAM);
+        assert!(start_playback(&mut state, movie.clone()).is_err());
+        cheats::set_cheats(&mut state, &cheats);
+        start_playback(&mut state, movie).unwrap();
+    }
+}
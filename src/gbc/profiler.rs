@@ -0,0 +1,214 @@
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    io::{self, Write},
+};
+
+use super::{cpu::CallFrame, SymbolTable};
+
+// Name of the code that runs outside of any call, like the cartridge entry point
+const ROOT_NAME: &str = "root";
+
+// (bank, address) of an instruction or function entry point
+type Location = (u16, u16);
+
+/**
+ * Counts the T-cycles spent on each instruction and in each call stack while enabled
+ */
+#[derive(Default)]
+pub struct Profiler {
+    enabled: bool,
+    // Instruction and call stack the cycles being consumed are charged to
+    current: Location,
+    stack_key: Vec<Location>,
+    instruction_cycles: HashMap<Location, u64>,
+    // Keyed by the entry points of the functions on the call stack, outermost first
+    stack_cycles: HashMap<Vec<Location>, u64>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /**
+     * Forget everything counted so far
+     */
+    pub fn reset(&mut self) {
+        self.instruction_cycles.clear();
+        self.stack_cycles.clear();
+    }
+
+    /**
+     * Charge the cycles consumed from now on to an instruction and the call stack it runs in. A
+     * call is charged to the caller and a return to the function returning
+     */
+    pub fn start_instruction(&mut self, bank: u16, pc: u16, call_stack: &[CallFrame]) {
+        self.current = (bank, pc);
+        self.stack_key.clear();
+        self.stack_key
+            .extend(call_stack.iter().map(|frame| (frame.bank, frame.target)));
+    }
+
+    pub fn add_cycles(&mut self, t_cycles: u8) {
+        if !self.enabled {
+            return;
+        }
+        let t_cycles = t_cycles as u64;
+        *self.instruction_cycles.entry(self.current).or_default() += t_cycles;
+        match self.stack_cycles.get_mut(self.stack_key.as_slice()) {
+            Some(cycles) => *cycles += t_cycles,
+            None => {
+                self.stack_cycles.insert(self.stack_key.clone(), t_cycles);
+            }
+        }
+    }
+
+    /**
+     * Summarize the counts with functions and instructions named by their labels
+     */
+    pub fn report(&self, symbols: &SymbolTable) -> ProfileReport {
+        let function_name = |function: Option<&Location>| match function {
+            Some(&(bank, addr)) => symbols.name(bank, addr),
+            None => ROOT_NAME.to_string(),
+        };
+
+        let mut self_cycles: HashMap<Option<&Location>, u64> = HashMap::new();
+        let mut total_cycles: HashMap<Option<&Location>, u64> = HashMap::new();
+        for (stack, &cycles) in &self.stack_cycles {
+            *self_cycles.entry(stack.last()).or_default() += cycles;
+            // Recursive functions are only counted once per stack
+            let functions: HashSet<Option<&Location>> =
+                stack.iter().map(Some).chain([None]).collect();
+            for function in functions {
+                *total_cycles.entry(function).or_default() += cycles;
+            }
+        }
+        let mut functions: Vec<FunctionProfile> = total_cycles
+            .iter()
+            .map(|(&function, &total_cycles)| FunctionProfile {
+                name: function_name(function),
+                self_cycles: self_cycles.get(&function).copied().unwrap_or_default(),
+                total_cycles,
+            })
+            .collect();
+        functions.sort_by_key(|profile| Reverse(profile.self_cycles));
+
+        let mut instructions: Vec<InstructionProfile> = self
+            .instruction_cycles
+            .iter()
+            .map(|(&(bank, pc), &cycles)| InstructionProfile {
+                location: symbols.name(bank, pc),
+                cycles,
+            })
+            .collect();
+        instructions.sort_by_key(|profile| Reverse(profile.cycles));
+
+        let stacks: Vec<(Vec<String>, u64)> = self
+            .stack_cycles
+            .iter()
+            .map(|(stack, &cycles)| {
+                let names: Vec<String> = [ROOT_NAME.to_string()]
+                    .into_iter()
+                    .chain(stack.iter().map(|function| function_name(Some(function))))
+                    .collect();
+                (names, cycles)
+            })
+            .collect();
+
+        ProfileReport {
+            total_cycles: self.instruction_cycles.values().sum(),
+            functions,
+            instructions,
+            stacks,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FunctionProfile {
+    pub name: String,
+    // Spent in the function itself
+    pub self_cycles: u64,
+    // Spent in the function and everything it called
+    pub total_cycles: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct InstructionProfile {
+    pub location: String,
+    pub cycles: u64,
+}
+
+/**
+ * T-cycle counts collected by the profiler
+ */
+#[derive(Debug, Clone, Default)]
+pub struct ProfileReport {
+    pub total_cycles: u64,
+    // Most self cycles first
+    pub functions: Vec<FunctionProfile>,
+    // Most cycles first
+    pub instructions: Vec<InstructionProfile>,
+    // Function names from the outermost call in, and the cycles spent with exactly that stack
+    pub stacks: Vec<(Vec<String>, u64)>,
+}
+
+impl ProfileReport {
+    /**
+     * Write the stacks in the folded format flamegraph.pl and inferno take. One `a;b;c cycles`
+     * line per stack
+     */
+    pub fn write_folded(&self, mut writer: impl Write) -> io::Result<()> {
+        for (names, cycles) in &self.stacks {
+            writeln!(writer, "{} {}", names.join(";"), cycles)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gbc::{cpu, step_instruction, GBCState};
+
+    #[test]
+    fn counts_cycles_per_function_and_stack() {
+        // CALL 0x0104; NOP; RET
+        let mut state = GBCState::with_program(&[0xCD, 0x04, 0x01, 0x00, 0xC9]);
+        state.symbols = std::sync::Arc::new(SymbolTable::parse("00:0104 Func").unwrap());
+        state.profiler.set_enabled(true);
+        let mut executed = 0;
+        while executed < 2 {
+            if step_instruction(&mut state) {
+                executed += 1;
+            }
+        }
+        assert!(cpu::call_stack(&state).is_empty());
+
+        let report = state.profiler.report(&state.symbols);
+        assert_eq!(report.total_cycles, 24 + 16);
+        let func = report.functions.iter().find(|f| f.name == "Func").unwrap();
+        assert_eq!((func.self_cycles, func.total_cycles), (16, 16));
+        let root = report
+            .functions
+            .iter()
+            .find(|f| f.name == ROOT_NAME)
+            .unwrap();
+        assert_eq!((root.self_cycles, root.total_cycles), (24, 40));
+
+        let mut folded = Vec::new();
+        report.write_folded(&mut folded).unwrap();
+        let mut lines: Vec<&str> = std::str::from_utf8(&folded).unwrap().lines().collect();
+        lines.sort();
+        assert_eq!(lines, ["root 24", "root;Func 16"]);
+    }
+}
@@ -1,105 +1,155 @@
 mod color_value;
+mod oam_scan;
 mod pixel_fetcher;
 
 use std::{
     collections::VecDeque,
     sync::{Arc, Mutex},
-    thread, time,
 };
 
-use eframe::epaint::ColorImage;
+use eframe::{egui::TextureOptions, epaint::ColorImage};
 use egui_extras::RetainedImage;
-use tracing::{debug, debug_span, trace};
+use tracing::debug;
 
-use crate::util::combine_high_low;
+use crate::util::{combine_high_low, hot_debug_span};
 
+pub use self::oam_scan::{
+    obj_height, read_oam_entry, scan as scan_oam, OAMEntry, MAX_OBJS_PER_LINE, OAM_ENTRY_COUNT,
+};
 use self::pixel_fetcher::{Pixel, PixelFetcher};
+pub use self::pixel_fetcher::{get_tile_data_addr, get_tile_map_addr, TileAttributes};
 
 use super::{
-    lcd_controller::{self, PPUMode},
-    virtual_memory, GBCState,
+    lcd_controller::{self, LCDControl, PPUMode},
+    scheduler::{self, Event},
+    video_recorder, virtual_memory, GBCState,
 };
 
-const FRAME_PERIOD: time::Duration = time::Duration::from_micros(16_666);
-const GBC_RESOLUTION_X: u8 = 160;
-const GBC_RESOLUTION_Y: u8 = 144;
+pub const GBC_RESOLUTION_X: u8 = 160;
+pub const GBC_RESOLUTION_Y: u8 = 144;
 const IMG_BUFFER_SIZE: usize = GBC_RESOLUTION_X as usize * GBC_RESOLUTION_Y as usize * 3;
 
 const BYTES_PER_PALETTE: u8 = 8;
 const BYTES_PER_PALETTE_COLOR: u8 = 2;
 
+/**
+ * Renderer state at the time of a snapshot. The frame buffer is saved separately as raw bytes
+ */
+#[derive(Clone, Hash)]
+pub struct RendererSnapshot {
+    bg_fifo: VecDeque<Pixel>,
+    obj_fifo: VecDeque<Pixel>,
+    obj_slots: [u8; MAX_OBJS_PER_LINE],
+    obj_slot_count: u8,
+    pixel_fetcher: PixelFetcher,
+    lcd_x: u8,
+    lcd_y: u8,
+}
+
 pub struct Renderer {
     // The current frame being displayed
     display_buffer: Arc<Mutex<RetainedImage>>,
-    // Allows render enginer to redraw gui when publishing a frame
-    gui_ctx: eframe::egui::Context,
+    // Allows render enginer to redraw gui when publishing a frame. None when running without a GUI
+    gui_ctx: Option<eframe::egui::Context>,
     // Flat RGB values for each pixel
     // The current frame being drawn
     working_frame_buffer: [u8; IMG_BUFFER_SIZE],
     // FIFO of pixels to draw. Refilled by pixel fetcher
     bg_fifo: VecDeque<Pixel>,
     obj_fifo: VecDeque<Pixel>,
-    obj_slots: [u8; 10],
+    // OAM indices of the objects picked by the OAM scan for the current scanline
+    obj_slots: [u8; MAX_OBJS_PER_LINE],
+    obj_slot_count: u8,
+    // Bitmask of the OAM entries picked for each scanline, kept for the debug windows
+    oam_scan_lines: [u64; GBC_RESOLUTION_Y as usize],
     pixel_fetcher: PixelFetcher,
     lcd_x: u8,
     lcd_y: u8,
-    last_frame_time: time::Instant,
 }
 
 impl Renderer {
-    pub fn new(display_buffer: Arc<Mutex<RetainedImage>>, gui_ctx: eframe::egui::Context) -> Self {
+    pub fn new(
+        display_buffer: Arc<Mutex<RetainedImage>>,
+        gui_ctx: Option<eframe::egui::Context>,
+    ) -> Self {
         Self {
             display_buffer,
             gui_ctx,
             working_frame_buffer: [0xFF; IMG_BUFFER_SIZE],
             bg_fifo: VecDeque::with_capacity(8),
             obj_fifo: VecDeque::with_capacity(8),
-            obj_slots: [0; 10],
+            obj_slots: [0; MAX_OBJS_PER_LINE],
+            obj_slot_count: 0,
+            oam_scan_lines: [0; GBC_RESOLUTION_Y as usize],
             pixel_fetcher: PixelFetcher::new(),
             lcd_x: 0,
             lcd_y: 0,
-            last_frame_time: time::Instant::now(),
         }
     }
 }
 
-pub fn tick(state: &mut GBCState) {
-    let status_reg = lcd_controller::get_lcd_status_register(state);
-
-    match status_reg.ppu_mode {
-        PPUMode::Drawing => {
-            let span = debug_span!(
-                "Render Engine Draw",
-                x = state.render_engine.lcd_x,
-                y = state.render_engine.lcd_y
-            )
-            .entered();
+/**
+ * Draw the 4 dots of a machine cycle. Reschedules itself every cycle until the scanline is done
+ */
+pub fn render_dots(state: &mut GBCState) {
+    if lcd_controller::get_lcd_status_register(state).ppu_mode != PPUMode::Drawing {
+        return;
+    }
 
-            let ctrl_reg = lcd_controller::get_lcd_control_register(state);
+    // Registers can only change between machine cycles, so read them once for all 4 dots
+    let ctrl_reg = lcd_controller::get_lcd_control_register(state);
+    for _ in 0..4 {
+        if !tick(state, &ctrl_reg) {
+            return;
+        }
+    }
+    scheduler::schedule_in(state, Event::RenderDots, 1);
+}
 
-            pixel_fetcher::tick(state, &ctrl_reg);
-            draw(state);
+/**
+ * Draw a single dot. Returns false once the scanline is complete
+ */
+fn tick(state: &mut GBCState, ctrl_reg: &LCDControl) -> bool {
+    let span = hot_debug_span!(
+        "Render Engine Draw",
+        x = state.render_engine.lcd_x,
+        y = state.render_engine.lcd_y
+    );
 
-            if state.render_engine.lcd_x == GBC_RESOLUTION_X {
-                // Scanline drawing complete
-                state.render_engine.lcd_x = 0;
-                state.render_engine.lcd_y += 1;
-                // Reset pixel fetcher
-                state.render_engine.pixel_fetcher = PixelFetcher::new();
+    pixel_fetcher::tick(state, ctrl_reg);
+    draw(state);
 
-                lcd_controller::update_ppu_mode(state, PPUMode::HBlank);
+    let scanline_complete = state.render_engine.lcd_x == GBC_RESOLUTION_X;
+    if scanline_complete {
+        state.render_engine.lcd_x = 0;
+        state.render_engine.lcd_y += 1;
+        // Reset pixel fetcher
+        state.render_engine.pixel_fetcher = PixelFetcher::new();
 
-                if state.render_engine.lcd_y == GBC_RESOLUTION_Y {
-                    // Frame drawing complete
-                    state.render_engine.lcd_y = 0;
-                    publish_frame(state);
-                    debug!("Frame published");
-                }
-            }
+        lcd_controller::update_ppu_mode(state, PPUMode::HBlank);
 
-            span.exit();
+        if state.render_engine.lcd_y == GBC_RESOLUTION_Y {
+            // Frame drawing complete
+            state.render_engine.lcd_y = 0;
+            publish_frame(state);
+            debug!("Frame published");
         }
-        _ => {}
+    }
+
+    span.exit();
+    !scanline_complete
+}
+
+/**
+ * Run the pixel fetcher on its own and throw away what it fetches. Only used for benchmarking
+ */
+#[cfg(feature = "bench")]
+pub fn fetch_pixels(state: &mut GBCState) {
+    let ctrl_reg = lcd_controller::get_lcd_control_register(state);
+    pixel_fetcher::tick(state, &ctrl_reg);
+    state.render_engine.bg_fifo.clear();
+    if state.render_engine.pixel_fetcher.finished_scanline() {
+        state.render_engine.pixel_fetcher = PixelFetcher::new();
     }
 }
 
@@ -119,36 +169,109 @@ fn draw(state: &mut GBCState) {
     state.render_engine.lcd_x += 1;
 }
 
-fn publish_frame(state: &mut GBCState) {
+/**
+ * RGB pixels of the frame being drawn. Holds the complete last frame between the end of the
+ * last scanline and the start of the next frame
+ */
+pub fn borrow_frame_buffer(state: &GBCState) -> &[u8] {
+    &state.render_engine.working_frame_buffer
+}
+
+/**
+ * For each scanline, a bitmask of the OAM entries the last OAM scan of that line picked
+ */
+pub fn borrow_oam_scan_lines(state: &GBCState) -> &[u64] {
+    &state.render_engine.oam_scan_lines
+}
+
+pub fn publish_frame(state: &mut GBCState) {
     let image = ColorImage::from_rgb(
         [GBC_RESOLUTION_X.into(), GBC_RESOLUTION_Y.into()],
         &state.render_engine.working_frame_buffer,
     );
-    let texture = RetainedImage::from_color_image("GBC frame", image);
+    // Keep pixels sharp when the frontend scales the screen up
+    let texture = RetainedImage::from_color_image("GBC frame", image)
+        .with_options(TextureOptions::NEAREST);
 
     // debug!("{:?}", virtual_memory::read_bytes(state, 0xFF69, 0x0400));
     // debug!("{:?}", virtual_memory::borrow_palette_mem(state));
 
-    // Sleep until frame is needed
-    let time_since_last_frame = state.render_engine.last_frame_time.elapsed();
-    thread::sleep(FRAME_PERIOD.saturating_sub(time_since_last_frame));
-    state.render_engine.last_frame_time = time::Instant::now();
-
     let mut display_buffer = state.render_engine.display_buffer.lock().unwrap();
     *display_buffer = texture;
 
-    state.render_engine.gui_ctx.request_repaint();
+    if let Some(ref gui_ctx) = state.render_engine.gui_ctx {
+        gui_ctx.request_repaint();
+    }
+    drop(display_buffer);
+
+    video_recorder::record_frame(state);
 }
 
 fn pixel_to_rgb(state: &GBCState, pixel: &Pixel) -> [u8; 3] {
     let palettes = virtual_memory::borrow_palette_mem(state);
-    let palette_idx =
-        (pixel.palette * BYTES_PER_PALETTE) + (pixel.color_idx * BYTES_PER_PALETTE_COLOR);
-    let low = palettes[palette_idx as usize];
-    let high = palettes[palette_idx as usize + 1];
-    let rgb555 = combine_high_low(high, low);
+    palette_color_to_rgb(palettes, pixel.palette, pixel.color_idx)
+}
+
+/**
+ * Look up a color in BG or OBJ palette memory and convert it from RGB555
+ */
+pub fn palette_color_to_rgb(palettes: &[u8], palette: u8, color_idx: u8) -> [u8; 3] {
+    let rgb555 = palette_color_rgb555(palettes, palette, color_idx);
     let r5 = (rgb555 & 0x1F) as u8;
     let g5 = ((rgb555 >> 5) & 0x1F) as u8;
     let b5 = ((rgb555 >> 10) & 0x1F) as u8;
     color_value::rgb555_to_rgb888(&[r5, g5, b5])
 }
+
+fn palette_color_idx(palette: u8, color_idx: u8) -> usize {
+    ((palette * BYTES_PER_PALETTE) + (color_idx * BYTES_PER_PALETTE_COLOR)) as usize
+}
+
+/**
+ * Raw little endian RGB555 value of a color in BG or OBJ palette memory
+ */
+pub fn palette_color_rgb555(palettes: &[u8], palette: u8, color_idx: u8) -> u16 {
+    let palette_idx = palette_color_idx(palette, color_idx);
+    combine_high_low(palettes[palette_idx + 1], palettes[palette_idx])
+}
+
+pub fn set_palette_color_rgb555(palettes: &mut [u8], palette: u8, color_idx: u8, rgb555: u16) {
+    let palette_idx = palette_color_idx(palette, color_idx);
+    palettes[palette_idx] = rgb555 as u8;
+    palettes[palette_idx + 1] = (rgb555 >> 8) as u8;
+}
+
+/**
+ * Save renderer state. The working frame buffer is appended to `data`
+ */
+pub fn save_snapshot(state: &GBCState, data: &mut Vec<u8>) -> RendererSnapshot {
+    let renderer = &state.render_engine;
+    data.extend_from_slice(&renderer.working_frame_buffer);
+    RendererSnapshot {
+        bg_fifo: renderer.bg_fifo.clone(),
+        obj_fifo: renderer.obj_fifo.clone(),
+        obj_slots: renderer.obj_slots,
+        obj_slot_count: renderer.obj_slot_count,
+        pixel_fetcher: renderer.pixel_fetcher.clone(),
+        lcd_x: renderer.lcd_x,
+        lcd_y: renderer.lcd_y,
+    }
+}
+
+/**
+ * Restore a snapshot made by save_snapshot. The frame buffer is consumed from the front of `data`
+ */
+pub fn load_snapshot(state: &mut GBCState, snapshot: &RendererSnapshot, data: &mut &[u8]) {
+    let renderer = &mut state.render_engine;
+    let (frame_buffer, rest) = data.split_at(IMG_BUFFER_SIZE);
+    renderer.working_frame_buffer.copy_from_slice(frame_buffer);
+    *data = rest;
+
+    renderer.bg_fifo = snapshot.bg_fifo.clone();
+    renderer.obj_fifo = snapshot.obj_fifo.clone();
+    renderer.obj_slots = snapshot.obj_slots;
+    renderer.obj_slot_count = snapshot.obj_slot_count;
+    renderer.pixel_fetcher = snapshot.pixel_fetcher.clone();
+    renderer.lcd_x = snapshot.lcd_x;
+    renderer.lcd_y = snapshot.lcd_y;
+}
@@ -0,0 +1,119 @@
+use crate::util::index_bits;
+
+use super::{
+    super::{lcd_controller, virtual_memory, GBCState},
+    TileAttributes,
+};
+
+pub const OAM_ENTRY_COUNT: usize = 40;
+pub const MAX_OBJS_PER_LINE: usize = 10;
+const BYTES_PER_OAM_ENTRY: usize = 4;
+// OAM Y positions are offset so objects can be partially scrolled off the top of the screen
+const OBJ_Y_OFFSET: u16 = 16;
+
+/**
+ * One object (sprite) in OAM
+ */
+#[derive(Clone, Copy, Debug)]
+pub struct OAMEntry {
+    // Screen Y + 16
+    pub y: u8,
+    // Screen X + 8
+    pub x: u8,
+    pub tile_id: u8,
+    // Same layout as BG map attributes, except bit 4 which is the DMG palette
+    pub attributes: TileAttributes,
+    pub dmg_palette: u8,
+}
+impl From<&[u8]> for OAMEntry {
+    fn from(bytes: &[u8]) -> Self {
+        Self {
+            y: bytes[0],
+            x: bytes[1],
+            tile_id: bytes[2],
+            attributes: TileAttributes::from(bytes[3]),
+            dmg_palette: index_bits(bytes[3], 4) as u8,
+        }
+    }
+}
+impl OAMEntry {
+    /**
+     * Whether any row of the object is on scanline `ly`. X doesn't matter to the OAM scan
+     */
+    pub fn covers_line(&self, ly: u8, obj_height: u8) -> bool {
+        let line = ly as u16 + OBJ_Y_OFFSET;
+        let top = self.y as u16;
+        line >= top && line < top + obj_height as u16
+    }
+}
+
+/**
+ * Read entry `idx` out of raw OAM memory
+ */
+pub fn read_oam_entry(oam: &[u8], idx: usize) -> OAMEntry {
+    let start = idx * BYTES_PER_OAM_ENTRY;
+    OAMEntry::from(&oam[start..start + BYTES_PER_OAM_ENTRY])
+}
+
+pub fn obj_height(ctrl_reg: &lcd_controller::LCDControl) -> u8 {
+    match ctrl_reg.obj_size {
+        true => 16,
+        false => 8,
+    }
+}
+
+/**
+ * Pick the first 10 objects in OAM order that are on the current scanline. Called at the start
+ * of mode 2
+ */
+pub fn scan(state: &mut GBCState) {
+    let ly = lcd_controller::get_lcd_y_coordinate(state);
+    let obj_height = obj_height(&lcd_controller::get_lcd_control_register(state));
+
+    let mut slots = [0; MAX_OBJS_PER_LINE];
+    let mut slot_count = 0;
+    let oam = virtual_memory::borrow_oam(state);
+    for idx in 0..OAM_ENTRY_COUNT {
+        if slot_count == MAX_OBJS_PER_LINE {
+            break;
+        }
+        if read_oam_entry(oam, idx).covers_line(ly, obj_height) {
+            slots[slot_count] = idx as u8;
+            slot_count += 1;
+        }
+    }
+
+    let renderer = &mut state.render_engine;
+    renderer.obj_slots = slots;
+    renderer.obj_slot_count = slot_count as u8;
+    if let Some(line) = renderer.oam_scan_lines.get_mut(ly as usize) {
+        *line = slots[..slot_count]
+            .iter()
+            .fold(0, |mask, &idx| mask | (1 << idx));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::gbc::{lcd_controller::LCD_Y_COORDINATE_REGISTER, virtual_memory::OAM_ADDR};
+
+    #[test]
+    fn selects_at_most_ten_objects_per_line() {
+        let mut state = GBCState::with_program(&[]);
+        for idx in 0..12 {
+            let addr = OAM_ADDR + (idx * BYTES_PER_OAM_ENTRY) as u16;
+            // Objects 0 and 1 are on lines 0-7, the rest on lines 4-11
+            let y = if idx < 2 { 16 } else { 20 };
+            virtual_memory::write_without_triggers(&mut state, addr, y);
+        }
+        virtual_memory::write_without_triggers(&mut state, LCD_Y_COORDINATE_REGISTER, 6);
+
+        scan(&mut state);
+        let renderer = &state.render_engine;
+        assert_eq!(renderer.obj_slot_count, 10);
+        assert_eq!(renderer.obj_slots, [0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(renderer.oam_scan_lines[6], 0x3FF);
+    }
+}
@@ -22,6 +22,7 @@ const TILE_DATA_UPPER_ADDR: u16 = 0x9000;
 const BYTES_PER_TILE: u16 = 16;
 const BYTES_PER_TILE_LINE: u16 = 2;
 
+#[derive(Clone, Hash)]
 enum PixelFetcherState {
     FetchTileID,
     FetchTileRowLow {
@@ -45,17 +46,20 @@ impl PixelFetcherState {
     }
 }
 
-#[derive(Clone, Copy)]
-struct TileAttributes {
-    vertical_flip: bool,
-    horizontal_flip: bool,
-    vram_bank: VRAMBank,
-    palette: u8,
+// BG map attributes, stored in VRAM bank 1 at the same address as the tile ID
+#[derive(Clone, Copy, Debug, Hash)]
+pub struct TileAttributes {
+    // TODO BG-to-OAM priority isn't used when drawing yet
+    pub bg_priority: bool,
+    pub vertical_flip: bool,
+    pub horizontal_flip: bool,
+    pub vram_bank: VRAMBank,
+    pub palette: u8,
 }
 impl From<u8> for TileAttributes {
     fn from(val: u8) -> Self {
         Self {
-            // TODO BG-to-OAM priority bit 7
+            bg_priority: index_bits(val, 7),
             vertical_flip: index_bits(val, 6),
             horizontal_flip: index_bits(val, 5),
             // Bit 4 is not used
@@ -65,7 +69,7 @@ impl From<u8> for TileAttributes {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Hash)]
 pub(super) struct Pixel {
     pub color_idx: u8,
     pub palette: u8,
@@ -73,6 +77,7 @@ pub(super) struct Pixel {
     // background_priority: bool,
 }
 
+#[derive(Clone, Hash)]
 pub(super) struct PixelFetcher {
     state: PixelFetcherState,
     // Current display X coordinate we are fetching for
@@ -85,6 +90,11 @@ impl PixelFetcher {
             fetching_x: 0,
         }
     }
+
+    #[cfg(feature = "bench")]
+    pub fn finished_scanline(&self) -> bool {
+        matches!(self.state, PixelFetcherState::FinishedScanline)
+    }
 }
 
 pub(super) fn tick(state: &mut GBCState, ctrl_reg: &LCDControl) {
@@ -186,10 +196,7 @@ fn get_bg_tile_id_and_attr(
     } else {
         ctrl_reg.bg_tile_map_area
     };
-    let tile_map_base_addr = match map_area {
-        TileMapArea::Map0 => TILE_MAP_0_ADDR,
-        TileMapArea::Map1 => TILE_MAP_1_ADDR,
-    };
+    let tile_map_base_addr = get_tile_map_addr(map_area);
 
     let (x_coordinate, y_coordinate) = if fetching_window {
         let x = state.render_engine.pixel_fetcher.fetching_x;
@@ -239,7 +246,30 @@ fn get_bg_tile_data_addr(
         lcd_y.wrapping_add(scroll_y)
     };
 
-    let mut tile_addr = match ctrl_reg.bg_and_window_tile_data_area {
+    let mut tile_addr = get_tile_data_addr(tile_id, ctrl_reg.bg_and_window_tile_data_area);
+
+    // Get the offset for the specific tile line
+    let mut y_offset = (y_coordinate as u16 % 8) * BYTES_PER_TILE_LINE;
+    if tile_attr.vertical_flip {
+        // Flip the 3 bits that are y_offset to read the tile backwards
+        y_offset = (!y_offset) & 0x0E;
+    }
+    tile_addr |= y_offset;
+    tile_addr
+}
+
+pub fn get_tile_map_addr(map_area: TileMapArea) -> u16 {
+    match map_area {
+        TileMapArea::Map0 => TILE_MAP_0_ADDR,
+        TileMapArea::Map1 => TILE_MAP_1_ADDR,
+    }
+}
+
+/**
+ * Address of the first byte of a BG or window tile
+ */
+pub fn get_tile_data_addr(tile_id: u8, data_area: TileDataArea) -> u16 {
+    match data_area {
         TileDataArea::Lower => TILE_DATA_LOWER_ADDR + (BYTES_PER_TILE * tile_id as u16),
         TileDataArea::Upper => {
             // When querying from uppper data area tile id is treated as a signed int
@@ -249,16 +279,7 @@ fn get_bg_tile_data_addr(
                 true => TILE_DATA_UPPER_ADDR - ((tile_idx * -1) as u16),
             }
         }
-    };
-
-    // Get the offset for the specific tile line
-    let mut y_offset = (y_coordinate as u16 % 8) * BYTES_PER_TILE_LINE;
-    if tile_attr.vertical_flip {
-        // Flip the 3 bits that are y_offset to read the tile backwards
-        y_offset = (!y_offset) & 0x0E;
     }
-    tile_addr |= y_offset;
-    tile_addr
 }
 
 fn get_bg_tile_data(state: &mut GBCState, tile_addr: u16, attr: &TileAttributes) -> u8 {
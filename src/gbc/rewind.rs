@@ -0,0 +1,175 @@
+use std::{collections::VecDeque, mem::size_of};
+
+use super::{
+    snapshot::{self, Snapshot},
+    GBCState,
+};
+
+// How many frames to run between snapshots
+const SNAPSHOT_INTERVAL_FRAMES: u32 = 2;
+// Oldest snapshots are dropped once the buffer grows past this
+const MEMORY_BUDGET_BYTES: usize = 64 * 1024 * 1024;
+
+/**
+ * Ring buffer of snapshots for playing the game backwards. The newest snapshot is stored in full.
+ * Every older snapshot only stores the bytes that differ from the snapshot after it, so the
+ * oldest can be dropped without decoding anything.
+ */
+pub struct RewindBuffer {
+    // Oldest first
+    snapshots: VecDeque<Snapshot>,
+    memory_used: usize,
+    frames_until_snapshot: u32,
+}
+
+impl RewindBuffer {
+    pub fn new() -> Self {
+        Self {
+            snapshots: VecDeque::new(),
+            memory_used: 0,
+            frames_until_snapshot: 0,
+        }
+    }
+
+    /**
+     * Called at the start of every frame. Takes a snapshot every SNAPSHOT_INTERVAL_FRAMES frames
+     */
+    pub fn start_frame(&mut self, state: &GBCState) {
+        if self.frames_until_snapshot > 0 {
+            self.frames_until_snapshot -= 1;
+            return;
+        }
+        self.frames_until_snapshot = SNAPSHOT_INTERVAL_FRAMES - 1;
+        self.push(snapshot::save(state));
+    }
+
+    fn push(&mut self, snapshot: Snapshot) {
+        // The previous newest snapshot becomes a delta against the new one
+        if let Some(previous) = self.snapshots.back_mut() {
+            self.memory_used -= snapshot_size(previous);
+            previous.data = encode_delta(&snapshot.data, &previous.data);
+            self.memory_used += snapshot_size(previous);
+        }
+
+        self.memory_used += snapshot_size(&snapshot);
+        self.snapshots.push_back(snapshot);
+
+        // Always keep the newest snapshot
+        while self.memory_used > MEMORY_BUDGET_BYTES && self.snapshots.len() > 1 {
+            let oldest = self.snapshots.pop_front().unwrap();
+            self.memory_used -= snapshot_size(&oldest);
+        }
+    }
+
+    /**
+     * Load the newest snapshot into state and remove it from the buffer. Returns false if there
+     * is nothing left to rewind to.
+     */
+    pub fn pop_into(&mut self, state: &mut GBCState) -> bool {
+        let Some(newest) = self.snapshots.pop_back() else {
+            return false;
+        };
+        self.memory_used -= snapshot_size(&newest);
+
+        // The snapshot before it becomes the newest, so decode it to a full snapshot
+        if let Some(previous) = self.snapshots.back_mut() {
+            self.memory_used -= snapshot_size(previous);
+            previous.data = decode_delta(&newest.data, &previous.data);
+            self.memory_used += snapshot_size(previous);
+        }
+
+        snapshot::load(state, &newest);
+        // Start counting towards the next snapshot from the restored frame
+        self.frames_until_snapshot = SNAPSHOT_INTERVAL_FRAMES - 1;
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.memory_used = 0;
+        self.frames_until_snapshot = 0;
+    }
+}
+
+fn snapshot_size(snapshot: &Snapshot) -> usize {
+    size_of::<Snapshot>() + snapshot.data.capacity()
+}
+
+/**
+ * Encode `data` as the differences from `base`. Both must be the same length. The output is a
+ * sequence of (unchanged run length, changed run length, changed bytes) with u32 LE lengths.
+ */
+fn encode_delta(base: &[u8], data: &[u8]) -> Vec<u8> {
+    debug_assert_eq!(base.len(), data.len());
+    let mut delta = Vec::new();
+    let mut idx = 0;
+    while idx < data.len() {
+        let unchanged_start = idx;
+        while idx < data.len() && data[idx] == base[idx] {
+            idx += 1;
+        }
+        let changed_start = idx;
+        while idx < data.len() && data[idx] != base[idx] {
+            idx += 1;
+        }
+        delta.extend_from_slice(&((changed_start - unchanged_start) as u32).to_le_bytes());
+        delta.extend_from_slice(&((idx - changed_start) as u32).to_le_bytes());
+        delta.extend_from_slice(&data[changed_start..idx]);
+    }
+    delta.shrink_to_fit();
+    delta
+}
+
+fn decode_delta(base: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut data = base.to_vec();
+    let mut idx = 0;
+    let mut delta = delta;
+    while !delta.is_empty() {
+        let (unchanged_len, rest) = delta.split_at(4);
+        let (changed_len, rest) = rest.split_at(4);
+        let unchanged_len = u32::from_le_bytes(unchanged_len.try_into().unwrap()) as usize;
+        let changed_len = u32::from_le_bytes(changed_len.try_into().unwrap()) as usize;
+        let (changed, rest) = rest.split_at(changed_len);
+
+        idx += unchanged_len;
+        data[idx..idx + changed_len].copy_from_slice(changed);
+        idx += changed_len;
+        delta = rest;
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gbc::virtual_memory;
+
+    #[test]
+    fn delta_round_trip() {
+        let base = [0u8, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+        let data = [0u8, 9, 2, 3, 7, 7, 6, 7, 8, 0];
+        let delta = encode_delta(&base, &data);
+        assert_eq!(decode_delta(&base, &delta), data);
+        assert!(encode_delta(&base, &base).len() <= 8);
+    }
+
+    #[test]
+    fn rewinds_to_earlier_snapshots() {
+        let mut state = GBCState::with_program(&[]);
+        let mut rewind = RewindBuffer::new();
+
+        for val in 0..4 {
+            virtual_memory::write(&mut state, 0xC000, val);
+            virtual_memory::write(&mut state, 0x8000, val * 2);
+            rewind.push(snapshot::save(&state));
+        }
+        virtual_memory::write(&mut state, 0xC000, 0xFF);
+
+        for val in (0..4).rev() {
+            assert!(rewind.pop_into(&mut state));
+            assert_eq!(virtual_memory::read(&state, 0xC000), val);
+            assert_eq!(virtual_memory::read(&state, 0x8000), val * 2);
+        }
+        assert!(!rewind.pop_into(&mut state));
+    }
+}
@@ -0,0 +1,221 @@
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    hash::{Hash, Hasher},
+    mem::discriminant,
+};
+
+use super::{GBCState, MACHINE_CYCLES_PER_FRAME};
+
+/**
+ * Something that has to happen at a specific machine cycle. Components schedule their own next
+ * event when handling one, so nothing runs on cycles where there is nothing to do.
+ */
+#[derive(Clone, Copy, Debug)]
+pub enum Event {
+    // Draw the 4 dots of the previous machine cycle. Scheduled every cycle while drawing
+    RenderDots,
+    // Scanline start or PPU mode change
+    LCDUpdate,
+    // Copy the next byte of an OAM DMA transfer
+    OAMDMATransfer,
+    DividerIncrement,
+    TimerIncrement,
+    // Last bit of a serial transfer has been shifted out
+    SerialTransferComplete,
+}
+
+impl Event {
+    // Events due on the same cycle run in this order. Matches the order components used
+    // to be ticked in.
+    fn priority(&self) -> u8 {
+        match self {
+            Event::RenderDots => 0,
+            Event::LCDUpdate => 1,
+            Event::OAMDMATransfer => 2,
+            Event::DividerIncrement => 3,
+            Event::TimerIncrement => 4,
+            Event::SerialTransferComplete => 5,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct ScheduledEvent {
+    cycle: u64,
+    event: Event,
+    // Keeps events with the same cycle and priority in the order they were scheduled
+    sequence: u64,
+}
+
+impl ScheduledEvent {
+    fn key(&self) -> (u64, u8, u64) {
+        (self.cycle, self.event.priority(), self.sequence)
+    }
+}
+
+// Reversed so the BinaryHeap (a max heap) pops the earliest event first
+impl Ord for ScheduledEvent {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.key().cmp(&self.key())
+    }
+}
+impl PartialOrd for ScheduledEvent {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl PartialEq for ScheduledEvent {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+impl Eq for ScheduledEvent {}
+// Every kind of event has its own priority, so this hashes the same things Eq compares
+impl Hash for ScheduledEvent {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+#[derive(Clone)]
+pub struct Scheduler {
+    // Machine cycles since power on
+    now: u64,
+    events: BinaryHeap<ScheduledEvent>,
+    next_sequence: u64,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self {
+            now: 0,
+            events: BinaryHeap::new(),
+            next_sequence: 0,
+        }
+    }
+}
+
+// The heap's layout depends on how it was built, so events are hashed in the order they run
+impl Hash for Scheduler {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.now.hash(state);
+        self.next_sequence.hash(state);
+        self.events.clone().into_sorted_vec().hash(state);
+    }
+}
+
+pub fn now(state: &GBCState) -> u64 {
+    state.scheduler.now
+}
+
+/**
+ * Machine cycle within the current frame
+ */
+pub fn frame_cycle(state: &GBCState) -> u16 {
+    (state.scheduler.now % MACHINE_CYCLES_PER_FRAME as u64) as u16
+}
+
+/**
+ * Move time forward. Called by the CPU as it executes so newly scheduled events are relative to
+ * the cycle the CPU is on.
+ */
+pub fn advance_to(state: &mut GBCState, cycle: u64) {
+    debug_assert!(cycle >= state.scheduler.now);
+    state.scheduler.now = cycle;
+}
+
+pub fn schedule_at(state: &mut GBCState, event: Event, cycle: u64) {
+    let scheduler = &mut state.scheduler;
+    scheduler.events.push(ScheduledEvent {
+        cycle,
+        event,
+        sequence: scheduler.next_sequence,
+    });
+    scheduler.next_sequence += 1;
+}
+
+/**
+ * Schedule an event `cycles` machine cycles from now
+ */
+pub fn schedule_in(state: &mut GBCState, event: Event, cycles: u64) {
+    schedule_at(state, event, state.scheduler.now + cycles);
+}
+
+/**
+ * Remove all pending events of the same kind as `event`
+ */
+pub fn cancel(state: &mut GBCState, event: Event) {
+    state
+        .scheduler
+        .events
+        .retain(|scheduled| discriminant(&scheduled.event) != discriminant(&event));
+}
+
+/**
+ * Cycle of the earliest pending event
+ */
+pub fn next_event_cycle(state: &GBCState) -> u64 {
+    state
+        .scheduler
+        .events
+        .peek()
+        .map_or(u64::MAX, |scheduled| scheduled.cycle)
+}
+
+/**
+ * Remove and return the earliest event if it is due
+ */
+pub fn pop_due_event(state: &mut GBCState) -> Option<Event> {
+    if next_event_cycle(state) > state.scheduler.now {
+        return None;
+    }
+    state.scheduler.events.pop().map(|scheduled| scheduled.event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pop_all_due(state: &mut GBCState) -> Vec<u8> {
+        let mut popped = Vec::new();
+        while let Some(event) = pop_due_event(state) {
+            popped.push(event.priority());
+        }
+        popped
+    }
+
+    #[test]
+    fn events_pop_in_cycle_then_priority_order() {
+        let mut state = GBCState::with_program(&[]);
+        state.scheduler = Scheduler::new();
+        schedule_at(&mut state, Event::TimerIncrement, 5);
+        schedule_at(&mut state, Event::DividerIncrement, 3);
+        schedule_at(&mut state, Event::RenderDots, 5);
+
+        assert!(pop_all_due(&mut state).is_empty());
+        assert_eq!(next_event_cycle(&state), 3);
+        advance_to(&mut state, 3);
+        assert_eq!(pop_all_due(&mut state), vec![Event::DividerIncrement.priority()]);
+        advance_to(&mut state, 5);
+        assert_eq!(
+            pop_all_due(&mut state),
+            vec![Event::RenderDots.priority(), Event::TimerIncrement.priority()]
+        );
+        assert_eq!(next_event_cycle(&state), u64::MAX);
+    }
+
+    #[test]
+    fn cancel_removes_only_matching_events() {
+        let mut state = GBCState::with_program(&[]);
+        state.scheduler = Scheduler::new();
+        schedule_in(&mut state, Event::TimerIncrement, 1);
+        schedule_in(&mut state, Event::TimerIncrement, 2);
+        schedule_in(&mut state, Event::OAMDMATransfer, 2);
+
+        cancel(&mut state, Event::TimerIncrement);
+        assert_eq!(next_event_cycle(&state), 2);
+        advance_to(&mut state, 2);
+        assert_eq!(pop_all_due(&mut state), vec![Event::OAMDMATransfer.priority()]);
+    }
+}
@@ -0,0 +1,149 @@
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::eyre::Result;
+
+use super::{GBC_RESOLUTION_X, GBC_RESOLUTION_Y};
+
+pub const MAX_SCREENSHOT_SCALE: u8 = 8;
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/**
+ * Save an RGB frame as a PNG with every pixel blown up to a scale x scale square
+ */
+pub fn write_screenshot_png(path: &Path, rgb: &[u8], scale: u8) -> Result<()> {
+    let scale = scale.clamp(1, MAX_SCREENSHOT_SCALE) as u32;
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(
+        file,
+        GBC_RESOLUTION_X as u32 * scale,
+        GBC_RESOLUTION_Y as u32 * scale,
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()?
+        .write_image_data(&scale_rgb(rgb, scale as usize))?;
+    Ok(())
+}
+
+fn scale_rgb(rgb: &[u8], scale: usize) -> Vec<u8> {
+    if scale == 1 {
+        return rgb.to_vec();
+    }
+    rgb.chunks_exact(GBC_RESOLUTION_X as usize * 3)
+        .flat_map(|row| {
+            let scaled_row: Vec<u8> = row
+                .chunks_exact(3)
+                .flat_map(|pixel| pixel.repeat(scale))
+                .collect();
+            scaled_row.repeat(scale)
+        })
+        .collect()
+}
+
+/**
+ * <title>_<YYYYMMDD-HHMMSS>.png with the time in UTC
+ */
+pub fn screenshot_file_name(title: &str, time: SystemTime) -> String {
+    format!("{}.png", capture_name(title, time))
+}
+
+/**
+ * <title>_<YYYYMMDD-HHMMSS> with the time in UTC, for naming screenshots and recordings.
+ * Characters that don't belong in a file name are replaced
+ */
+pub fn capture_name(title: &str, time: SystemTime) -> String {
+    let title: String = title
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() || c == '-' {
+            true => c,
+            false => '_',
+        })
+        .collect();
+    let title = match title.is_empty() {
+        true => "capture",
+        false => &title,
+    };
+    format!("{}_{}", title, format_timestamp(time))
+}
+
+/**
+ * YYYYMMDD-HHMMSS in UTC
+ */
+fn format_timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let (year, month, day) = civil_from_days(seconds / SECONDS_PER_DAY);
+    let seconds_of_day = seconds % SECONDS_PER_DAY;
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60
+    )
+}
+
+/**
+ * (year, month, day) of a number of days since 1970-01-01. Howard Hinnant's algorithm, which
+ * counts in 400 year eras starting on March 1st so leap days come last
+ */
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = match shifted_month < 10 {
+        true => shifted_month + 3,
+        false => shifted_month - 9,
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as u64;
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn names_files_after_the_title_and_utc_time() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert_eq!(
+            screenshot_file_name("POKEMON RED", time),
+            "POKEMON_RED_20231114-221320.png"
+        );
+        assert_eq!(
+            screenshot_file_name("", UNIX_EPOCH),
+            "capture_19700101-000000.png"
+        );
+        // Leap day
+        let time = UNIX_EPOCH + Duration::from_secs(951_782_400);
+        assert_eq!(screenshot_file_name("A", time), "A_20000229-000000.png");
+    }
+
+    #[test]
+    fn scales_every_pixel_to_a_square() {
+        let rgb: Vec<u8> = (0..GBC_RESOLUTION_X as usize * GBC_RESOLUTION_Y as usize * 3)
+            .map(|i| (i / 3) as u8)
+            .collect();
+        let scaled = scale_rgb(&rgb, 2);
+        let row_len = GBC_RESOLUTION_X as usize * 2 * 3;
+        assert_eq!(scaled.len(), rgb.len() * 4);
+        assert_eq!(scaled[..12], [0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1]);
+        assert_eq!(scaled[..row_len], scaled[row_len..2 * row_len]);
+    }
+}
@@ -0,0 +1,81 @@
+use crate::util::{index_bits, reset_bit};
+
+use super::{
+    interrupt_controller::{self, InterruptFlag},
+    scheduler::{self, Event},
+    virtual_memory, GBCState,
+};
+
+const SERIAL_DATA_REGISTER: u16 = 0xFF01;
+pub const SERIAL_CONTROL_REGISTER: u16 = 0xFF02;
+
+// 8 bits at 8192Hz with the internal clock
+const MCYCLES_PER_TRANSFER: u64 = 1024;
+
+#[derive(Clone)]
+pub struct SerialController {
+    // Every byte sent since power on. Nothing is ever connected to the link port, so this is
+    // only used to read the results printed by test ROMs
+    output: Vec<u8>,
+}
+impl SerialController {
+    pub fn new() -> Self {
+        Self { output: Vec::new() }
+    }
+}
+
+/**
+ * Start a transfer when the control register is written with the start and internal clock
+ * bits set. With an external clock the transfer never finishes because there is no partner.
+ */
+pub fn set_serial_control_register(state: &mut GBCState, val: u8) {
+    if !index_bits(val, 7) || !index_bits(val, 0) {
+        return;
+    }
+    scheduler::cancel(state, Event::SerialTransferComplete);
+    scheduler::schedule_in(state, Event::SerialTransferComplete, MCYCLES_PER_TRANSFER);
+}
+
+pub fn handle_transfer_complete(state: &mut GBCState) {
+    let sent = virtual_memory::read(state, SERIAL_DATA_REGISTER);
+    state.serial_ctrl.output.push(sent);
+
+    // A disconnected link port reads as all ones
+    virtual_memory::write_without_triggers(state, SERIAL_DATA_REGISTER, 0xFF);
+    let control = virtual_memory::read(state, SERIAL_CONTROL_REGISTER);
+    virtual_memory::write_without_triggers(state, SERIAL_CONTROL_REGISTER, reset_bit(control, 7));
+    interrupt_controller::set_interrupt_request_flag(state, InterruptFlag::SerialTransferComplete);
+}
+
+pub fn borrow_output(state: &GBCState) -> &[u8] {
+    &state.serial_ctrl.output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gbc::{interrupt_controller::INTERRUPT_REQUEST_ADDR, run_frame};
+
+    #[test]
+    fn transfer_with_internal_clock_completes() {
+        let mut state = GBCState::with_program(&[
+            0x3E, b'P', // LD A, 'P'
+            0xE0, 0x01, // LDH (SB), A
+            0x3E, 0x81, // LD A, 0x81
+            0xE0, 0x02, // LDH (SC), A
+            0x18, 0xFE, // JR -2
+        ]);
+        run_frame(&mut state);
+
+        assert_eq!(borrow_output(&state), b"P");
+        assert_eq!(virtual_memory::read(&state, SERIAL_DATA_REGISTER), 0xFF);
+        assert!(!index_bits(
+            virtual_memory::read(&state, SERIAL_CONTROL_REGISTER),
+            7
+        ));
+        assert!(index_bits(
+            virtual_memory::read(&state, INTERRUPT_REQUEST_ADDR),
+            3
+        ));
+    }
+}
@@ -0,0 +1,65 @@
+use super::{
+    cpu::CPU,
+    dma_controller::DMAController,
+    interrupt_controller::InterruptController,
+    joypad_controller::JoypadController,
+    lcd_controller::LCDController,
+    render_engine::{self, RendererSnapshot},
+    scheduler::Scheduler,
+    timer_controller::TimerController,
+    virtual_memory::{self, MemorySnapshot},
+    GBCState,
+};
+
+/**
+ * A copy of the emulation state at a point in time. ROM is never saved since it can't change.
+ */
+#[derive(Clone, Hash)]
+pub struct Snapshot {
+    cpu: CPU,
+    lcd_ctrl: LCDController,
+    intr_ctrl: InterruptController,
+    joypad_ctrl: JoypadController,
+    timer_ctrl: TimerController,
+    dma_ctrl: DMAController,
+    scheduler: Scheduler,
+    mem: MemorySnapshot,
+    render_engine: RendererSnapshot,
+    // RAM, VRAM and the frame buffer laid end to end. Kept apart from the rest of the state so
+    // it can be delta compressed.
+    pub data: Vec<u8>,
+}
+
+pub fn save(state: &GBCState) -> Snapshot {
+    let mut data = Vec::new();
+    let mem = virtual_memory::save_snapshot(state, &mut data);
+    let render_engine = render_engine::save_snapshot(state, &mut data);
+    Snapshot {
+        cpu: state.cpu.clone(),
+        lcd_ctrl: state.lcd_ctrl.clone(),
+        intr_ctrl: state.intr_ctrl.clone(),
+        joypad_ctrl: state.joypad_ctrl.clone(),
+        timer_ctrl: state.timer_ctrl.clone(),
+        dma_ctrl: state.dma_ctrl.clone(),
+        scheduler: state.scheduler.clone(),
+        mem,
+        render_engine,
+        data,
+    }
+}
+
+pub fn load(state: &mut GBCState, snapshot: &Snapshot) {
+    state.cpu = snapshot.cpu.clone();
+    state.lcd_ctrl = snapshot.lcd_ctrl.clone();
+    state.intr_ctrl = snapshot.intr_ctrl.clone();
+    state.joypad_ctrl = snapshot.joypad_ctrl.clone();
+    state.timer_ctrl = snapshot.timer_ctrl.clone();
+    state.dma_ctrl = snapshot.dma_ctrl.clone();
+    state.scheduler = snapshot.scheduler.clone();
+
+    // Must be consumed in the same order it was saved
+    let mut data = snapshot.data.as_slice();
+    virtual_memory::load_snapshot(state, &snapshot.mem, &mut data);
+    render_engine::load_snapshot(state, &snapshot.render_engine, &mut data);
+    debug_assert!(data.is_empty());
+}
@@ -0,0 +1,115 @@
+use std::hash::{Hash, Hasher};
+
+use super::{snapshot, virtual_memory, GBCState};
+
+/**
+ * Hash of what a snapshot saves: the CPU registers, every component, pending events, banking,
+ * RAM, VRAM and the frame buffer. The enabled cheats are hashed too, since they change what the
+ * game sees without being saved in snapshots. State only kept for debugging or output is left
+ * out: the shadow call stack, profiler, watchpoints, recorders and the serial output log.
+ *
+ * Emulation never reads the host clock or any other source of randomness. Pacing to real time is
+ * done by GBC outside of GBCState, so the same ROM, boot ROM, cheats and input always give the
+ * same hash on the same frame. Movies compare these to notice a desync
+ */
+pub fn state_hash(state: &GBCState) -> u64 {
+    let mut hasher = StateHasher::new();
+    snapshot::save(state).hash(&mut hasher);
+    state.game_shark_codes.hash(&mut hasher);
+    virtual_memory::rom_patches(state).hash(&mut hasher);
+    hasher.finish()
+}
+
+/**
+ * FNV-1a. Unlike DefaultHasher it is the same in every build and on every platform, so hashes can
+ * be saved in files and compared between machines
+ */
+struct StateHasher(u64);
+
+impl StateHasher {
+    fn new() -> Self {
+        Self(0xCBF2_9CE4_8422_2325)
+    }
+}
+
+impl Hasher for StateHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0000_0100_0000_01B3);
+        }
+    }
+
+    // The defaults use native byte order and size
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.write_u64(i as u64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gbc::{
+        joypad_controller::{self, Buttons},
+        run_frame, INPUT_PROGRAM,
+    };
+
+    fn hashes_per_frame(input: &[Buttons]) -> Vec<u64> {
+        let mut state = GBCState::with_program(&INPUT_PROGRAM);
+        input
+            .iter()
+            .map(|&held| {
+                joypad_controller::set_buttons(&mut state, held);
+                run_frame(&mut state);
+                state_hash(&state)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_input_gives_same_hash_every_frame() {
+        let input: Vec<Buttons> = (0..120)
+            .map(|frame| Buttons::from_bits(((frame / 7) as u8) << 4))
+            .collect();
+        let hashes = hashes_per_frame(&input);
+        assert_eq!(hashes, hashes_per_frame(&input));
+        assert!(hashes.windows(2).all(|pair| pair[0] != pair[1]));
+
+        let mut changed = input.clone();
+        changed[50] = Buttons::from_bits(0x80);
+        let changed_hashes = hashes_per_frame(&changed);
+        assert_eq!(hashes[..50], changed_hashes[..50]);
+        assert_ne!(hashes[50], changed_hashes[50]);
+    }
+
+    #[test]
+    fn loading_a_snapshot_restores_the_hash() {
+        let mut state = GBCState::with_program(&INPUT_PROGRAM);
+        run_frame(&mut state);
+        let saved = snapshot::save(&state);
+        let hash = state_hash(&state);
+        run_frame(&mut state);
+        assert_ne!(state_hash(&state), hash);
+        snapshot::load(&mut state, &saved);
+        assert_eq!(state_hash(&state), hash);
+    }
+}
@@ -0,0 +1,199 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, fs,
+    path::Path,
+};
+
+use color_eyre::eyre::{eyre, Result};
+use tracing::info;
+
+use super::{memory_area_at, virtual_memory, GBCState};
+
+// Comments in .sym files start with this and run to the end of the line
+const COMMENT_START: char = ';';
+
+/**
+ * Labels from an RGBDS .sym file. Each line is `bank:address label` with the bank and address in
+ * hex. Banks are numbered the way RGBDS does, so switchable work RAM starts at bank 1
+ */
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    // Sorted so the closest label before an address can be found
+    by_location: BTreeMap<(u16, u16), String>,
+    by_label: HashMap<String, (u16, u16)>,
+}
+
+impl SymbolTable {
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut symbols = Self::default();
+        for (line_idx, line) in text.lines().enumerate() {
+            let line = match line.split_once(COMMENT_START) {
+                Some((content, _)) => content,
+                None => line,
+            }
+            .trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (bank, addr, label) = parse_line(line)
+                .ok_or_else(|| eyre!("Bad symbol on line {}: {}", line_idx + 1, line))?;
+            symbols.insert(bank, addr, label);
+        }
+        Ok(symbols)
+    }
+
+    /**
+     * Load the .sym file next to a ROM, which RGBDS names after the ROM. Empty if there is none
+     */
+    pub fn load_for_rom(rom_path: &Path) -> Result<Self> {
+        let sym_path = rom_path.with_extension("sym");
+        if !sym_path.is_file() {
+            return Ok(Self::default());
+        }
+        let symbols = Self::parse(&fs::read_to_string(&sym_path)?)?;
+        info!(
+            "Loaded {} symbols from {}",
+            symbols.len(),
+            sym_path.display()
+        );
+        Ok(symbols)
+    }
+
+    fn insert(&mut self, bank: u16, addr: u16, label: &str) {
+        // Several labels can share an address. The first one listed is usually the global one
+        self.by_location
+            .entry((bank, addr))
+            .or_insert_with(|| label.to_string());
+        self.by_label.insert(label.to_string(), (bank, addr));
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_label.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_label.is_empty()
+    }
+
+    /**
+     * (bank, address) of a label
+     */
+    pub fn resolve(&self, label: &str) -> Option<(u16, u16)> {
+        self.by_label.get(label).copied()
+    }
+
+    /**
+     * Closest label at or before an address in the same bank and memory area, and how far past it
+     * the address is
+     */
+    pub fn nearest(&self, bank: u16, addr: u16) -> Option<(&str, u16)> {
+        let (&(label_bank, label_addr), label) =
+            self.by_location.range(..=(bank, addr)).next_back()?;
+        if label_bank != bank || memory_area_at(label_addr) != memory_area_at(addr) {
+            return None;
+        }
+        Some((label, addr - label_addr))
+    }
+
+    /**
+     * Closest label before an address, or the address as bank:address if there is none
+     */
+    pub fn name(&self, bank: u16, addr: u16) -> String {
+        match self.nearest(bank, addr) {
+            Some((label, 0)) => label.to_string(),
+            Some((label, offset)) => format!("{}+{:#x}", label, offset),
+            None => format!("{:02x}:{:04x}", bank, addr),
+        }
+    }
+}
+
+/**
+ * `bank:address label`
+ */
+fn parse_line(line: &str) -> Option<(u16, u16, &str)> {
+    let (location, label) = line.split_once(char::is_whitespace)?;
+    let (bank, addr) = location.split_once(':')?;
+    let bank = u16::from_str_radix(bank, 16).ok()?;
+    let addr = u16::from_str_radix(addr, 16).ok()?;
+    Some((bank, addr, label.trim()))
+}
+
+/**
+ * Bank an address is in right now, numbered the way RGBDS does
+ */
+pub fn current_bank(state: &GBCState, addr: u16) -> u16 {
+    let Some(area) = memory_area_at(addr) else {
+        return 0;
+    };
+    virtual_memory::symbol_bank(state, area)
+}
+
+/**
+ * Address formatted with the closest label before it in the mapped bank, if there is one
+ */
+pub fn describe(state: &GBCState, addr: u16) -> String {
+    describe_in_bank(state, current_bank(state, addr), addr)
+}
+
+/**
+ * Like describe but for an address in a bank that may not be mapped right now
+ */
+pub fn describe_in_bank(state: &GBCState, bank: u16, addr: u16) -> String {
+    match state.symbols.nearest(bank, addr) {
+        Some((label, 0)) => format!("{:#06x} <{}>", addr, label),
+        Some((label, offset)) => format!("{:#06x} <{}+{:#x}>", addr, label, offset),
+        None => format!("{:#06x}", addr),
+    }
+}
+
+/**
+ * Like describe, but the label is only looked up if the result is formatted. For spans that are
+ * usually filtered out
+ */
+pub fn display(state: &GBCState, addr: u16) -> impl fmt::Display + '_ {
+    Described { state, addr }
+}
+
+struct Described<'a> {
+    state: &'a GBCState,
+    addr: u16,
+}
+
+impl fmt::Display for Described<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&describe(self.state, self.addr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SYM_FILE: &str = "; File generated by rgblink
+00:0150 Main
+00:0158 Main.loop
+02:4000 LoadLevel
+00:c000 wPlayerX
+";
+
+    #[test]
+    fn parses_rgbds_sym_files() {
+        let symbols = SymbolTable::parse(SYM_FILE).unwrap();
+        assert_eq!(symbols.len(), 4);
+        assert_eq!(symbols.resolve("Main.loop"), Some((0x00, 0x0158)));
+        assert_eq!(symbols.resolve("LoadLevel"), Some((0x02, 0x4000)));
+        assert!(SymbolTable::parse("00:0150").is_err());
+    }
+
+    #[test]
+    fn finds_the_closest_label_in_the_same_bank_and_area() {
+        let symbols = SymbolTable::parse(SYM_FILE).unwrap();
+        assert_eq!(symbols.nearest(0x00, 0x0150), Some(("Main", 0)));
+        assert_eq!(symbols.nearest(0x00, 0x015A), Some(("Main.loop", 2)));
+        assert_eq!(symbols.nearest(0x02, 0x4010), Some(("LoadLevel", 0x10)));
+        // LoadLevel is in a different bank
+        assert_eq!(symbols.nearest(0x01, 0x4010), None);
+        // Main.loop is in ROM, not cartridge RAM
+        assert_eq!(symbols.nearest(0x00, 0xA000), None);
+    }
+}
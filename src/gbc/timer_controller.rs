@@ -1,82 +1,89 @@
 use crate::util::index_bits;
 
-use super::{virtual_memory, GBCState, interrupt_controller::{self, InterruptFlag}};
+use super::{
+    interrupt_controller::{self, InterruptFlag},
+    scheduler::{self, Event},
+    virtual_memory, GBCState,
+};
 
 pub const DIVIDER_REGISTER: u16 = 0xFF04;
 const TIMER_COUNTER_REGISTER: u16 = 0xFF05;
 const TIMER_MODULO_REGISTER: u16 = 0xFF06;
 pub const TIMER_CONTROL_REGISTER: u16 = 0xFF07;
 
-const MCYCLES_PER_DIVIDER_UPDATE: u16 = 64;
+const MCYCLES_PER_DIVIDER_UPDATE: u64 = 64;
 
+#[derive(Clone, Hash)]
 pub struct TimerController {
-    // Cycles left until next div update
-    div_update_countdown: u16,
     timer_enabled: bool,
-    // Cycles left until next timer update
-    timer_update_countdown: u16,
-    mcyles_per_timer_countdown: u16
+    // Cycles left until next timer update. Only kept up to date while the timer is disabled
+    timer_update_countdown: u64,
+    // Cycle of the next timer update. Only valid while the timer is enabled
+    next_timer_update_cycle: u64,
+    mcyles_per_timer_countdown: u64
 }
 impl TimerController {
     pub fn new() -> Self {
         Self {
-            div_update_countdown: MCYCLES_PER_DIVIDER_UPDATE,
             timer_enabled: false,
             timer_update_countdown: 128,
+            next_timer_update_cycle: 0,
             mcyles_per_timer_countdown: 128,
         }
     }
-
-    fn reset_div_update_countdown(&mut self) {
-        self.div_update_countdown = MCYCLES_PER_DIVIDER_UPDATE;
-    }
-
-    fn reset_timer_update_countdown(&mut self) {
-        self.timer_update_countdown = self.mcyles_per_timer_countdown;
-    }
 }
 
-pub fn tick(state: &mut GBCState) {
-    handle_divider(state);
-    handle_timer(state);
+/**
+ * Schedule the first divider update. The divider runs from power on
+ */
+pub fn start_divider(state: &mut GBCState) {
+    scheduler::schedule_in(state, Event::DividerIncrement, MCYCLES_PER_DIVIDER_UPDATE - 1);
 }
 
-fn handle_divider(state: &mut GBCState) {
-    state.timer_ctrl.div_update_countdown -= 1;
-    if state.timer_ctrl.div_update_countdown == 0 {
-        let curr_val = virtual_memory::read(state, DIVIDER_REGISTER);
-        virtual_memory::write_without_triggers(state, DIVIDER_REGISTER, curr_val.wrapping_add(1));
-        state.timer_ctrl.reset_div_update_countdown();
-    }
+pub fn handle_divider(state: &mut GBCState) {
+    let curr_val = virtual_memory::read(state, DIVIDER_REGISTER);
+    virtual_memory::write_without_triggers(state, DIVIDER_REGISTER, curr_val.wrapping_add(1));
+    scheduler::schedule_in(state, Event::DividerIncrement, MCYCLES_PER_DIVIDER_UPDATE);
 }
 
-fn handle_timer(state: &mut GBCState) {
-    if !state.timer_ctrl.timer_enabled {
-        return;
+pub fn handle_timer(state: &mut GBCState) {
+    let curr_val = virtual_memory::read(state, TIMER_COUNTER_REGISTER);
+    if curr_val == 0xFF {
+        // Timer overflow
+        let tma = virtual_memory::read(state, TIMER_MODULO_REGISTER);
+        virtual_memory::write_without_triggers(state, TIMER_COUNTER_REGISTER, tma);
+        interrupt_controller::set_interrupt_request_flag(state, InterruptFlag::TimerOverflow);
+    } else {
+        virtual_memory::write_without_triggers(state, TIMER_COUNTER_REGISTER, curr_val + 1);
     }
 
-    state.timer_ctrl.timer_update_countdown -= 1;
-    if state.timer_ctrl.timer_update_countdown == 0 {
-        let curr_val = virtual_memory::read(state, TIMER_COUNTER_REGISTER);
-        if curr_val == 0xFF {
-            // Timer overflow
-            let tma = virtual_memory::read(state, TIMER_MODULO_REGISTER);
-            virtual_memory::write_without_triggers(state, TIMER_COUNTER_REGISTER, tma);
-            interrupt_controller::set_interrupt_request_flag(state, InterruptFlag::TimerOverflow);
-        } else {
-            virtual_memory::write_without_triggers(state, TIMER_COUNTER_REGISTER, curr_val + 1);
-        }
-        
-        state.timer_ctrl.reset_timer_update_countdown();
-    }
+    schedule_timer_update(state, state.timer_ctrl.mcyles_per_timer_countdown);
+}
+
+fn schedule_timer_update(state: &mut GBCState, cycles: u64) {
+    state.timer_ctrl.next_timer_update_cycle = scheduler::now(state) + cycles;
+    scheduler::schedule_in(state, Event::TimerIncrement, cycles);
 }
 
 pub fn set_timer_control_register(state: &mut GBCState, val: u8) {
+    let was_enabled = state.timer_ctrl.timer_enabled;
     state.timer_ctrl.timer_enabled = index_bits(val, 2);
+    // A new clock speed only applies from the next update
     state.timer_ctrl.mcyles_per_timer_countdown = clock_select_bits_to_mcycles(val & 0x03);
+
+    match (was_enabled, state.timer_ctrl.timer_enabled) {
+        (false, true) => schedule_timer_update(state, state.timer_ctrl.timer_update_countdown),
+        (true, false) => {
+            // Pick up where we left off when the timer is enabled again
+            state.timer_ctrl.timer_update_countdown =
+                state.timer_ctrl.next_timer_update_cycle - scheduler::now(state);
+            scheduler::cancel(state, Event::TimerIncrement);
+        }
+        _ => {}
+    }
 }
 
-fn clock_select_bits_to_mcycles(select_bits: u8) -> u16 {
+fn clock_select_bits_to_mcycles(select_bits: u8) -> u64 {
     match select_bits {
         0x00 => 128,
         0x01 => 4,
@@ -85,3 +92,38 @@ fn clock_select_bits_to_mcycles(select_bits: u8) -> u16 {
         _ => panic!("Invalid clock select bit"),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gbc::{
+        cpu::{self, Register},
+        interrupt_controller::INTERRUPT_REQUEST_ADDR,
+        run_frame,
+    };
+
+    #[test]
+    fn timer_overflow_lands_on_the_same_instruction() {
+        // Counts 9 cycle loop iterations in B until the timer requests its interrupt
+        let mut state = GBCState::with_program(&[
+            0x3E, 0xFE, // LD A, 0xFE
+            0xE0, 0x05, // LDH (TIMA), A
+            0x3E, 0x05, // LD A, 0x05
+            0xE0, 0x07, // LDH (TAC), A
+            0x06, 0x00, // LD B, 0
+            0x04, // INC B
+            0xF0, 0x0F, // LDH A, (IF)
+            0xCB, 0x57, // BIT 2, A
+            0x28, 0xF9, // JR Z, -7
+            0x18, 0xFE, // JR -2
+        ]);
+        run_frame(&mut state);
+
+        // The first update comes 128 cycles after the timer is enabled, then one every 4 cycles
+        assert_eq!(cpu::read_register(&state, Register::B), 30);
+        assert!(index_bits(
+            virtual_memory::read(&state, INTERRUPT_REQUEST_ADDR),
+            2
+        ));
+    }
+}
@@ -0,0 +1,289 @@
+mod avi_writer;
+
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::Result;
+use tracing::{info, warn};
+
+use self::avi_writer::AviWriter;
+
+use super::{
+    frame_pacer::MACHINE_CYCLES_PER_SECOND, render_engine, scheduler, screenshot, GBCEvent,
+    GBCState, MACHINE_CYCLES_PER_FRAME,
+};
+
+// Written next to the frames of a PNG sequence
+const MANIFEST_NAME: &str = "frames.csv";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordingFormat {
+    // One uncompressed AVI file
+    Avi,
+    // A directory of numbered PNGs and a manifest with the time of each frame
+    PngSequence,
+}
+
+impl RecordingFormat {
+    /**
+     * Extension of the file, or suffix of the directory, a recording is written to
+     */
+    pub fn extension(&self) -> &'static str {
+        match self {
+            RecordingFormat::Avi => "avi",
+            RecordingFormat::PngSequence => "frames",
+        }
+    }
+}
+
+enum RecordingOutput {
+    Avi(AviWriter<BufWriter<File>>),
+    PngSequence(PngSequenceWriter),
+}
+
+/**
+ * Writes every published frame to disk. The core doesn't emulate sound yet, so recordings have no
+ * audio
+ */
+pub struct VideoRecorder {
+    path: PathBuf,
+    output: RecordingOutput,
+    // Machine cycle the last frame was published on, and its pixels
+    last_frame_cycle: Option<u64>,
+    last_frame: Vec<u8>,
+    // Machine cycles run before the last reset. Frame times keep counting up across resets
+    cycles_before_reset: u64,
+}
+
+impl VideoRecorder {
+    pub fn create(path: &Path, format: RecordingFormat) -> Result<Self> {
+        let output = match format {
+            RecordingFormat::Avi => {
+                let file = BufWriter::new(File::create(path)?);
+                RecordingOutput::Avi(AviWriter::new(file)?)
+            }
+            RecordingFormat::PngSequence => {
+                RecordingOutput::PngSequence(PngSequenceWriter::create(path)?)
+            }
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            output,
+            last_frame_cycle: None,
+            last_frame: Vec::new(),
+            cycles_before_reset: 0,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /**
+     * Add a frame published on machine cycle `cycle`
+     */
+    pub fn write_frame(&mut self, rgb: &[u8], cycle: u64) -> Result<()> {
+        match self.output {
+            RecordingOutput::Avi(ref mut avi) => {
+                // AVI frames are evenly spaced, so frames the LCD didn't publish while it was off
+                // are filled with the last one shown. Rewinding moves backwards and skips this
+                if let Some(last_cycle) = self.last_frame_cycle {
+                    let elapsed = cycle.saturating_sub(last_cycle);
+                    let frame_cycles = MACHINE_CYCLES_PER_FRAME as u64;
+                    let missed_frames = (elapsed + frame_cycles / 2) / frame_cycles;
+                    for _ in 1..missed_frames {
+                        avi.write_frame(&self.last_frame)?;
+                    }
+                }
+                avi.write_frame(rgb)?;
+            }
+            // The manifest records when each frame was shown instead
+            RecordingOutput::PngSequence(ref mut frames) => frames.write_frame(rgb, cycle)?,
+        }
+        self.last_frame_cycle = Some(cycle);
+        self.last_frame.clear();
+        self.last_frame.extend_from_slice(rgb);
+        Ok(())
+    }
+
+    /**
+     * Flush everything and fill in what can only be written at the end. Returns where the
+     * recording is
+     */
+    pub fn finish(self) -> Result<PathBuf> {
+        match self.output {
+            RecordingOutput::Avi(avi) => {
+                let frames = avi.frame_count();
+                avi.finish()?;
+                info!("Recorded {} frames to {}", frames, self.path.display());
+            }
+            RecordingOutput::PngSequence(mut frames) => {
+                frames.manifest.flush()?;
+                info!(
+                    "Recorded {} frames to {}",
+                    frames.count,
+                    self.path.display()
+                );
+            }
+        }
+        Ok(self.path)
+    }
+}
+
+/**
+ * Writes frame_000000.png, frame_000001.png, ... into a directory and a CSV line for each
+ */
+struct PngSequenceWriter {
+    dir: PathBuf,
+    manifest: BufWriter<File>,
+    count: u64,
+    // Times in the manifest are relative to the first frame
+    first_cycle: Option<u64>,
+}
+
+impl PngSequenceWriter {
+    fn create(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let mut manifest = BufWriter::new(File::create(dir.join(MANIFEST_NAME))?);
+        writeln!(manifest, "frame,file,machine_cycle,seconds")?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            manifest,
+            count: 0,
+            first_cycle: None,
+        })
+    }
+
+    fn write_frame(&mut self, rgb: &[u8], cycle: u64) -> Result<()> {
+        let file_name = format!("frame_{:06}.png", self.count);
+        screenshot::write_screenshot_png(&self.dir.join(&file_name), rgb, 1)?;
+        let first_cycle = *self.first_cycle.get_or_insert(cycle);
+        // Negative after rewinding past the start of the recording
+        let seconds = (cycle as f64 - first_cycle as f64) / MACHINE_CYCLES_PER_SECOND as f64;
+        writeln!(
+            self.manifest,
+            "{},{},{},{:.6}",
+            self.count, file_name, cycle, seconds
+        )?;
+        self.count += 1;
+        Ok(())
+    }
+}
+
+/**
+ * Start recording published frames, finishing any recording already in progress
+ */
+pub fn start(state: &mut GBCState, path: &Path, format: RecordingFormat) -> Result<()> {
+    stop(state);
+    state.video_recorder = Some(VideoRecorder::create(path, format)?);
+    info!("Recording {:?} to {}", format, path.display());
+    Ok(())
+}
+
+/**
+ * Finish the recording in progress. Returns where it was written, or None if there was nothing to
+ * finish or finishing failed
+ */
+pub fn stop(state: &mut GBCState) -> Option<PathBuf> {
+    let recorder = state.video_recorder.take()?;
+    match recorder.finish() {
+        Ok(path) => Some(path),
+        Err(e) => {
+            report_failure(state, e.to_string());
+            None
+        }
+    }
+}
+
+/**
+ * Keep recording into a state that replaces `old` on reset. The new state's clock starts from 0
+ * again, so the recording carries on from the cycle `old` was on
+ */
+pub fn carry_over_reset(old: &mut GBCState, new: &mut GBCState) {
+    let Some(mut recorder) = old.video_recorder.take() else {
+        return;
+    };
+    recorder.cycles_before_reset += scheduler::now(old);
+    new.video_recorder = Some(recorder);
+}
+
+/**
+ * Called whenever a frame is published. A recording that can't be written to is stopped
+ */
+pub fn record_frame(state: &mut GBCState) {
+    let Some(mut recorder) = state.video_recorder.take() else {
+        return;
+    };
+    let frame = render_engine::borrow_frame_buffer(state);
+    let cycle = recorder.cycles_before_reset + scheduler::now(state);
+    match recorder.write_frame(frame, cycle) {
+        Ok(()) => state.video_recorder = Some(recorder),
+        Err(e) => {
+            // Keep whatever made it to disk playable
+            let path = recorder.path().to_path_buf();
+            if let Err(e) = recorder.finish() {
+                warn!("Couldn't finish recording {}: {}", path.display(), e);
+            }
+            report_failure(state, format!("{}: {}", path.display(), e));
+        }
+    }
+}
+
+fn report_failure(state: &GBCState, message: String) {
+    warn!("Recording failed: {}", message);
+    state.events.send(GBCEvent::RecordingFailed(message)).ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gbc::run_frame;
+
+    #[test]
+    fn writes_a_png_and_manifest_line_per_frame() {
+        let dir = std::env::temp_dir().join(format!("gbc_recording_{}", std::process::id()));
+        let mut state = GBCState::with_program(&[0x18, 0xFE]);
+        start(&mut state, &dir, RecordingFormat::PngSequence).unwrap();
+        for _ in 0..3 {
+            run_frame(&mut state);
+        }
+        assert_eq!(stop(&mut state), Some(dir.clone()));
+
+        let manifest = fs::read_to_string(dir.join(MANIFEST_NAME)).unwrap();
+        let lines: Vec<&str> = manifest.lines().collect();
+        assert!(lines.len() >= 3);
+        assert!(lines[1].starts_with("0,frame_000000.png,"));
+        assert!(lines[1].ends_with(",0.000000"));
+        assert!(dir.join("frame_000000.png").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn frame_times_keep_counting_up_across_a_reset() {
+        let dir = std::env::temp_dir().join(format!("gbc_reset_recording_{}", std::process::id()));
+        let mut state = GBCState::with_program(&[0x18, 0xFE]);
+        start(&mut state, &dir, RecordingFormat::PngSequence).unwrap();
+        for _ in 0..2 {
+            run_frame(&mut state);
+        }
+        let mut reset_state = GBCState::with_program(&[0x18, 0xFE]);
+        carry_over_reset(&mut state, &mut reset_state);
+        for _ in 0..2 {
+            run_frame(&mut reset_state);
+        }
+        stop(&mut reset_state).unwrap();
+
+        let manifest = fs::read_to_string(dir.join(MANIFEST_NAME)).unwrap();
+        let cycles: Vec<u64> = manifest
+            .lines()
+            .skip(1)
+            .map(|line| line.split(',').nth(2).unwrap().parse().unwrap())
+            .collect();
+        assert!(cycles.len() >= 3);
+        assert!(cycles.windows(2).all(|pair| pair[0] < pair[1]));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
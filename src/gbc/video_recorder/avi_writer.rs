@@ -0,0 +1,254 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+use color_eyre::eyre::{eyre, Result};
+
+use crate::gbc::{GBC_RESOLUTION_X, GBC_RESOLUTION_Y};
+
+const WIDTH: u32 = GBC_RESOLUTION_X as u32;
+const HEIGHT: u32 = GBC_RESOLUTION_Y as u32;
+// 24 bit BGR. Rows are already a multiple of 4 bytes long, so there is no padding
+const FRAME_SIZE: u32 = WIDTH * HEIGHT * 3;
+// The LCD draws a frame every 70224 dots of the 4 MiHz clock
+const FRAME_RATE_NUMERATOR: u32 = 4_194_304;
+const FRAME_RATE_DENOMINATOR: u32 = 70_224;
+const MICROSECONDS_PER_FRAME: u32 = 16_743;
+// Sizes in the headers are 32 bits
+const MAX_FILE_SIZE: u64 = u32::MAX as u64;
+
+const AVIF_HASINDEX: u32 = 0x10;
+const AVIIF_KEYFRAME: u32 = 0x10;
+const MAIN_HEADER_SIZE: u32 = 56;
+const STREAM_HEADER_SIZE: u32 = 56;
+const BITMAP_INFO_HEADER_SIZE: u32 = 40;
+const INDEX_ENTRY_SIZE: u32 = 16;
+
+/**
+ * Writes an AVI 1.0 file with a single stream of uncompressed RGB frames
+ */
+pub struct AviWriter<W: Write + Seek> {
+    writer: W,
+    // Where values only known once recording stops are patched in
+    total_frames_pos: u64,
+    stream_length_pos: u64,
+    movi_size_pos: u64,
+    // The offsets in the index are relative to the 'movi' fourcc
+    movi_start: u64,
+    // Offset of each frame chunk
+    index: Vec<u32>,
+}
+
+impl<W: Write + Seek> AviWriter<W> {
+    pub fn new(mut writer: W) -> Result<Self> {
+        writer.write_all(b"RIFF")?;
+        // Patched in finish
+        write_u32(&mut writer, 0)?;
+        writer.write_all(b"AVI ")?;
+
+        let hdrl_size =
+            4 + 8 + MAIN_HEADER_SIZE + 8 + 4 + 8 + STREAM_HEADER_SIZE + 8 + BITMAP_INFO_HEADER_SIZE;
+        writer.write_all(b"LIST")?;
+        write_u32(&mut writer, hdrl_size)?;
+        writer.write_all(b"hdrl")?;
+
+        writer.write_all(b"avih")?;
+        write_u32(&mut writer, MAIN_HEADER_SIZE)?;
+        let main_header_start = writer.stream_position()?;
+        write_u32(&mut writer, MICROSECONDS_PER_FRAME)?;
+        // Max bytes per second
+        write_u32(&mut writer, FRAME_SIZE * 60)?;
+        // Padding granularity
+        write_u32(&mut writer, 0)?;
+        write_u32(&mut writer, AVIF_HASINDEX)?;
+        let total_frames_pos = writer.stream_position()?;
+        write_u32(&mut writer, 0)?;
+        // Initial frames
+        write_u32(&mut writer, 0)?;
+        // Streams
+        write_u32(&mut writer, 1)?;
+        // Suggested buffer size
+        write_u32(&mut writer, FRAME_SIZE)?;
+        write_u32(&mut writer, WIDTH)?;
+        write_u32(&mut writer, HEIGHT)?;
+        writer.write_all(&[0; 16])?;
+        debug_assert_eq!(
+            writer.stream_position()? - main_header_start,
+            MAIN_HEADER_SIZE as u64
+        );
+
+        writer.write_all(b"LIST")?;
+        write_u32(
+            &mut writer,
+            4 + 8 + STREAM_HEADER_SIZE + 8 + BITMAP_INFO_HEADER_SIZE,
+        )?;
+        writer.write_all(b"strl")?;
+
+        writer.write_all(b"strh")?;
+        write_u32(&mut writer, STREAM_HEADER_SIZE)?;
+        writer.write_all(b"vids")?;
+        writer.write_all(b"DIB ")?;
+        // Flags, priority and language
+        write_u32(&mut writer, 0)?;
+        write_u32(&mut writer, 0)?;
+        // Initial frames
+        write_u32(&mut writer, 0)?;
+        write_u32(&mut writer, FRAME_RATE_DENOMINATOR)?;
+        write_u32(&mut writer, FRAME_RATE_NUMERATOR)?;
+        // Start
+        write_u32(&mut writer, 0)?;
+        let stream_length_pos = writer.stream_position()?;
+        write_u32(&mut writer, 0)?;
+        // Suggested buffer size
+        write_u32(&mut writer, FRAME_SIZE)?;
+        // Default quality
+        write_u32(&mut writer, u32::MAX)?;
+        // Sample size
+        write_u32(&mut writer, FRAME_SIZE)?;
+        // Frame rectangle: left, top, right and bottom as 16 bits each
+        write_u32(&mut writer, 0)?;
+        write_u32(&mut writer, (HEIGHT << 16) | WIDTH)?;
+
+        writer.write_all(b"strf")?;
+        write_u32(&mut writer, BITMAP_INFO_HEADER_SIZE)?;
+        write_u32(&mut writer, BITMAP_INFO_HEADER_SIZE)?;
+        write_u32(&mut writer, WIDTH)?;
+        // Positive means the rows are stored bottom up
+        write_u32(&mut writer, HEIGHT)?;
+        // Planes and bits per pixel
+        write_u16(&mut writer, 1)?;
+        write_u16(&mut writer, 24)?;
+        // Uncompressed
+        write_u32(&mut writer, 0)?;
+        write_u32(&mut writer, FRAME_SIZE)?;
+        // Pixels per meter and palette sizes
+        writer.write_all(&[0; 16])?;
+
+        writer.write_all(b"LIST")?;
+        let movi_size_pos = writer.stream_position()?;
+        write_u32(&mut writer, 0)?;
+        let movi_start = writer.stream_position()?;
+        writer.write_all(b"movi")?;
+
+        Ok(Self {
+            writer,
+            total_frames_pos,
+            stream_length_pos,
+            movi_size_pos,
+            movi_start,
+            index: Vec::new(),
+        })
+    }
+
+    pub fn frame_count(&self) -> u32 {
+        self.index.len() as u32
+    }
+
+    /**
+     * Append a frame of RGB pixels, row by row from the top
+     */
+    pub fn write_frame(&mut self, rgb: &[u8]) -> Result<()> {
+        debug_assert_eq!(rgb.len(), FRAME_SIZE as usize);
+        let chunk_start = self.writer.stream_position()?;
+        // Leave room for the chunk header, the index and the end of the file
+        let index_size = (self.index.len() as u64 + 1) * INDEX_ENTRY_SIZE as u64;
+        if chunk_start + 8 + FRAME_SIZE as u64 + 8 + index_size > MAX_FILE_SIZE {
+            return Err(eyre!(
+                "AVI files can't be larger than 4 GiB. Record a PNG sequence instead"
+            ));
+        }
+
+        self.writer.write_all(b"00db")?;
+        write_u32(&mut self.writer, FRAME_SIZE)?;
+        let row_size = WIDTH as usize * 3;
+        for row in rgb.chunks_exact(row_size).rev() {
+            let bgr: Vec<u8> = row
+                .chunks_exact(3)
+                .flat_map(|pixel| [pixel[2], pixel[1], pixel[0]])
+                .collect();
+            self.writer.write_all(&bgr)?;
+        }
+        self.index.push((chunk_start - self.movi_start) as u32);
+        Ok(())
+    }
+
+    /**
+     * Write the index and fill in the sizes. The file isn't playable before this
+     */
+    pub fn finish(mut self) -> Result<W> {
+        let index_start = self.writer.stream_position()?;
+        self.writer.write_all(b"idx1")?;
+        write_u32(&mut self.writer, self.index.len() as u32 * INDEX_ENTRY_SIZE)?;
+        for &offset in &self.index {
+            self.writer.write_all(b"00db")?;
+            write_u32(&mut self.writer, AVIIF_KEYFRAME)?;
+            write_u32(&mut self.writer, offset)?;
+            write_u32(&mut self.writer, FRAME_SIZE)?;
+        }
+        let file_size = self.writer.stream_position()?;
+
+        let frames = self.frame_count();
+        self.patch_u32(4, file_size as u32 - 8)?;
+        self.patch_u32(self.total_frames_pos, frames)?;
+        self.patch_u32(self.stream_length_pos, frames)?;
+        self.patch_u32(self.movi_size_pos, (index_start - self.movi_start) as u32)?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn patch_u32(&mut self, pos: u64, val: u32) -> io::Result<()> {
+        self.writer.seek(SeekFrom::Start(pos))?;
+        write_u32(&mut self.writer, val)
+    }
+}
+
+fn write_u32(writer: &mut impl Write, val: u32) -> io::Result<()> {
+    writer.write_all(&val.to_le_bytes())
+}
+
+fn write_u16(writer: &mut impl Write, val: u16) -> io::Result<()> {
+    writer.write_all(&val.to_le_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn read_u32(data: &[u8], pos: usize) -> u32 {
+        u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn writes_sizes_and_index_on_finish() {
+        let mut avi = AviWriter::new(Cursor::new(Vec::new())).unwrap();
+        let mut frame = vec![0; FRAME_SIZE as usize];
+        // Top left pixel is red
+        frame[0] = 0xFF;
+        avi.write_frame(&frame).unwrap();
+        avi.write_frame(&frame).unwrap();
+        let data = avi.finish().unwrap().into_inner();
+
+        assert_eq!(&data[..4], b"RIFF");
+        assert_eq!(read_u32(&data, 4) as usize, data.len() - 8);
+        // Total frames in the main header and length in the stream header
+        assert_eq!(read_u32(&data, 48), 2);
+        assert_eq!(read_u32(&data, 140), 2);
+
+        let movi_start = 220;
+        assert_eq!(&data[movi_start..movi_start + 4], b"movi");
+        let first_frame = movi_start + 4 + 8;
+        // Bottom up and BGR, so the top left pixel is in the last row
+        let last_row = first_frame + (FRAME_SIZE - WIDTH * 3) as usize;
+        assert_eq!(data[last_row..last_row + 3], [0, 0, 0xFF]);
+
+        let index_start = movi_start + read_u32(&data, movi_start - 4) as usize;
+        assert_eq!(&data[index_start..index_start + 4], b"idx1");
+        assert_eq!(read_u32(&data, index_start + 4), 2 * INDEX_ENTRY_SIZE);
+        // Offset of the second frame chunk
+        assert_eq!(
+            read_u32(&data, index_start + 8 + 16 + 8),
+            4 + 8 + FRAME_SIZE
+        );
+    }
+}
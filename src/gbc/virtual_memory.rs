@@ -1,22 +1,31 @@
 mod memory_area;
 mod memory_bank_controller;
+mod watchpoint;
 
-use std::{borrow::Cow, cmp::min};
+use std::{borrow::Cow, cell::Cell, cmp::min};
 
 use color_eyre::eyre::Result;
 use enum_map::{enum_map, EnumMap};
-use tracing::{debug, debug_span, error, info_span, trace};
+use tracing::error;
 
-use crate::{gbc::virtual_memory::memory_area::MemoryPermission, util::index_bits};
+use crate::{
+    gbc::virtual_memory::memory_area::MemoryPermission,
+    util::{hot_debug_span, index_bits},
+};
 
 use self::{
-    memory_area::{MemoryArea, MemoryAreaName},
+    memory_area::MemoryArea,
     memory_bank_controller::{get_num_ext_ram_banks, get_num_rom_banks, MBC},
 };
 
+pub use self::memory_area::MemoryAreaName;
+pub use self::watchpoint::{WatchHit, WatchKind, Watchpoint};
+
 use super::{
-    dma_controller,
+    dma_controller, interrupt_controller,
+    joypad_controller::{self, JOYPAD_REGISTER},
     lcd_controller::{self, LCD_STATUS_REGISTER, LCD_Y_COORDINATE_REGISTER, LY_COMPARE_REGISTER},
+    serial_controller::{self, SERIAL_CONTROL_REGISTER},
     timer_controller::{self, DIVIDER_REGISTER, TIMER_CONTROL_REGISTER},
     GBCState,
 };
@@ -59,6 +68,11 @@ const HIGH_RAM_ADDR_END: u16 = 0xFFFE;
 
 const IE_REGISTER_ADDR: u16 = 0xFFFF;
 
+// The boot ROM is mapped over these while enabled. The cartridge header in between stays visible
+const BOOT_ROM_ADDR_END: u16 = 0x00FF;
+const CGB_BOOT_ROM_ADDR: u16 = 0x0200;
+const CGB_BOOT_ROM_ADDR_END: u16 = 0x08FF;
+
 /**
  * Registers that trigger behaviors when written to
  */
@@ -68,6 +82,7 @@ const OAM_DMA_REGISTER: u16 = 0xFF46;
 pub const VRAM_DMA_REGISTER: u16 = 0xFF55;
 const BG_PALETTE_INDEX_REGISTER: u16 = 0xFF68;
 const OBJ_PALETTE_INDEX_REGISTER: u16 = 0xFF6A;
+const BOOT_ROM_DISABLE_REGISTER: u16 = 0xFF50;
 
 /**
  * ROM Data Addresses
@@ -75,19 +90,80 @@ const OBJ_PALETTE_INDEX_REGISTER: u16 = 0xFF6A;
 const ROM_SIZE_ADDR: u16 = 0x0148;
 const EXT_RAM_SIZE_ADDR: u16 = 0x0149;
 
+const PAGE_SIZE: usize = 0x100;
+const NUM_PAGES: usize = 0x100;
+
+/**
+ * Where a 256 byte page of the address space lives. Lets reads and writes of plain memory skip
+ * address decoding and write triggers.
+ */
+#[derive(Clone, Copy)]
+enum Page {
+    // Backed by the data of a memory area, starting at the offset
+    Direct { area: MemoryAreaName, offset: usize },
+    // Reads return 0xFF and writes are ignored
+    Unmapped,
+    // Mixes memory areas or has side effects. Has to go through map_memory
+    Slow,
+}
+
+/**
+ * A ROM byte replaced by a Game Genie code. Only replaced while the ROM holds `compare` there, if
+ * given, since the same address is shared by every bank
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RomPatch {
+    pub addr: u16,
+    pub val: u8,
+    pub compare: Option<u8>,
+}
+
+/**
+ * Bank selection and MBC registers at the time of a snapshot. The memory contents are
+ * saved separately as raw bytes so they can be delta compressed.
+ */
+#[derive(Clone, Hash)]
+pub struct MemorySnapshot {
+    banking: EnumMap<MemoryAreaName, (usize, MemoryPermission)>,
+    mbc: Box<dyn MBC>,
+    boot_rom_mapped: bool,
+}
+
 pub struct VirtualMemory {
     areas: EnumMap<MemoryAreaName, MemoryArea>,
     mbc: Box<dyn MBC>,
+    // Indexed by the upper byte of the address. Updated whenever banking or permissions change
+    read_pages: [Page; NUM_PAGES],
+    write_pages: [Page; NUM_PAGES],
+    // Empty if the emulator was started without a boot ROM
+    boot_rom: Vec<u8>,
+    // Boot ROM is overlaid on cartridge ROM until 0xFF50 is written
+    boot_rom_mapped: bool,
+    // Game Genie patches. ROM pages holding one are read through read_slow
+    rom_patches: Vec<RomPatch>,
+    watchpoints: Vec<Watchpoint>,
+    // Set while the CPU executes an instruction and there are watchpoints to check
+    watchpoints_armed: bool,
+    // First watchpoint hit since it was last taken. A Cell because reads only borrow the state
+    watch_hit: Cell<Option<WatchHit>>,
 }
 
 impl VirtualMemory {
-    pub fn new(rom_data: Vec<u8>) -> Result<Self> {
+    pub fn new(rom_data: Vec<u8>, boot_rom: Option<Vec<u8>>) -> Result<Self> {
         let num_rom_banks = get_num_rom_banks(&rom_data)?;
         let num_ext_ram_banks = get_num_ext_ram_banks(&rom_data)?;
         let mbc = memory_bank_controller::build_mbc(&rom_data)?;
 
         let mut vm = Self {
             mbc,
+            read_pages: [Page::Slow; NUM_PAGES],
+            write_pages: [Page::Slow; NUM_PAGES],
+            boot_rom_mapped: boot_rom.is_some(),
+            boot_rom: boot_rom.unwrap_or_default(),
+            rom_patches: Vec::new(),
+            watchpoints: Vec::new(),
+            watchpoints_armed: false,
+            watch_hit: Cell::new(None),
             areas: enum_map! {
                 MemoryAreaName::PrgRomFixed => MemoryArea::new(
                     PRG_ROM_FIXED_ADDR,
@@ -162,10 +238,82 @@ impl VirtualMemory {
         // Initialize palette mem
         vm.areas[MemoryAreaName::BGPalette].fill_from_src(&[0xFF; 64]);
         vm.areas[MemoryAreaName::OBJPalette].fill_from_src(&[0xFF; 64]);
+
+        for area in PAGED_AREAS {
+            update_pages(&mut vm, area);
+        }
         Ok(vm)
     }
 }
 
+// Memory areas that cover whole pages and can be accessed through the page tables
+const PAGED_AREAS: [MemoryAreaName; 6] = [
+    MemoryAreaName::PrgRomFixed,
+    MemoryAreaName::PrgRomBanked,
+    MemoryAreaName::Vram,
+    MemoryAreaName::ExternalRam,
+    MemoryAreaName::WorkRamFixed,
+    MemoryAreaName::WorkRamBanked,
+];
+
+/**
+ * Point the pages of a memory area at its active bank. Must be called after changing the active
+ * bank or permission of any area in PAGED_AREAS.
+ */
+fn update_pages(mem: &mut VirtualMemory, name: MemoryAreaName) {
+    let area = &mem.areas[name];
+    let (readable, writable) = match area.get_permission() {
+        MemoryPermission::None => (false, false),
+        MemoryPermission::ReadOnly => (true, false),
+        MemoryPermission::ReadAndWrite => (true, true),
+    };
+
+    let first_page = area.get_start_addr() as usize / PAGE_SIZE;
+    let last_page = area.get_end_addr() as usize / PAGE_SIZE;
+    for page_idx in first_page..=last_page {
+        let offset = area.translate_virtual_address_to_data_index((page_idx * PAGE_SIZE) as u16);
+        // Banks past the end of the data (e.g. cartridges without RAM) read as open bus
+        let page = match offset + PAGE_SIZE <= area.borrow_raw_data().len() {
+            true => Page::Direct { area: name, offset },
+            false => Page::Unmapped,
+        };
+
+        mem.read_pages[page_idx] = match readable {
+            // Reads have to check for the boot ROM overlay
+            true if matches!(name, MemoryAreaName::PrgRomFixed)
+                && is_boot_rom_page(mem, page_idx) =>
+            {
+                Page::Slow
+            }
+            true if is_patched_rom_page(mem, page_idx) => Page::Slow,
+            true => page,
+            false => Page::Unmapped,
+        };
+        mem.write_pages[page_idx] = match name {
+            // Writes to ROM set MBC registers
+            MemoryAreaName::PrgRomFixed | MemoryAreaName::PrgRomBanked => Page::Slow,
+            _ if writable => page,
+            _ => Page::Unmapped,
+        };
+    }
+}
+
+fn is_patched_rom_page(mem: &VirtualMemory, page_idx: usize) -> bool {
+    mem.rom_patches
+        .iter()
+        .any(|patch| patch.addr as usize / PAGE_SIZE == page_idx)
+}
+
+fn is_boot_rom_addr(mem: &VirtualMemory, addr: u16) -> bool {
+    mem.boot_rom_mapped
+        && (addr as usize) < mem.boot_rom.len()
+        && matches!(addr, 0..=BOOT_ROM_ADDR_END | CGB_BOOT_ROM_ADDR..=CGB_BOOT_ROM_ADDR_END)
+}
+
+fn is_boot_rom_page(mem: &VirtualMemory, page_idx: usize) -> bool {
+    is_boot_rom_addr(mem, (page_idx * PAGE_SIZE) as u16)
+}
+
 fn map_memory(addr: u16) -> MemoryAreaName {
     match addr {
         PRG_ROM_FIXED_ADDR..=PRG_ROM_FIXED_ADDR_END => MemoryAreaName::PrgRomFixed,
@@ -202,6 +350,7 @@ fn preprocess_value(state: &GBCState, addr: u16, val: u8) -> u8 {
         }
         // Writing any value to divider register resets the register
         DIVIDER_REGISTER => 0,
+        JOYPAD_REGISTER => joypad_controller::preprocess_joypad_register(state, val),
         _ => val,
     }
 }
@@ -219,15 +368,19 @@ fn handle_write_triggered_events(state: &mut GBCState, addr: u16, val: u8) {
                 .mem
                 .mbc
                 .write_register(&mut state.mem.areas, addr, val);
+            update_pages(&mut state.mem, MemoryAreaName::PrgRomBanked);
+            update_pages(&mut state.mem, MemoryAreaName::ExternalRam);
         }
         WORK_RAM_BANK_REGISTER => {
             // First 3 bits hold the flags. Both 0 and 1 mean the first bank
             let active_bank = (val & 0x07).saturating_sub(1).into();
             state.mem.areas[MemoryAreaName::WorkRamBanked].set_active_bank(active_bank);
+            update_pages(&mut state.mem, MemoryAreaName::WorkRamBanked);
         }
         VRAM_BANK_REGISTER => {
             let active_bank = (val & 0x01).into();
             state.mem.areas[MemoryAreaName::Vram].set_active_bank(active_bank);
+            update_pages(&mut state.mem, MemoryAreaName::Vram);
         }
         OAM_DMA_REGISTER => dma_controller::trigger_oam_transfer(state, val),
         VRAM_DMA_REGISTER => dma_controller::trigger_vram_transfer(state, val),
@@ -235,6 +388,8 @@ fn handle_write_triggered_events(state: &mut GBCState, addr: u16, val: u8) {
         LY_COMPARE_REGISTER | LCD_Y_COORDINATE_REGISTER => {
             lcd_controller::update_lyc_match_ly_check(state)
         }
+        // STAT interrupt sources may have changed
+        LCD_STATUS_REGISTER => interrupt_controller::update_stat_interrupt_line(state),
         // Set the palette index
         BG_PALETTE_INDEX_REGISTER => {
             let palette_idx = (val & 0x3F).into();
@@ -272,30 +427,89 @@ fn handle_write_triggered_events(state: &mut GBCState, addr: u16, val: u8) {
             }
         }
         TIMER_CONTROL_REGISTER => timer_controller::set_timer_control_register(state, val),
+        SERIAL_CONTROL_REGISTER => serial_controller::set_serial_control_register(state, val),
+        JOYPAD_REGISTER => joypad_controller::update_joypad_register(state),
+        // The boot ROM unmaps itself right before jumping to the cartridge. It can't be mapped
+        // again
+        BOOT_ROM_DISABLE_REGISTER if val != 0 && state.mem.boot_rom_mapped => {
+            state.mem.boot_rom_mapped = false;
+            update_pages(&mut state.mem, MemoryAreaName::PrgRomFixed);
+        }
         _ => {}
     };
 }
 
 pub fn read(state: &GBCState, addr: u16) -> u8 {
-    let span = debug_span!("VM Read", addr = format!("{:#06x}", addr)).entered();
+    if state.mem.watchpoints_armed {
+        check_watchpoints(state, addr, false);
+    }
+    match state.mem.read_pages[addr as usize / PAGE_SIZE] {
+        Page::Direct { area, offset } => {
+            state.mem.areas[area].borrow_raw_data()[offset + addr as usize % PAGE_SIZE]
+        }
+        Page::Unmapped => 0xFF,
+        Page::Slow => read_slow(state, addr),
+    }
+}
+
+fn read_slow(state: &GBCState, addr: u16) -> u8 {
+    if is_boot_rom_addr(&state.mem, addr) {
+        return state.mem.boot_rom[addr as usize];
+    }
+
+    let span = hot_debug_span!("VM Read", addr = format!("{:#06x}", addr));
 
     let area = map_memory(addr);
-    let read_val = state.mem.areas[area].read(addr);
+    let mut read_val = state.mem.areas[area].read(addr);
+    let patch = state.mem.rom_patches.iter().find(|patch| {
+        patch.addr == addr && patch.compare.is_none_or(|compare| compare == read_val)
+    });
+    if let Some(patch) = patch {
+        read_val = patch.val;
+    }
 
     span.exit();
     read_val
 }
 
+/**
+ * Which memory area an address belongs to. None for echo RAM, which isn't emulated
+ */
+pub fn memory_area_at(addr: u16) -> Option<MemoryAreaName> {
+    match addr {
+        0xE000..=0xFDFF => None,
+        _ => Some(map_memory(addr)),
+    }
+}
+
+/**
+ * (active bank, number of banks) of a memory area
+ */
+pub fn area_banks(state: &GBCState, area: MemoryAreaName) -> (usize, usize) {
+    let area = &state.mem.areas[area];
+    (area.get_active_bank(), area.get_num_banks())
+}
+
+/**
+ * Active bank of a memory area numbered the way RGBDS numbers it. Switchable work RAM starts at
+ * bank 1 there. Areas without bank switching are always bank 0
+ */
+pub fn symbol_bank(state: &GBCState, area: MemoryAreaName) -> u16 {
+    let active_bank = state.mem.areas[area].get_active_bank() as u16;
+    match area {
+        MemoryAreaName::PrgRomBanked | MemoryAreaName::Vram | MemoryAreaName::ExternalRam => {
+            active_bank
+        }
+        MemoryAreaName::WorkRamBanked => active_bank + 1,
+        _ => 0,
+    }
+}
+
 /**
  * Directly read from a specific bank without writing to bank register
  */
-pub fn read_override_bank(state: &mut GBCState, addr: u16, bank: usize) -> u8 {
-    let area = &mut state.mem.areas[map_memory(addr)];
-    let original_bank = area.get_active_bank();
-    area.set_active_bank(bank);
-    let read_val = area.read(addr);
-    area.set_active_bank(original_bank);
-    read_val
+pub fn read_override_bank(state: &GBCState, addr: u16, bank: usize) -> u8 {
+    state.mem.areas[map_memory(addr)].read_from_bank(addr, bank)
 }
 
 pub fn read_bytes(state: &GBCState, addr: u16, length_bytes: usize) -> Cow<[u8]> {
@@ -320,14 +534,22 @@ pub fn read_bytes(state: &GBCState, addr: u16, length_bytes: usize) -> Cow<[u8]>
 }
 
 pub fn write(state: &mut GBCState, addr: u16, val: u8) {
+    if state.mem.watchpoints_armed {
+        check_watchpoints(state, addr, true);
+    }
+    if !write_direct(state, addr, val) {
+        write_slow(state, addr, val);
+    }
+}
+
+fn write_slow(state: &mut GBCState, addr: u16, val: u8) {
     let area = map_memory(addr);
-    let span = debug_span!(
+    let span = hot_debug_span!(
         "VM Write",
         addr = format!("{:#06x}", addr),
         bank = state.mem.areas[area].get_active_bank(),
         value = format!("{:#04x}", val),
-    )
-    .entered();
+    );
 
     let val = preprocess_value(state, addr, val);
     state.mem.areas[area].write(addr, val);
@@ -341,20 +563,44 @@ pub fn write(state: &mut GBCState, addr: u16, val: u8) {
  * setting values.
  */
 pub fn write_without_triggers(state: &mut GBCState, addr: u16, val: u8) {
+    if write_direct(state, addr, val) {
+        return;
+    }
+
     let area = map_memory(addr);
-    let span = debug_span!(
+    let span = hot_debug_span!(
         "VM Write (no trigger)",
         addr = format!("{:#06x}", addr),
         bank = state.mem.areas[area].get_active_bank(),
         value = format!("{:#04x}", val),
-    )
-    .entered();
+    );
 
     state.mem.areas[area].write(addr, val);
 
     span.exit();
 }
 
+/**
+ * Directly write to a specific bank without writing to bank register or running write triggers
+ */
+pub fn write_override_bank(state: &mut GBCState, addr: u16, bank: usize, val: u8) {
+    state.mem.areas[map_memory(addr)].write_to_bank(addr, bank, val);
+}
+
+/**
+ * Write through the page table. Returns false if the address needs the slow path
+ */
+fn write_direct(state: &mut GBCState, addr: u16, val: u8) -> bool {
+    match state.mem.write_pages[addr as usize / PAGE_SIZE] {
+        Page::Direct { area, offset } => {
+            state.mem.areas[area].borrow_raw_data_mut()[offset + addr as usize % PAGE_SIZE] = val;
+            true
+        }
+        Page::Unmapped => true,
+        Page::Slow => false,
+    }
+}
+
 pub fn write_bytes(state: &mut GBCState, addr: u16, vals: &[u8]) {
     let area_name = map_memory(addr);
     let area = &mut state.mem.areas[area_name];
@@ -371,6 +617,212 @@ pub fn write_bytes(state: &mut GBCState, addr: u16, vals: &[u8]) {
     }
 }
 
+/**
+ * Replace the patches applied to ROM reads
+ */
+pub fn set_rom_patches(state: &mut GBCState, patches: Vec<RomPatch>) {
+    state.mem.rom_patches = patches;
+    update_pages(&mut state.mem, MemoryAreaName::PrgRomFixed);
+    update_pages(&mut state.mem, MemoryAreaName::PrgRomBanked);
+}
+
+pub fn rom_patches(state: &GBCState) -> &[RomPatch] {
+    &state.mem.rom_patches
+}
+
+pub fn add_watchpoint(state: &mut GBCState, watchpoint: Watchpoint) {
+    state.mem.watchpoints.push(watchpoint);
+}
+
+/**
+ * Returns false if there was no such watchpoint
+ */
+pub fn remove_watchpoint(state: &mut GBCState, watchpoint: &Watchpoint) -> bool {
+    let len = state.mem.watchpoints.len();
+    state.mem.watchpoints.retain(|w| w != watchpoint);
+    state.mem.watchpoints.len() != len
+}
+
+/**
+ * Start or stop checking reads and writes against the watchpoints
+ */
+pub fn arm_watchpoints(state: &mut GBCState, armed: bool) {
+    state.mem.watchpoints_armed = armed && !state.mem.watchpoints.is_empty();
+}
+
+pub fn take_watch_hit(state: &mut GBCState) -> Option<WatchHit> {
+    state.mem.watch_hit.take()
+}
+
+fn check_watchpoints(state: &GBCState, addr: u16, write: bool) {
+    let mem = &state.mem;
+    if mem.watch_hit.get().is_none() {
+        mem.watch_hit
+            .set(watchpoint::find_hit(&mem.watchpoints, addr, write));
+    }
+}
+
 pub fn borrow_palette_mem(state: &GBCState) -> &[u8] {
     state.mem.areas[MemoryAreaName::BGPalette].borrow_raw_data()
 }
+
+pub fn borrow_obj_palette_mem(state: &GBCState) -> &[u8] {
+    state.mem.areas[MemoryAreaName::OBJPalette].borrow_raw_data()
+}
+
+pub fn borrow_palette_mem_mut(state: &mut GBCState) -> &mut [u8] {
+    state.mem.areas[MemoryAreaName::BGPalette].borrow_raw_data_mut()
+}
+
+pub fn borrow_obj_palette_mem_mut(state: &mut GBCState) -> &mut [u8] {
+    state.mem.areas[MemoryAreaName::OBJPalette].borrow_raw_data_mut()
+}
+
+/**
+ * Raw IO register memory, 0xFF00-0xFF7F
+ */
+pub fn borrow_io_registers(state: &GBCState) -> &[u8] {
+    state.mem.areas[MemoryAreaName::IORegisters].borrow_raw_data()
+}
+
+/**
+ * Both VRAM banks, bank 0 first
+ */
+pub fn borrow_vram(state: &GBCState) -> &[u8] {
+    state.mem.areas[MemoryAreaName::Vram].borrow_raw_data()
+}
+
+pub fn borrow_oam(state: &GBCState) -> &[u8] {
+    state.mem.areas[MemoryAreaName::Oam].borrow_raw_data()
+}
+
+pub fn borrow_external_ram(state: &GBCState) -> &[u8] {
+    state.mem.areas[MemoryAreaName::ExternalRam].borrow_raw_data()
+}
+
+/**
+ * Overwrite all banks of external RAM, e.g. to carry cartridge RAM over a reset
+ */
+pub fn fill_external_ram(state: &mut GBCState, data: &[u8]) {
+    state.mem.areas[MemoryAreaName::ExternalRam].fill_from_src(data);
+}
+
+// ROM can't change so it is never saved in snapshots
+fn is_saved_in_snapshot(area: MemoryAreaName) -> bool {
+    !matches!(
+        area,
+        MemoryAreaName::PrgRomFixed | MemoryAreaName::PrgRomBanked
+    )
+}
+
+/**
+ * Save bank selection and MBC state. The contents of all writable memory areas are appended to
+ * `data`
+ */
+pub fn save_snapshot(state: &GBCState, data: &mut Vec<u8>) -> MemorySnapshot {
+    let areas = &state.mem.areas;
+    for (name, area) in areas {
+        if is_saved_in_snapshot(name) {
+            data.extend_from_slice(area.borrow_raw_data());
+        }
+    }
+    MemorySnapshot {
+        banking: enum_map! {
+            name => (areas[name].get_active_bank(), areas[name].get_permission())
+        },
+        mbc: state.mem.mbc.clone(),
+        boot_rom_mapped: state.mem.boot_rom_mapped,
+    }
+}
+
+/**
+ * Restore a snapshot made by save_snapshot. Memory contents are consumed from the front of `data`
+ */
+pub fn load_snapshot(state: &mut GBCState, snapshot: &MemorySnapshot, data: &mut &[u8]) {
+    for (name, area) in &mut state.mem.areas {
+        let (active_bank, permission) = snapshot.banking[name];
+        area.set_active_bank(active_bank);
+        area.set_permission(permission);
+        if is_saved_in_snapshot(name) {
+            let (area_data, rest) = data.split_at(area.borrow_raw_data().len());
+            area.fill_from_src(area_data);
+            *data = rest;
+        }
+    }
+    state.mem.mbc = snapshot.mbc.clone();
+    state.mem.boot_rom_mapped = snapshot.boot_rom_mapped;
+    for area in PAGED_AREAS {
+        update_pages(&mut state.mem, area);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gbc::{GBCConfig, Model};
+
+    #[test]
+    fn bank_switch_updates_page_table() {
+        let mut state = GBCState::with_program(&[]);
+        write(&mut state, 0x8010, 0xAA);
+        write(&mut state, 0xD010, 0xBB);
+
+        write(&mut state, VRAM_BANK_REGISTER, 0x01);
+        write(&mut state, WORK_RAM_BANK_REGISTER, 0x03);
+        assert_eq!(read(&state, 0x8010), 0x00);
+        assert_eq!(read(&state, 0xD010), 0x00);
+        write(&mut state, 0x8010, 0x11);
+        assert_eq!(read_override_bank(&state, 0x8010, 0), 0xAA);
+
+        write(&mut state, VRAM_BANK_REGISTER, 0x00);
+        write(&mut state, WORK_RAM_BANK_REGISTER, 0x01);
+        assert_eq!(read(&state, 0x8010), 0xAA);
+        assert_eq!(read(&state, 0xD010), 0xBB);
+        assert_eq!(read_override_bank(&state, 0x8010, 1), 0x11);
+    }
+
+    #[test]
+    fn override_bank_past_the_last_bank_is_ignored() {
+        let mut state = GBCState::with_program(&[]);
+        write_override_bank(&mut state, 0x8010, 2, 0xAA);
+        write_override_bank(&mut state, 0xD010, 7, 0xBB);
+        assert_eq!(read_override_bank(&state, 0x8010, 2), 0xFF);
+        assert_eq!(read_override_bank(&state, 0x8010, 0), 0x00);
+        assert_eq!(read_override_bank(&state, 0x8010, 1), 0x00);
+        assert_eq!(read_override_bank(&state, 0xD010, 6), 0x00);
+    }
+
+    #[test]
+    fn rom_and_disabled_ram_are_not_writable() {
+        let mut state = GBCState::with_program(&[0x3C]);
+        write(&mut state, 0x0100, 0x00);
+        assert_eq!(read(&state, 0x0100), 0x3C);
+        // Cartridge has no external RAM
+        write(&mut state, EXTERNAL_RAM_ADDR, 0x12);
+        assert_eq!(read(&state, EXTERNAL_RAM_ADDR), 0xFF);
+    }
+
+    #[test]
+    fn boot_rom_is_unmapped_by_register_write() {
+        let mut rom_data = vec![0x00; 0x8000];
+        rom_data[0x0000] = 0xAA;
+        rom_data[0x0100] = 0xBB;
+        rom_data[0x08FF] = 0xAA;
+        rom_data[0x0900] = 0xAA;
+        let config = GBCConfig {
+            model: Model::CGB,
+            boot_rom: Some(vec![0x55; 0x0900]),
+            ..GBCConfig::default()
+        };
+        let mut state = GBCState::new_headless(rom_data, &config).unwrap();
+        assert_eq!(read(&state, 0x0000), 0x55);
+        // Cartridge header stays visible
+        assert_eq!(read(&state, 0x0100), 0xBB);
+        assert_eq!(read(&state, 0x08FF), 0x55);
+        assert_eq!(read(&state, 0x0900), 0xAA);
+
+        write(&mut state, BOOT_ROM_DISABLE_REGISTER, 0x01);
+        assert_eq!(read(&state, 0x0000), 0xAA);
+        assert_eq!(read(&state, 0x08FF), 0xAA);
+    }
+}
@@ -1,9 +1,11 @@
 use std::borrow::Cow;
 
 use enum_map::Enum;
-use tracing::{error, trace};
+use tracing::error;
 
-#[derive(Enum, Clone, Copy)]
+use crate::util::hot_trace;
+
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
 pub enum MemoryAreaName {
     PrgRomFixed,
     PrgRomBanked,
@@ -20,6 +22,7 @@ pub enum MemoryAreaName {
     IERegister,
 }
 
+#[derive(Clone, Copy, Hash)]
 pub enum MemoryPermission {
     None,
     ReadOnly,
@@ -61,8 +64,16 @@ impl MemoryArea {
     }
 
     // Convert the u16 virtual address to an index in the data vec
-    fn translate_virtual_address_to_data_index(&self, addr: u16) -> usize {
-        (addr - self.start_addr) as usize + (self.bank_size * self.active_bank)
+    pub(super) fn translate_virtual_address_to_data_index(&self, addr: u16) -> usize {
+        self.translate_virtual_address_to_bank_index(addr, self.active_bank)
+    }
+
+    fn translate_virtual_address_to_bank_index(&self, addr: u16, bank: usize) -> usize {
+        (addr - self.start_addr) as usize + (self.bank_size * bank)
+    }
+
+    pub(super) fn get_start_addr(&self) -> u16 {
+        self.start_addr
     }
 
     pub(super) fn get_end_addr(&self) -> u16 {
@@ -73,10 +84,18 @@ impl MemoryArea {
         self.active_bank
     }
 
+    pub(super) fn get_num_banks(&self) -> usize {
+        self.num_banks
+    }
+
     pub(super) fn set_active_bank(&mut self, active_bank: usize) {
         self.active_bank = active_bank;
     }
 
+    pub(super) fn get_permission(&self) -> MemoryPermission {
+        self.permission
+    }
+
     pub(super) fn set_permission(&mut self, permission: MemoryPermission) {
         self.permission = permission;
     }
@@ -89,6 +108,18 @@ impl MemoryArea {
         self.data[idx]
     }
 
+    // Read from a bank other than the active one
+    pub(super) fn read_from_bank(&self, addr: u16, bank: usize) -> u8 {
+        if let MemoryPermission::None = self.permission {
+            return 0xFF;
+        }
+        if bank >= self.num_banks {
+            error!("Read from bank {} of {}", bank, self.num_banks);
+            return 0xFF;
+        }
+        self.data[self.translate_virtual_address_to_bank_index(addr, bank)]
+    }
+
     // Return cow which lets us return either a borrowed or owned value
     pub(super) fn read_bytes(&self, addr: u16, length_bytes: usize) -> Cow<[u8]> {
         if let MemoryPermission::None = self.permission {
@@ -107,7 +138,20 @@ impl MemoryArea {
         if idx >= self.data.len() {
             error!("Invalid Write Index");
         }
-        trace!("Wrote value {:#04x} to {:#06x}", val, addr);
+        hot_trace!("Wrote value {:#04x} to {:#06x}", val, addr);
+        self.data[idx] = val;
+    }
+
+    // Write to a bank other than the active one
+    pub(super) fn write_to_bank(&mut self, addr: u16, bank: usize, val: u8) {
+        if let MemoryPermission::ReadOnly | MemoryPermission::None = self.permission {
+            return;
+        }
+        if bank >= self.num_banks {
+            error!("Write to bank {} of {}", bank, self.num_banks);
+            return;
+        }
+        let idx = self.translate_virtual_address_to_bank_index(addr, bank);
         self.data[idx] = val;
     }
 
@@ -127,4 +171,8 @@ impl MemoryArea {
     pub(super) fn borrow_raw_data(&self) -> &[u8] {
         &self.data
     }
+
+    pub(super) fn borrow_raw_data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
 }
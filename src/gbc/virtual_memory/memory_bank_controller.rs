@@ -1,4 +1,7 @@
-use std::cmp::max;
+use std::{
+    cmp::max,
+    hash::{Hash, Hasher},
+};
 
 use color_eyre::eyre::{bail, ensure, Result};
 use enum_map::EnumMap;
@@ -14,7 +17,7 @@ use super::{
 const CARTRIDGE_TYPE_ADDR: u16 = 0x0147;
 
 #[repr(u8)]
-#[derive(Clone, Copy, IntEnum)]
+#[derive(Clone, Copy, IntEnum, Hash)]
 enum BankSelectMode {
     UpperROM = 0,
     RAM = 1,
@@ -27,8 +30,27 @@ pub trait MBC {
         addr: u16,
         val: u8,
     );
+
+    // Trait objects can't derive Clone. Needed for snapshotting
+    fn clone_box(&self) -> Box<dyn MBC>;
+
+    // Same for Hash. Needed for state hashes
+    fn hash_box(&self, state: &mut dyn Hasher);
+}
+
+impl Clone for Box<dyn MBC> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
 }
 
+impl Hash for Box<dyn MBC> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.hash_box(state)
+    }
+}
+
+#[derive(Clone, Hash)]
 struct NoMBC {}
 impl MBC for NoMBC {
     fn write_register(
@@ -39,8 +61,17 @@ impl MBC for NoMBC {
     ) {
         // Do nothing
     }
+
+    fn clone_box(&self) -> Box<dyn MBC> {
+        Box::new(self.clone())
+    }
+
+    fn hash_box(&self, mut state: &mut dyn Hasher) {
+        self.hash(&mut state)
+    }
 }
 
+#[derive(Clone, Hash)]
 struct MBC1 {
     // 5 bit bank select. Both 0x0 and 0x1 map to the first (non-fixed) bank
     rom_bank_select: u8,
@@ -98,8 +129,17 @@ impl MBC for MBC1 {
         mem_areas[MemoryAreaName::PrgRomBanked].set_active_bank(rom_bank.into());
         mem_areas[MemoryAreaName::ExternalRam].set_active_bank(ram_bank.into());
     }
+
+    fn clone_box(&self) -> Box<dyn MBC> {
+        Box::new(self.clone())
+    }
+
+    fn hash_box(&self, mut state: &mut dyn Hasher) {
+        self.hash(&mut state)
+    }
 }
 
+#[derive(Clone, Hash)]
 struct MBC5 {
     rom_bank_select_low: u8,
     // Upper 1 bit of rom bank select
@@ -141,6 +181,14 @@ impl MBC for MBC5 {
         mem_areas[MemoryAreaName::PrgRomBanked].set_active_bank(rom_bank);
         mem_areas[MemoryAreaName::ExternalRam].set_active_bank(self.ram_bank_select.into());
     }
+
+    fn clone_box(&self) -> Box<dyn MBC> {
+        Box::new(self.clone())
+    }
+
+    fn hash_box(&self, mut state: &mut dyn Hasher) {
+        self.hash(&mut state)
+    }
 }
 
 pub(super) fn build_mbc(rom_data: &Vec<u8>) -> Result<Box<dyn MBC>> {
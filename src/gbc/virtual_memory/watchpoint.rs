@@ -0,0 +1,51 @@
+use std::ops::RangeInclusive;
+
+/**
+ * Which accesses to a watched address stop execution
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    // Either a read or a write
+    Access,
+}
+
+impl WatchKind {
+    fn matches(&self, write: bool) -> bool {
+        match self {
+            WatchKind::Read => !write,
+            WatchKind::Write => write,
+            WatchKind::Access => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub addrs: RangeInclusive<u16>,
+    pub kind: WatchKind,
+}
+
+/**
+ * Access that triggered a watchpoint
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    pub addr: u16,
+    // Kind of the watchpoint that was hit, not of the access
+    pub kind: WatchKind,
+}
+
+/**
+ * First watchpoint that an access to addr triggers
+ */
+pub fn find_hit(watchpoints: &[Watchpoint], addr: u16, write: bool) -> Option<WatchHit> {
+    watchpoints
+        .iter()
+        .find(|watchpoint| watchpoint.addrs.contains(&addr) && watchpoint.kind.matches(write))
+        .map(|watchpoint| WatchHit {
+            addr,
+            kind: watchpoint.kind,
+        })
+}
@@ -1,36 +1,452 @@
+mod cheat_manager;
+mod disassembly_viewer;
+mod io_register_viewer;
+mod memory_viewer;
+mod oam_viewer;
+mod palette_viewer;
+mod profiler_viewer;
+mod tile_map_viewer;
+mod tile_viewer;
+
 use eframe::egui::{self, Context, Ui};
 
+use gbc_emulator::gbc::{
+    Button, Buttons, EmulationSpeed, GBCCommand, GBCEvent, RecordingFormat, GBC_RESOLUTION_X,
+    GBC_RESOLUTION_Y, MAX_SCREENSHOT_SCALE, MOVIE_EXTENSION,
+};
+
 use crate::App;
 
+pub use self::cheat_manager::CheatManager;
+pub use self::disassembly_viewer::DisassemblyViewer;
+pub use self::io_register_viewer::IORegisterViewer;
+pub use self::memory_viewer::MemoryViewer;
+pub use self::oam_viewer::OAMViewer;
+pub use self::palette_viewer::PaletteViewer;
+pub use self::profiler_viewer::ProfilerViewer;
+pub use self::tile_map_viewer::TileMapViewer;
+pub use self::tile_viewer::TileViewer;
+
+const FAST_FORWARD_KEY: egui::Key = egui::Key::Tab;
+const REWIND_KEY: egui::Key = egui::Key::Backspace;
+const SCREENSHOT_KEY: egui::Key = egui::Key::F12;
+const JOYPAD_KEYS: [(egui::Key, Button); 8] = [
+    (egui::Key::ArrowRight, Button::Right),
+    (egui::Key::ArrowLeft, Button::Left),
+    (egui::Key::ArrowUp, Button::Up),
+    (egui::Key::ArrowDown, Button::Down),
+    (egui::Key::X, Button::A),
+    (egui::Key::Z, Button::B),
+    (egui::Key::Space, Button::Select),
+    (egui::Key::Enter, Button::Start),
+];
+const SPEED_MULTIPLIERS: [f32; 9] = [0.25, 0.5, 0.75, 1.0, 1.5, 2.0, 3.0, 4.0, 8.0];
+
 impl eframe::App for App {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.check_gbc_thread();
+        self.poll_gbc_events();
+        self.handle_fast_forward_key(ctx);
+        self.handle_rewind_key(ctx);
+        self.handle_joypad_keys(ctx);
+        if ctx.input(|i| i.key_pressed(SCREENSHOT_KEY)) {
+            self.save_screenshot();
+        }
+        egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
+            self.menu_bar_ui(ctx, ui);
+        });
+        egui::TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
+            self.status_bar_ui(ui);
+        });
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.heading("Hello World!");
             self.gbc_ui(ctx, ui);
         });
+        self.tile_viewer.show(ctx, self.debug_snapshot.as_deref());
+        self.tile_map_viewer.show(ctx, self.debug_snapshot.as_deref());
+        self.oam_viewer.show(ctx, self.debug_snapshot.as_deref());
+        if let Some(command) = self.palette_viewer.show(ctx, self.debug_snapshot.as_deref()) {
+            self.send_gbc_command(command);
+        }
+        if let Some(command) = self.memory_viewer.show(ctx, self.debug_snapshot.as_deref()) {
+            self.send_gbc_command(command);
+        }
+        self.io_register_viewer.show(ctx, self.debug_snapshot.as_deref());
+        self.disassembly_viewer.show(ctx, self.debug_snapshot.as_deref());
+        if let Some(command) = self.profiler_viewer.show(ctx, self.debug_snapshot.as_deref()) {
+            self.send_gbc_command(command);
+        }
+        if let Some(command) = self.cheat_manager.show(ctx) {
+            self.send_gbc_command(command);
+        }
+        self.update_debug_capture();
+        self.fault_dialog(ctx);
+    }
+
+    fn on_close_event(&mut self) -> bool {
+        self.close_gbc();
+        true
     }
 }
 
 impl App {
+    fn poll_gbc_events(&mut self) {
+        let Some(ref mut gbc) = self.gbc else {
+            return;
+        };
+        for event in gbc.events.try_iter() {
+            match event {
+                GBCEvent::CPULockedUp { pc, opcode } => {
+                    self.fault = Some(format!(
+                        "The CPU executed illegal opcode {:#04x} at PC {:#06x} and has locked up.",
+                        opcode, pc
+                    ))
+                }
+                GBCEvent::FrameRate { fps, speed_percent } => {
+                    gbc.frame_rate = Some((fps, speed_percent))
+                }
+                GBCEvent::DebugSnapshot(snapshot) => self.debug_snapshot = Some(snapshot),
+                GBCEvent::Profile(report) => self.profiler_viewer.set_report(report),
+                GBCEvent::ScreenshotSaved(path) => {
+                    self.status_message = Some(format!("Saved screenshot {}", path.display()))
+                }
+                GBCEvent::ScreenshotFailed(e) => {
+                    self.fault = Some(format!("The screenshot couldn't be saved:\n{}", e))
+                }
+                GBCEvent::RecordingStarted(path) => {
+                    gbc.recording = true;
+                    self.status_message = Some(format!("Recording to {}", path.display()))
+                }
+                GBCEvent::RecordingStopped(path) => {
+                    gbc.recording = false;
+                    self.status_message = Some(format!("Saved recording {}", path.display()))
+                }
+                GBCEvent::RecordingFailed(e) => {
+                    gbc.recording = false;
+                    self.fault = Some(format!("The recording couldn't be saved:\n{}", e))
+                }
+                GBCEvent::MovieRecordingStarted => {
+                    gbc.movie_recording = true;
+                    self.status_message = Some("Recording movie".to_string())
+                }
+                GBCEvent::MovieSaved(path) => {
+                    gbc.movie_recording = false;
+                    self.status_message = Some(format!("Saved movie {}", path.display()))
+                }
+                GBCEvent::MoviePlaybackStarted { frames } => {
+                    gbc.movie_playing = true;
+                    self.status_message = Some(format!("Playing movie of {} frames", frames))
+                }
+                GBCEvent::MovieDesynced { frame } => {
+                    self.status_message = Some(format!("Movie desynced on frame {}", frame))
+                }
+                GBCEvent::MoviePlaybackEnded { desynced } => {
+                    gbc.movie_playing = false;
+                    self.status_message = Some(
+                        match desynced {
+                            true => "Movie ended after desyncing",
+                            false => "Movie ended",
+                        }
+                        .to_string(),
+                    )
+                }
+                GBCEvent::MovieFailed(e) => {
+                    gbc.movie_recording = false;
+                    gbc.movie_playing = false;
+                    self.fault = Some(format!("The movie couldn't be saved or played:\n{}", e))
+                }
+            }
+        }
+    }
+
+    fn handle_fast_forward_key(&mut self, ctx: &Context) {
+        // Tab typed into a text field moves the focus instead
+        let held = !ctx.wants_keyboard_input() && ctx.input(|i| i.key_down(FAST_FORWARD_KEY));
+        if held != self.fast_forwarding {
+            self.fast_forwarding = held;
+            self.send_gbc_command(GBCCommand::SetSpeed(self.effective_speed()));
+        }
+    }
+
+    fn handle_rewind_key(&mut self, ctx: &Context) {
+        // Backspace typed into a text field deletes instead
+        let held = !ctx.wants_keyboard_input() && ctx.input(|i| i.key_down(REWIND_KEY));
+        if held != self.rewinding {
+            self.rewinding = held;
+            self.send_gbc_command(match held {
+                true => GBCCommand::StartRewind,
+                false => GBCCommand::StopRewind,
+            });
+        }
+    }
+
+    /**
+     * Send the buttons held on the keyboard whenever they change. Keys typed into text fields
+     * don't count
+     */
+    fn handle_joypad_keys(&mut self, ctx: &Context) {
+        let mut buttons = Buttons::default();
+        if !ctx.wants_keyboard_input() {
+            ctx.input(|i| {
+                for (key, button) in JOYPAD_KEYS {
+                    buttons.set(button, i.key_down(key));
+                }
+            });
+        }
+        if buttons != self.held_buttons {
+            self.held_buttons = buttons;
+            self.send_gbc_command(GBCCommand::SetButtons(buttons));
+        }
+    }
+
+    /**
+     * Only have the emulator publish debug snapshots while a debug window is open
+     */
+    fn update_debug_capture(&mut self) {
+        let wanted = self.tile_viewer.open
+            || self.tile_map_viewer.open
+            || self.oam_viewer.open
+            || self.palette_viewer.open
+            || self.memory_viewer.open
+            || self.io_register_viewer.open
+            || self.disassembly_viewer.open
+            || self.profiler_viewer.open;
+        // Copying all of memory is only worth it for the memory viewer
+        if self.memory_viewer.open != self.memory_capture && self.gbc.is_some() {
+            self.memory_capture = self.memory_viewer.open;
+            self.send_gbc_command(GBCCommand::SetMemoryCapture(self.memory_capture));
+        }
+        if wanted != self.debug_capture && self.gbc.is_some() {
+            self.debug_capture = wanted;
+            self.send_gbc_command(GBCCommand::SetDebugCapture(wanted));
+        }
+    }
+
+    fn save_screenshot(&mut self) {
+        self.send_gbc_command(GBCCommand::SaveScreenshot {
+            scale: self.screenshot_scale,
+        });
+    }
+
+    fn start_recording(&mut self, format: RecordingFormat) {
+        self.send_gbc_command(GBCCommand::StartRecording(format));
+    }
+
+    fn play_movie_dialog(&mut self) {
+        if let Some(path) = rfd::FileDialog::new()
+            .add_filter("Movie", &[MOVIE_EXTENSION])
+            .pick_file()
+        {
+            self.send_gbc_command(GBCCommand::PlayMovie(path));
+        }
+    }
+
+    fn set_speed(&mut self, speed: EmulationSpeed) {
+        self.speed = speed;
+        self.send_gbc_command(GBCCommand::SetSpeed(self.effective_speed()));
+    }
+
+    fn open_rom_dialog(&mut self, ctx: &Context) {
+        if let Some(path) = rfd::FileDialog::new()
+            .add_filter("GB(C) ROM", &["gbc", "gb"])
+            .pick_file()
+        {
+            self.spawn_gbc(path, ctx);
+        }
+    }
+
+    fn menu_bar_ui(&mut self, ctx: &Context, ui: &mut Ui) {
+        let running = self.gbc.is_some();
+        let recording = self.gbc.as_ref().is_some_and(|gbc| gbc.recording);
+        let in_movie = self
+            .gbc
+            .as_ref()
+            .is_some_and(|gbc| gbc.movie_recording || gbc.movie_playing);
+        let paused = self.gbc.as_ref().is_some_and(|gbc| gbc.paused);
+
+        egui::menu::bar(ui, |ui| {
+            ui.menu_button("File", |ui| {
+                if ui.button("Open ROM...").clicked() {
+                    ui.close_menu();
+                    self.open_rom_dialog(ctx);
+                }
+                if ui.add_enabled(running, egui::Button::new("Close ROM")).clicked() {
+                    ui.close_menu();
+                    self.close_gbc();
+                }
+                ui.separator();
+                let screenshot_button = egui::Button::new("Save Screenshot").shortcut_text("F12");
+                if ui.add_enabled(running, screenshot_button).clicked() {
+                    ui.close_menu();
+                    self.save_screenshot();
+                }
+                ui.menu_button("Screenshot Size", |ui| {
+                    for scale in 1..=MAX_SCREENSHOT_SCALE {
+                        let label = format!(
+                            "{}x{}",
+                            GBC_RESOLUTION_X as u32 * scale as u32,
+                            GBC_RESOLUTION_Y as u32 * scale as u32
+                        );
+                        if ui.radio_value(&mut self.screenshot_scale, scale, label).clicked() {
+                            ui.close_menu();
+                        }
+                    }
+                });
+                ui.separator();
+                let can_start = running && !recording;
+                if ui.add_enabled(can_start, egui::Button::new("Record AVI")).clicked() {
+                    ui.close_menu();
+                    self.start_recording(RecordingFormat::Avi);
+                }
+                let png_button = egui::Button::new("Record PNG Sequence");
+                if ui.add_enabled(can_start, png_button).clicked() {
+                    ui.close_menu();
+                    self.start_recording(RecordingFormat::PngSequence);
+                }
+                if ui.add_enabled(recording, egui::Button::new("Stop Recording")).clicked() {
+                    ui.close_menu();
+                    self.send_gbc_command(GBCCommand::StopRecording);
+                }
+                ui.separator();
+                let can_start_movie = running && !in_movie;
+                let power_on_button = egui::Button::new("Record Movie from Power On");
+                if ui.add_enabled(can_start_movie, power_on_button).clicked() {
+                    ui.close_menu();
+                    self.send_gbc_command(GBCCommand::StartMovieRecording {
+                        from_power_on: true,
+                    });
+                }
+                let here_button = egui::Button::new("Record Movie from Here");
+                if ui.add_enabled(can_start_movie, here_button).clicked() {
+                    ui.close_menu();
+                    self.send_gbc_command(GBCCommand::StartMovieRecording {
+                        from_power_on: false,
+                    });
+                }
+                let play_button = egui::Button::new("Play Movie...");
+                if ui.add_enabled(can_start_movie, play_button).clicked() {
+                    ui.close_menu();
+                    self.play_movie_dialog();
+                }
+                if ui.add_enabled(in_movie, egui::Button::new("Stop Movie")).clicked() {
+                    ui.close_menu();
+                    self.send_gbc_command(GBCCommand::StopMovie);
+                }
+            });
+            ui.menu_button("Emulation", |ui| {
+                let pause_label = if paused { "Resume" } else { "Pause" };
+                if ui.add_enabled(running, egui::Button::new(pause_label)).clicked() {
+                    ui.close_menu();
+                    self.send_gbc_command(match paused {
+                        true => GBCCommand::Resume,
+                        false => GBCCommand::Pause,
+                    });
+                }
+                if ui.add_enabled(running, egui::Button::new("Reset")).clicked() {
+                    ui.close_menu();
+                    self.send_gbc_command(GBCCommand::SoftReset);
+                }
+                if ui.add_enabled(running, egui::Button::new("Hard Reset")).clicked() {
+                    ui.close_menu();
+                    self.send_gbc_command(GBCCommand::HardReset);
+                }
+                ui.separator();
+                ui.checkbox(&mut self.cheat_manager.open, "Cheats");
+                ui.menu_button("Speed", |ui| {
+                    let mut speed = self.speed;
+                    for multiplier in SPEED_MULTIPLIERS {
+                        let label = format!("{}%", multiplier * 100.0);
+                        ui.radio_value(&mut speed, EmulationSpeed::Multiplier(multiplier), label);
+                    }
+                    ui.radio_value(&mut speed, EmulationSpeed::Uncapped, "Uncapped");
+                    if speed != self.speed {
+                        ui.close_menu();
+                        self.set_speed(speed);
+                    }
+                });
+            });
+            ui.menu_button("Debug", |ui| {
+                ui.checkbox(&mut self.tile_viewer.open, "VRAM Tiles");
+                ui.checkbox(&mut self.tile_map_viewer.open, "Tile Maps");
+                ui.checkbox(&mut self.oam_viewer.open, "OAM");
+                ui.checkbox(&mut self.palette_viewer.open, "Palettes");
+                ui.checkbox(&mut self.memory_viewer.open, "Memory");
+                ui.checkbox(&mut self.io_register_viewer.open, "IO Registers");
+                ui.checkbox(&mut self.disassembly_viewer.open, "Disassembly");
+                ui.checkbox(&mut self.profiler_viewer.open, "Profiler");
+            });
+        });
+    }
+
+    fn status_bar_ui(&mut self, ui: &mut Ui) {
+        let Some(ref gbc) = self.gbc else {
+            ui.label("No ROM loaded");
+            return;
+        };
+        ui.horizontal(|ui| {
+            match (gbc.paused, gbc.frame_rate) {
+                (true, _) => ui.label("Paused"),
+                (false, Some((fps, speed_percent))) => {
+                    ui.label(format!("{:.1} FPS ({:.0}%)", fps, speed_percent))
+                }
+                (false, None) => ui.label("Running"),
+            };
+            if self.fast_forwarding {
+                ui.label("Fast forward");
+            }
+            if self.rewinding {
+                ui.label("Rewinding");
+            }
+            if gbc.recording {
+                ui.label("Recording");
+            }
+            if gbc.movie_recording {
+                ui.label("Recording movie");
+            }
+            if gbc.movie_playing {
+                ui.label("Playing movie");
+            }
+            if let Some(ref message) = self.status_message {
+                ui.label(message);
+            }
+        });
+    }
+
     fn gbc_ui(&mut self, ctx: &Context, ui: &mut Ui) {
         match self.gbc {
             Some(ref mut gbc) => {
                 let display_buffer = gbc.display_buffer.lock().unwrap();
                 ui.add(egui::Image::new(
                     display_buffer.texture_id(ctx),
-                    display_buffer.size_vec2(),
+                    display_buffer.size_vec2() * self.scale,
                 ));
             }
             None => {
                 if ui.button("Load ROM").clicked() {
-                    if let Some(path) = rfd::FileDialog::new()
-                        .add_filter("GB(C) ROM", &["gbc", "gb"])
-                        .pick_file()
-                    {
-                        self.spawn_gbc(path, ctx);
-                    }
+                    self.open_rom_dialog(ctx);
                 }
             }
         }
     }
+
+    fn fault_dialog(&mut self, ctx: &Context) {
+        let Some(ref fault) = self.fault else {
+            return;
+        };
+
+        let mut dismissed = false;
+        egui::Window::new("Emulator Fault")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(fault);
+                if ui.button("OK").clicked() {
+                    dismissed = true;
+                }
+            });
+        if dismissed {
+            self.fault = None;
+        }
+    }
 }
@@ -0,0 +1,136 @@
+use std::path::{Path, PathBuf};
+
+use eframe::egui::{self, Color32, Context};
+
+use gbc_emulator::gbc::{Cheat, CheatList, GBCCommand};
+
+/**
+ * Add, enable and disable cheats for the running ROM. Every change is saved next to the ROM
+ */
+pub struct CheatManager {
+    pub open: bool,
+    // ROM the cheats belong to. None while nothing is running
+    rom_path: Option<PathBuf>,
+    cheats: CheatList,
+    name_text: String,
+    code_text: String,
+    // Why a cheat couldn't be added, loaded or saved
+    error: Option<String>,
+}
+
+impl CheatManager {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            rom_path: None,
+            cheats: CheatList::default(),
+            name_text: String::new(),
+            code_text: String::new(),
+            error: None,
+        }
+    }
+
+    /**
+     * Show the cheats saved for a ROM that is starting
+     */
+    pub fn load_for_rom(&mut self, rom_path: &Path) {
+        self.cheats = match CheatList::load_for_rom(rom_path) {
+            Ok(cheats) => cheats,
+            Err(e) => {
+                self.error = Some(format!("Couldn't load cheats: {}", e));
+                CheatList::default()
+            }
+        };
+        self.rom_path = Some(rom_path.to_path_buf());
+    }
+
+    /**
+     * Forget the cheats of a ROM that has stopped
+     */
+    pub fn clear(&mut self) {
+        self.rom_path = None;
+        self.cheats = CheatList::default();
+        self.error = None;
+    }
+
+    /**
+     * Returns a command to send to the emulator if the cheats changed
+     */
+    pub fn show(&mut self, ctx: &Context) -> Option<GBCCommand> {
+        let mut open = self.open;
+        let mut command = None;
+        egui::Window::new("Cheats")
+            .open(&mut open)
+            .show(ctx, |ui| match self.rom_path.clone() {
+                Some(rom_path) => command = self.cheats_ui(ui, &rom_path),
+                None => {
+                    ui.label("No ROM running");
+                }
+            });
+        self.open = open;
+        command
+    }
+
+    fn cheats_ui(&mut self, ui: &mut egui::Ui, rom_path: &Path) -> Option<GBCCommand> {
+        let mut changed = false;
+        let mut removed = None;
+        match self.cheats.cheats.is_empty() {
+            true => {
+                ui.label("No cheats for this ROM yet");
+            }
+            false => {
+                egui::Grid::new("cheats").striped(true).show(ui, |ui| {
+                    for (idx, cheat) in self.cheats.cheats.iter_mut().enumerate() {
+                        changed |= ui.checkbox(&mut cheat.enabled, "").changed();
+                        ui.label(&cheat.name);
+                        ui.monospace(cheat.code());
+                        if ui.small_button("Remove").clicked() {
+                            removed = Some(idx);
+                        }
+                        ui.end_row();
+                    }
+                });
+            }
+        }
+        if let Some(idx) = removed {
+            self.cheats.cheats.remove(idx);
+            changed = true;
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Name");
+            ui.add(egui::TextEdit::singleline(&mut self.name_text).desired_width(120.0));
+            ui.label("Code");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.code_text)
+                    .hint_text("01VVLLHH or ABC-DEF-GHI")
+                    .desired_width(160.0),
+            );
+            if ui.button("Add").clicked() {
+                match Cheat::new(&self.name_text, &self.code_text) {
+                    Ok(cheat) => {
+                        self.cheats.cheats.push(cheat);
+                        self.name_text.clear();
+                        self.code_text.clear();
+                        self.error = None;
+                        changed = true;
+                    }
+                    Err(e) => self.error = Some(e.to_string()),
+                }
+            }
+        });
+        ui.label("Join several codes with + to turn them on and off together");
+        if let Some(ref error) = self.error {
+            ui.colored_label(Color32::RED, error);
+        }
+
+        if !changed {
+            return None;
+        }
+        if let Err(e) = self.cheats.save_for_rom(rom_path) {
+            self.error = Some(format!("Couldn't save cheats: {}", e));
+        }
+        Some(GBCCommand::SetCheats(self.cheats.clone()))
+    }
+}
@@ -0,0 +1,57 @@
+use eframe::egui::{self, Context};
+
+use gbc_emulator::gbc::DebugSnapshot;
+
+/**
+ * Disassembly from PC on. Labels from the symbol file are shown above the instructions they mark
+ * and next to the addresses instructions use
+ */
+pub struct DisassemblyViewer {
+    pub open: bool,
+}
+
+impl DisassemblyViewer {
+    pub fn new() -> Self {
+        Self { open: false }
+    }
+
+    pub fn show(&mut self, ctx: &Context, snapshot: Option<&DebugSnapshot>) {
+        let mut open = self.open;
+        egui::Window::new("Disassembly")
+            .open(&mut open)
+            .show(ctx, |ui| match snapshot {
+                Some(snapshot) => {
+                    egui::ScrollArea::vertical().show(ui, |ui| disassembly_ui(ui, snapshot));
+                }
+                None => {
+                    ui.label("No ROM running");
+                }
+            });
+        self.open = open;
+    }
+}
+
+fn disassembly_ui(ui: &mut egui::Ui, snapshot: &DebugSnapshot) {
+    egui::Grid::new("disassembly").striped(true).show(ui, |ui| {
+        for (i, instruction) in snapshot.disassembly.iter().enumerate() {
+            if let Some(ref label) = instruction.label {
+                ui.monospace(format!("{}:", label));
+                ui.end_row();
+            }
+            let bytes: Vec<String> = instruction
+                .bytes
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect();
+            // The first instruction is the one at PC
+            let addr = format!("{:04X}", instruction.addr);
+            match i {
+                0 => ui.strong(egui::RichText::new(addr).monospace()),
+                _ => ui.monospace(addr),
+            };
+            ui.monospace(bytes.join(" "));
+            ui.monospace(&instruction.text);
+            ui.end_row();
+        }
+    });
+}
@@ -0,0 +1,412 @@
+use std::collections::HashMap;
+
+use eframe::egui::{self, Color32, Context, RichText};
+
+use gbc_emulator::gbc::DebugSnapshot;
+
+const IO_REGISTERS_ADDR: u16 = 0xFF00;
+const NUM_IO_REGISTERS: u16 = 0x80;
+const BG_PALETTE_INDEX_REGISTER: u16 = 0xFF68;
+const BG_PALETTE_DATA_REGISTER: u16 = 0xFF69;
+const OBJ_PALETTE_INDEX_REGISTER: u16 = 0xFF6A;
+const OBJ_PALETTE_DATA_REGISTER: u16 = 0xFF6B;
+const INTERRUPT_ENABLE_REGISTER: u16 = 0xFFFF;
+// How long a changed field stays highlighted
+const HIGHLIGHT_SECONDS: f64 = 0.5;
+const CHANGED_COLOR: Color32 = Color32::YELLOW;
+
+/**
+ * Named group of bits within a register
+ */
+struct Field {
+    name: &'static str,
+    lsb: u8,
+    width: u8,
+}
+
+const fn bit(name: &'static str, lsb: u8) -> Field {
+    Field {
+        name,
+        lsb,
+        width: 1,
+    }
+}
+
+const fn bits(name: &'static str, lsb: u8, width: u8) -> Field {
+    Field { name, lsb, width }
+}
+
+impl Field {
+    fn mask(&self) -> u8 {
+        (((1u16 << self.width) - 1) << self.lsb) as u8
+    }
+
+    fn value(&self, register: u8) -> u8 {
+        (register & self.mask()) >> self.lsb
+    }
+}
+
+struct Register {
+    addr: u16,
+    name: &'static str,
+    fields: &'static [Field],
+}
+
+const INTERRUPT_FIELDS: &[Field] = &[
+    bit("Joypad", 4),
+    bit("Serial", 3),
+    bit("Timer", 2),
+    bit("STAT", 1),
+    bit("VBlank", 0),
+];
+const DMG_PALETTE_FIELDS: &[Field] = &[
+    bits("Color 3", 6, 2),
+    bits("Color 2", 4, 2),
+    bits("Color 1", 2, 2),
+    bits("Color 0", 0, 2),
+];
+const PALETTE_INDEX_FIELDS: &[Field] = &[bit("Auto increment", 7), bits("Address", 0, 6)];
+
+const REGISTERS: &[Register] = &[
+    Register {
+        addr: 0xFF00,
+        name: "P1",
+        fields: &[
+            bit("Select buttons", 5),
+            bit("Select d-pad", 4),
+            bits("Inputs", 0, 4),
+        ],
+    },
+    Register {
+        addr: 0xFF01,
+        name: "SB",
+        fields: &[],
+    },
+    Register {
+        addr: 0xFF02,
+        name: "SC",
+        fields: &[
+            bit("Transfer", 7),
+            bit("Fast clock", 1),
+            bit("Internal clock", 0),
+        ],
+    },
+    Register {
+        addr: 0xFF04,
+        name: "DIV",
+        fields: &[],
+    },
+    Register {
+        addr: 0xFF05,
+        name: "TIMA",
+        fields: &[],
+    },
+    Register {
+        addr: 0xFF06,
+        name: "TMA",
+        fields: &[],
+    },
+    Register {
+        addr: 0xFF07,
+        name: "TAC",
+        fields: &[bit("Enable", 2), bits("Clock select", 0, 2)],
+    },
+    Register {
+        addr: 0xFF0F,
+        name: "IF",
+        fields: INTERRUPT_FIELDS,
+    },
+    Register {
+        addr: 0xFF40,
+        name: "LCDC",
+        fields: &[
+            bit("LCD enable", 7),
+            bit("Window map", 6),
+            bit("Window enable", 5),
+            bit("Tile data", 4),
+            bit("BG map", 3),
+            bit("OBJ size", 2),
+            bit("OBJ enable", 1),
+            bit("BG/window priority", 0),
+        ],
+    },
+    Register {
+        addr: 0xFF41,
+        name: "STAT",
+        fields: &[
+            bit("LYC interrupt", 6),
+            bit("Mode 2 interrupt", 5),
+            bit("Mode 1 interrupt", 4),
+            bit("Mode 0 interrupt", 3),
+            bit("LYC=LY", 2),
+            bits("Mode", 0, 2),
+        ],
+    },
+    Register {
+        addr: 0xFF42,
+        name: "SCY",
+        fields: &[],
+    },
+    Register {
+        addr: 0xFF43,
+        name: "SCX",
+        fields: &[],
+    },
+    Register {
+        addr: 0xFF44,
+        name: "LY",
+        fields: &[],
+    },
+    Register {
+        addr: 0xFF45,
+        name: "LYC",
+        fields: &[],
+    },
+    Register {
+        addr: 0xFF46,
+        name: "DMA",
+        fields: &[],
+    },
+    Register {
+        addr: 0xFF47,
+        name: "BGP",
+        fields: DMG_PALETTE_FIELDS,
+    },
+    Register {
+        addr: 0xFF48,
+        name: "OBP0",
+        fields: DMG_PALETTE_FIELDS,
+    },
+    Register {
+        addr: 0xFF49,
+        name: "OBP1",
+        fields: DMG_PALETTE_FIELDS,
+    },
+    Register {
+        addr: 0xFF4A,
+        name: "WY",
+        fields: &[],
+    },
+    Register {
+        addr: 0xFF4B,
+        name: "WX",
+        fields: &[],
+    },
+    Register {
+        addr: 0xFF4D,
+        name: "KEY1",
+        fields: &[bit("Double speed", 7), bit("Switch armed", 0)],
+    },
+    Register {
+        addr: 0xFF4F,
+        name: "VBK",
+        fields: &[bit("Bank", 0)],
+    },
+    Register {
+        addr: 0xFF51,
+        name: "HDMA1",
+        fields: &[],
+    },
+    Register {
+        addr: 0xFF52,
+        name: "HDMA2",
+        fields: &[],
+    },
+    Register {
+        addr: 0xFF53,
+        name: "HDMA3",
+        fields: &[],
+    },
+    Register {
+        addr: 0xFF54,
+        name: "HDMA4",
+        fields: &[],
+    },
+    Register {
+        addr: 0xFF55,
+        name: "HDMA5",
+        fields: &[bit("Inactive", 7), bits("Length", 0, 7)],
+    },
+    Register {
+        addr: BG_PALETTE_INDEX_REGISTER,
+        name: "BCPS",
+        fields: PALETTE_INDEX_FIELDS,
+    },
+    Register {
+        addr: BG_PALETTE_DATA_REGISTER,
+        name: "BCPD",
+        fields: &[],
+    },
+    Register {
+        addr: OBJ_PALETTE_INDEX_REGISTER,
+        name: "OCPS",
+        fields: PALETTE_INDEX_FIELDS,
+    },
+    Register {
+        addr: OBJ_PALETTE_DATA_REGISTER,
+        name: "OCPD",
+        fields: &[],
+    },
+    Register {
+        addr: 0xFF70,
+        name: "SVBK",
+        fields: &[bits("Bank", 0, 3)],
+    },
+    Register {
+        addr: INTERRUPT_ENABLE_REGISTER,
+        name: "IE",
+        fields: INTERRUPT_FIELDS,
+    },
+];
+
+/**
+ * A register's current value and the one it had before its last change
+ */
+#[derive(Clone, Copy)]
+struct History {
+    value: u8,
+    previous: u8,
+    changed_at: f64,
+}
+
+/**
+ * Lists the IO registers decoded into their fields. Fields that just changed are highlighted
+ */
+pub struct IORegisterViewer {
+    pub open: bool,
+    // Keyed by address. IME is stored under 0 since it isn't memory mapped
+    history: HashMap<u16, History>,
+}
+
+impl IORegisterViewer {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            history: HashMap::new(),
+        }
+    }
+
+    pub fn show(&mut self, ctx: &Context, snapshot: Option<&DebugSnapshot>) {
+        let mut open = self.open;
+        egui::Window::new("IO Registers")
+            .open(&mut open)
+            .show(ctx, |ui| match snapshot {
+                Some(snapshot) => {
+                    let now = ctx.input(|i| i.time);
+                    self.update_history(snapshot, now);
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        self.registers_ui(ui, now);
+                        egui::CollapsingHeader::new("All IO registers")
+                            .show(ui, |ui| self.raw_registers_ui(ui, now));
+                    });
+                }
+                None => {
+                    self.history.clear();
+                    ui.label("No ROM running");
+                }
+            });
+        self.open = open;
+    }
+
+    fn update_history(&mut self, snapshot: &DebugSnapshot, now: f64) {
+        let values = (IO_REGISTERS_ADDR..IO_REGISTERS_ADDR + NUM_IO_REGISTERS)
+            .chain([INTERRUPT_ENABLE_REGISTER])
+            .map(|addr| (addr, register_value(snapshot, addr)))
+            .chain([(0, snapshot.interrupt_master_enable as u8)]);
+        for (addr, value) in values {
+            let history = self.history.entry(addr).or_insert(History {
+                value,
+                previous: value,
+                changed_at: f64::NEG_INFINITY,
+            });
+            if history.value != value {
+                *history = History {
+                    value,
+                    previous: history.value,
+                    changed_at: now,
+                };
+            }
+        }
+    }
+
+    /**
+     * Bits of a register that changed recently
+     */
+    fn changed_bits(&self, addr: u16, now: f64) -> u8 {
+        match self.history.get(&addr) {
+            Some(history) if now - history.changed_at < HIGHLIGHT_SECONDS => {
+                history.value ^ history.previous
+            }
+            _ => 0,
+        }
+    }
+
+    fn registers_ui(&self, ui: &mut egui::Ui, now: f64) {
+        egui::Grid::new("io_registers")
+            .striped(true)
+            .show(ui, |ui| {
+                for register in REGISTERS {
+                    let value = self.history[&register.addr].value;
+                    let changed_bits = self.changed_bits(register.addr, now);
+                    ui.monospace(format!("{:04X}", register.addr));
+                    ui.label(register.name);
+                    ui.label(highlight(format!("{:02X}", value), changed_bits != 0).monospace());
+                    ui.horizontal(|ui| {
+                        for field in register.fields {
+                            let changed = changed_bits & field.mask() != 0;
+                            ui.label(highlight(
+                                format!("{}: {}", field.name, field.value(value)),
+                                changed,
+                            ));
+                        }
+                    });
+                    ui.end_row();
+                }
+
+                let ime = self.history[&0].value;
+                ui.label("");
+                ui.label("IME");
+                ui.label(highlight(ime.to_string(), self.changed_bits(0, now) != 0));
+                ui.end_row();
+            });
+    }
+
+    fn raw_registers_ui(&self, ui: &mut egui::Ui, now: f64) {
+        egui::Grid::new("raw_io_registers").show(ui, |ui| {
+            for row in (0..NUM_IO_REGISTERS).step_by(16) {
+                ui.monospace(format!("{:04X}", IO_REGISTERS_ADDR + row));
+                for addr in IO_REGISTERS_ADDR + row..IO_REGISTERS_ADDR + row + 16 {
+                    let value = self.history[&addr].value;
+                    let changed = self.changed_bits(addr, now) != 0;
+                    ui.label(highlight(format!("{:02X}", value), changed).monospace());
+                }
+                ui.end_row();
+            }
+        });
+    }
+}
+
+/**
+ * Palette data registers read through to palette memory at the index register's address
+ */
+fn register_value(snapshot: &DebugSnapshot, addr: u16) -> u8 {
+    match addr {
+        BG_PALETTE_DATA_REGISTER => {
+            let idx = snapshot.io_register(BG_PALETTE_INDEX_REGISTER) & 0x3F;
+            snapshot.bg_palettes[idx as usize]
+        }
+        OBJ_PALETTE_DATA_REGISTER => {
+            let idx = snapshot.io_register(OBJ_PALETTE_INDEX_REGISTER) & 0x3F;
+            snapshot.obj_palettes[idx as usize]
+        }
+        INTERRUPT_ENABLE_REGISTER => snapshot.interrupt_enable,
+        _ => snapshot.io_register(addr),
+    }
+}
+
+fn highlight(text: String, changed: bool) -> RichText {
+    match changed {
+        true => RichText::new(text).color(CHANGED_COLOR),
+        false => RichText::new(text),
+    }
+}
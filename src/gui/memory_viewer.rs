@@ -0,0 +1,352 @@
+use eframe::egui::{self, Color32, Context, RichText, Sense, TextStyle};
+
+use gbc_emulator::gbc::{
+    find_pattern, memory_area_at, DebugSnapshot, GBCCommand, MemoryAreaName, MemorySearch,
+    MemoryViewBanks, SearchFilter,
+};
+
+const BYTES_PER_ROW: usize = 16;
+const NUM_ROWS: usize = 0x10000 / BYTES_PER_ROW;
+// Only list this many search results
+const MAX_RESULTS_SHOWN: usize = 64;
+// Areas with a bank selector, with the name shown next to it
+const BANKED_AREAS: [(MemoryAreaName, &str); 4] = [
+    (MemoryAreaName::PrgRomBanked, "ROM"),
+    (MemoryAreaName::Vram, "VRAM"),
+    (MemoryAreaName::WorkRamBanked, "WRAM"),
+    (MemoryAreaName::ExternalRam, "Cart RAM"),
+];
+
+/**
+ * Hex editor over the whole address space, with pattern and value searches
+ */
+pub struct MemoryViewer {
+    pub open: bool,
+    view_banks: MemoryViewBanks,
+    selected: Option<u16>,
+    // Row to scroll to on the next frame
+    scroll_to: Option<usize>,
+    goto_text: String,
+    edit_text: String,
+    pattern_text: String,
+    pattern_results: Vec<u16>,
+    search: Option<MemorySearch>,
+    search_value_text: String,
+}
+
+impl MemoryViewer {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            view_banks: MemoryViewBanks::default(),
+            selected: None,
+            scroll_to: None,
+            goto_text: String::new(),
+            edit_text: String::new(),
+            pattern_text: String::new(),
+            pattern_results: Vec::new(),
+            search: None,
+            search_value_text: String::new(),
+        }
+    }
+
+    /**
+     * Returns a command to send to the emulator if banks were switched or memory was edited
+     */
+    pub fn show(&mut self, ctx: &Context, snapshot: Option<&DebugSnapshot>) -> Option<GBCCommand> {
+        let mut open = self.open;
+        let mut command = None;
+        egui::Window::new("Memory")
+            .open(&mut open)
+            .default_width(600.0)
+            .show(ctx, |ui| match snapshot {
+                Some(snapshot) => {
+                    let bank_cmd = self.bank_selector_ui(ui, snapshot);
+                    ui.separator();
+                    // Memory is only in the snapshots published after the viewer was opened
+                    let Some(memory) = snapshot.memory.as_deref() else {
+                        command = bank_cmd;
+                        ui.label("Waiting for memory");
+                        return;
+                    };
+                    command = self.selected_byte_ui(ui, memory).or(bank_cmd);
+                    ui.separator();
+                    egui::CollapsingHeader::new("Search").show(ui, |ui| {
+                        self.pattern_search_ui(ui, memory);
+                        ui.separator();
+                        self.value_search_ui(ui, memory);
+                    });
+                    ui.separator();
+                    self.hex_ui(ui, memory);
+                }
+                None => {
+                    // A new cartridge may not have the same banks
+                    self.view_banks = MemoryViewBanks::default();
+                    ui.label("No ROM running");
+                }
+            });
+        self.open = open;
+        command
+    }
+
+    fn bank_selector_ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        snapshot: &DebugSnapshot,
+    ) -> Option<GBCCommand> {
+        let old_banks = self.view_banks;
+        ui.horizontal(|ui| {
+            for (area, name) in BANKED_AREAS {
+                let (active, count) = snapshot.area_banks[area];
+                let selected_text = match self.view_banks[area] {
+                    Some(bank) => bank_label(area, bank),
+                    None => format!("Active ({})", bank_label(area, active)),
+                };
+                egui::ComboBox::from_label(name)
+                    .selected_text(selected_text)
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.view_banks[area], None, "Active");
+                        for bank in 0..count {
+                            ui.selectable_value(
+                                &mut self.view_banks[area],
+                                Some(bank),
+                                bank_label(area, bank),
+                            );
+                        }
+                    });
+            }
+        });
+        (self.view_banks != old_banks).then_some(GBCCommand::SetMemoryViewBanks(self.view_banks))
+    }
+
+    fn selected_byte_ui(&mut self, ui: &mut egui::Ui, memory: &[u8]) -> Option<GBCCommand> {
+        let mut command = None;
+        ui.horizontal(|ui| {
+            ui.label("Go to");
+            let response =
+                ui.add(egui::TextEdit::singleline(&mut self.goto_text).desired_width(48.0));
+            if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                if let Ok(addr) = u16::from_str_radix(self.goto_text.trim_start_matches("0x"), 16) {
+                    self.select(addr, memory);
+                }
+            }
+
+            let Some(addr) = self.selected else {
+                ui.label("Click a byte to edit it");
+                return;
+            };
+            let Some(area) = memory_area_at(addr) else {
+                ui.label(format!("{:#06x} is echo RAM, which isn't emulated", addr));
+                return;
+            };
+            ui.separator();
+            ui.label(format!(
+                "{:#06x} ({:?}): {:#04x}",
+                addr, area, memory[addr as usize]
+            ));
+            if matches!(
+                area,
+                MemoryAreaName::PrgRomFixed | MemoryAreaName::PrgRomBanked
+            ) {
+                ui.label("ROM is read only");
+                return;
+            }
+            ui.add(egui::TextEdit::singleline(&mut self.edit_text).desired_width(24.0));
+            if ui.button("Write").clicked() {
+                if let Ok(val) = u8::from_str_radix(&self.edit_text, 16) {
+                    command = Some(GBCCommand::WriteMemory {
+                        addr,
+                        bank: self.view_banks[area],
+                        val,
+                    });
+                }
+            }
+        });
+        command
+    }
+
+    fn pattern_search_ui(&mut self, ui: &mut egui::Ui, memory: &[u8]) {
+        ui.horizontal(|ui| {
+            ui.label("Byte pattern");
+            ui.text_edit_singleline(&mut self.pattern_text);
+            if ui.button("Find").clicked() {
+                self.pattern_results = match parse_hex_bytes(&self.pattern_text) {
+                    Some(pattern) => find_pattern(memory, &pattern),
+                    None => Vec::new(),
+                };
+            }
+        });
+        ui.label(format!("{} matches", self.pattern_results.len()));
+        let mut clicked = None;
+        ui.horizontal_wrapped(|ui| {
+            for &addr in self.pattern_results.iter().take(MAX_RESULTS_SHOWN) {
+                if ui.link(format!("{:#06x}", addr)).clicked() {
+                    clicked = Some(addr);
+                }
+            }
+        });
+        if let Some(addr) = clicked {
+            self.select(addr, memory);
+        }
+    }
+
+    fn value_search_ui(&mut self, ui: &mut egui::Ui, memory: &[u8]) {
+        let mut filter = None;
+        ui.horizontal(|ui| {
+            if ui.button("New value search").clicked() {
+                let ram_addrs = (0..=u16::MAX).filter(|&addr| is_ram(memory_area_at(addr)));
+                self.search = Some(MemorySearch::new(memory, ram_addrs));
+            }
+            if self.search.is_none() {
+                return;
+            }
+            for (label, search_filter) in [
+                ("Changed", SearchFilter::Changed),
+                ("Unchanged", SearchFilter::Unchanged),
+                ("Increased", SearchFilter::Increased),
+                ("Decreased", SearchFilter::Decreased),
+            ] {
+                if ui.button(label).clicked() {
+                    filter = Some(search_filter);
+                }
+            }
+            if ui.button("Equal to").clicked() {
+                if let Ok(val) = u8::from_str_radix(&self.search_value_text, 16) {
+                    filter = Some(SearchFilter::EqualTo(val));
+                }
+            }
+            ui.add(egui::TextEdit::singleline(&mut self.search_value_text).desired_width(24.0));
+        });
+
+        let Some(ref mut search) = self.search else {
+            return;
+        };
+        if let Some(filter) = filter {
+            search.filter(memory, filter);
+        }
+        ui.label(format!("{} candidates", search.candidates().len()));
+        let mut clicked = None;
+        ui.horizontal_wrapped(|ui| {
+            for &(addr, val) in search.candidates().iter().take(MAX_RESULTS_SHOWN) {
+                let label = format!(
+                    "{:#06x}: {:02X} -> {:02X}",
+                    addr, val, memory[addr as usize]
+                );
+                if ui.link(label).clicked() {
+                    clicked = Some(addr);
+                }
+            }
+        });
+        if let Some(addr) = clicked {
+            self.select(addr, memory);
+        }
+    }
+
+    fn hex_ui(&mut self, ui: &mut egui::Ui, memory: &[u8]) {
+        let row_height = ui.text_style_height(&TextStyle::Monospace);
+        let mut scroll_area = egui::ScrollArea::vertical().auto_shrink([false, false]);
+        if let Some(row) = self.scroll_to.take() {
+            let offset = row as f32 * (row_height + ui.spacing().item_spacing.y);
+            scroll_area = scroll_area.vertical_scroll_offset(offset);
+        }
+
+        scroll_area.show_rows(ui, row_height, NUM_ROWS, |ui, rows| {
+            for row in rows {
+                let row_addr = row * BYTES_PER_ROW;
+                let bytes = &memory[row_addr..row_addr + BYTES_PER_ROW];
+                ui.horizontal(|ui| {
+                    ui.monospace(format!("{:04X}", row_addr));
+                    for (i, &byte) in bytes.iter().enumerate() {
+                        let addr = (row_addr + i) as u16;
+                        let mut text = RichText::new(format!("{:02X}", byte))
+                            .monospace()
+                            .color(area_color(memory_area_at(addr)));
+                        if self.selected == Some(addr) {
+                            text = text.background_color(Color32::DARK_BLUE);
+                        }
+                        let response = ui.add(egui::Label::new(text).sense(Sense::click()));
+                        if response.clicked() {
+                            self.select(addr, memory);
+                        }
+                    }
+                    let ascii: String = bytes
+                        .iter()
+                        .map(|&byte| match byte {
+                            0x20..=0x7E => byte as char,
+                            _ => '.',
+                        })
+                        .collect();
+                    ui.monospace(ascii);
+                });
+            }
+        });
+    }
+
+    fn select(&mut self, addr: u16, memory: &[u8]) {
+        self.selected = Some(addr);
+        self.scroll_to = Some(addr as usize / BYTES_PER_ROW);
+        self.edit_text = format!("{:02X}", memory[addr as usize]);
+    }
+}
+
+/**
+ * WRAM bank 0 is always mapped at 0xC000, so the switchable banks start at 1
+ */
+fn bank_label(area: MemoryAreaName, bank: usize) -> String {
+    match area {
+        MemoryAreaName::WorkRamBanked => (bank + 1).to_string(),
+        _ => bank.to_string(),
+    }
+}
+
+/**
+ * Memory a game could keep its variables in
+ */
+fn is_ram(area: Option<MemoryAreaName>) -> bool {
+    matches!(
+        area,
+        Some(
+            MemoryAreaName::Vram
+                | MemoryAreaName::ExternalRam
+                | MemoryAreaName::WorkRamFixed
+                | MemoryAreaName::WorkRamBanked
+                | MemoryAreaName::Oam
+                | MemoryAreaName::HighRam
+        )
+    )
+}
+
+fn area_color(area: Option<MemoryAreaName>) -> Color32 {
+    match area {
+        Some(MemoryAreaName::PrgRomFixed | MemoryAreaName::PrgRomBanked) => Color32::LIGHT_BLUE,
+        Some(MemoryAreaName::Vram) => Color32::LIGHT_GREEN,
+        Some(MemoryAreaName::ExternalRam) => Color32::GOLD,
+        Some(MemoryAreaName::WorkRamFixed | MemoryAreaName::WorkRamBanked) => Color32::WHITE,
+        Some(MemoryAreaName::Oam) => Color32::LIGHT_RED,
+        Some(
+            MemoryAreaName::IORegisters
+            | MemoryAreaName::BGPalette
+            | MemoryAreaName::OBJPalette
+            | MemoryAreaName::IERegister,
+        ) => Color32::YELLOW,
+        Some(MemoryAreaName::HighRam) => Color32::KHAKI,
+        Some(MemoryAreaName::Invalid2) | None => Color32::GRAY,
+    }
+}
+
+/**
+ * Parse hex bytes like "DE AD BE EF" or "DEADBEEF"
+ */
+fn parse_hex_bytes(text: &str) -> Option<Vec<u8>> {
+    let digits: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if digits.is_empty()
+        || !digits.len().is_multiple_of(2)
+        || !digits.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        return None;
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).ok())
+        .collect()
+}
@@ -0,0 +1,190 @@
+use eframe::egui::{
+    self, pos2, Color32, ColorImage, Context, Rect, RichText, TextureHandle, TextureOptions, Vec2,
+};
+
+use gbc_emulator::gbc::{
+    DebugSnapshot, OAMEntry, PaletteKind, GBC_RESOLUTION_Y, MAX_OBJS_PER_LINE, OAM_ENTRY_COUNT,
+};
+
+// Every object gets an 8x16 slot in the preview texture, whatever the current object size
+const SLOT_WIDTH: usize = 8;
+const SLOT_HEIGHT: usize = 16;
+const IMAGE_WIDTH: usize = OAM_ENTRY_COUNT * SLOT_WIDTH;
+const PREVIEW_SCALE: f32 = 2.0;
+const SELECTED_COLOR: Color32 = Color32::GREEN;
+// On the line but not picked because 10 objects were found before it
+const DROPPED_COLOR: Color32 = Color32::RED;
+
+/**
+ * Lists all 40 OAM entries and which of them the OAM scan picked for a scanline
+ */
+pub struct OAMViewer {
+    pub open: bool,
+    scanline: usize,
+    texture: Option<TextureHandle>,
+}
+
+impl OAMViewer {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            scanline: 0,
+            texture: None,
+        }
+    }
+
+    pub fn show(&mut self, ctx: &Context, snapshot: Option<&DebugSnapshot>) {
+        let mut open = self.open;
+        egui::Window::new("OAM")
+            .open(&mut open)
+            .show(ctx, |ui| match snapshot {
+                Some(snapshot) => self.oam_ui(ctx, ui, snapshot),
+                None => {
+                    ui.label("No ROM running");
+                }
+            });
+        self.open = open;
+    }
+
+    fn oam_ui(&mut self, ctx: &Context, ui: &mut egui::Ui, snapshot: &DebugSnapshot) {
+        let image = render_objs(snapshot);
+        let texture = match self.texture {
+            Some(ref mut texture) => {
+                texture.set(image, TextureOptions::NEAREST);
+                texture
+            }
+            None => {
+                self.texture
+                    .insert(ctx.load_texture("oam_objs", image, TextureOptions::NEAREST))
+            }
+        };
+
+        ui.add(
+            egui::Slider::new(&mut self.scanline, 0..=GBC_RESOLUTION_Y as usize - 1)
+                .text("Scanline"),
+        );
+        let obj_height = snapshot.obj_height();
+        let entries: Vec<OAMEntry> = (0..OAM_ENTRY_COUNT)
+            .map(|idx| snapshot.oam_entry(idx))
+            .collect();
+        let selected_count = (0..OAM_ENTRY_COUNT)
+            .filter(|&idx| snapshot.obj_selected_on_line(idx, self.scanline))
+            .count();
+        let covering_count = entries
+            .iter()
+            .filter(|entry| entry.covers_line(self.scanline as u8, obj_height))
+            .count();
+        ui.horizontal(|ui| {
+            ui.colored_label(
+                SELECTED_COLOR,
+                format!("{}/{} selected", selected_count, MAX_OBJS_PER_LINE),
+            );
+            if covering_count > selected_count {
+                ui.colored_label(
+                    DROPPED_COLOR,
+                    format!("{} dropped", covering_count - selected_count),
+                );
+            }
+        });
+        ui.separator();
+
+        let preview_size = Vec2::new(SLOT_WIDTH as f32, obj_height as f32) * PREVIEW_SCALE;
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("oam_entries").striped(true).show(ui, |ui| {
+                for header in [
+                    "#", "", "X", "Y", "Tile", "Palette", "Bank", "Flip", "Priority",
+                ] {
+                    ui.strong(header);
+                }
+                ui.strong("Lines");
+                ui.end_row();
+
+                for (idx, entry) in entries.iter().enumerate() {
+                    let color = match (
+                        snapshot.obj_selected_on_line(idx, self.scanline),
+                        entry.covers_line(self.scanline as u8, obj_height),
+                    ) {
+                        (true, _) => Some(SELECTED_COLOR),
+                        (false, true) => Some(DROPPED_COLOR),
+                        (false, false) => None,
+                    };
+                    let text = |text: String| match color {
+                        Some(color) => RichText::new(text).color(color),
+                        None => RichText::new(text),
+                    };
+
+                    ui.label(text(idx.to_string()));
+                    let u = (idx * SLOT_WIDTH) as f32 / IMAGE_WIDTH as f32;
+                    let uv = Rect::from_min_max(
+                        pos2(u, 0.0),
+                        pos2(
+                            u + SLOT_WIDTH as f32 / IMAGE_WIDTH as f32,
+                            obj_height as f32 / SLOT_HEIGHT as f32,
+                        ),
+                    );
+                    ui.add(egui::Image::new(texture.id(), preview_size).uv(uv));
+                    ui.label(text(format!("{}", entry.x)));
+                    ui.label(text(format!("{}", entry.y)));
+                    ui.label(text(format!("{:#04x}", entry.tile_id)));
+                    ui.label(text(format!(
+                        "OBJ {} (DMG {})",
+                        entry.attributes.palette, entry.dmg_palette
+                    )));
+                    ui.label(text(format!("{:?}", entry.attributes.vram_bank)));
+                    ui.label(text(
+                        match (
+                            entry.attributes.horizontal_flip,
+                            entry.attributes.vertical_flip,
+                        ) {
+                            (false, false) => "-",
+                            (true, false) => "X",
+                            (false, true) => "Y",
+                            (true, true) => "XY",
+                        }
+                        .to_string(),
+                    ));
+                    ui.label(text(match entry.attributes.bg_priority {
+                        true => "Behind BG".to_string(),
+                        false => "Above BG".to_string(),
+                    }));
+                    ui.label(text(selected_lines(snapshot, idx)));
+                    ui.end_row();
+                }
+            });
+        });
+    }
+}
+
+/**
+ * First and last scanline the OAM scan picked an object for
+ */
+fn selected_lines(snapshot: &DebugSnapshot, idx: usize) -> String {
+    let mut lines =
+        (0..GBC_RESOLUTION_Y as usize).filter(|&ly| snapshot.obj_selected_on_line(idx, ly));
+    match (lines.next(), lines.next_back()) {
+        (None, _) => "-".to_string(),
+        (Some(first), None) => first.to_string(),
+        (Some(first), Some(last)) => format!("{}-{}", first, last),
+    }
+}
+
+/**
+ * Draw every object side by side in its own OBJ palette. Color 0 is transparent
+ */
+fn render_objs(snapshot: &DebugSnapshot) -> ColorImage {
+    let mut image = ColorImage::new([IMAGE_WIDTH, SLOT_HEIGHT], Color32::TRANSPARENT);
+    for idx in 0..OAM_ENTRY_COUNT {
+        let entry = snapshot.oam_entry(idx);
+        let pixels = snapshot.obj_color_indices(&entry);
+        for (i, &color_idx) in pixels.iter().enumerate() {
+            if color_idx == 0 {
+                continue;
+            }
+            let [r, g, b] =
+                snapshot.palette_color(PaletteKind::OBJ, entry.attributes.palette, color_idx);
+            let (x, y) = (idx * SLOT_WIDTH + i % SLOT_WIDTH, i / SLOT_WIDTH);
+            image.pixels[y * IMAGE_WIDTH + x] = Color32::from_rgb(r, g, b);
+        }
+    }
+    image
+}
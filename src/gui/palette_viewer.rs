@@ -0,0 +1,109 @@
+use eframe::egui::{self, Color32, Context, Sense, Stroke, Vec2};
+
+use gbc_emulator::gbc::{DebugSnapshot, GBCCommand, PaletteKind, PALETTES_PER_KIND};
+
+const COLORS_PER_PALETTE: u8 = 4;
+const SWATCH_SIZE: f32 = 24.0;
+const SELECTION_COLOR: Color32 = Color32::YELLOW;
+
+/**
+ * Shows all BG and OBJ palettes and lets one color at a time be edited
+ */
+pub struct PaletteViewer {
+    pub open: bool,
+    // (kind, palette, color index) of the swatch being edited
+    selected: Option<(PaletteKind, u8, u8)>,
+}
+
+impl PaletteViewer {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            selected: None,
+        }
+    }
+
+    /**
+     * Returns a command to send to the emulator if a color was edited
+     */
+    pub fn show(&mut self, ctx: &Context, snapshot: Option<&DebugSnapshot>) -> Option<GBCCommand> {
+        let mut open = self.open;
+        let mut command = None;
+        egui::Window::new("Palettes")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| match snapshot {
+                Some(snapshot) => {
+                    ui.horizontal(|ui| {
+                        for kind in [PaletteKind::BG, PaletteKind::OBJ] {
+                            ui.vertical(|ui| self.palettes_ui(ui, snapshot, kind));
+                        }
+                    });
+                    ui.separator();
+                    command = self.editor_ui(ui, snapshot);
+                }
+                None => {
+                    ui.label("No ROM running");
+                }
+            });
+        self.open = open;
+        command
+    }
+
+    fn palettes_ui(&mut self, ui: &mut egui::Ui, snapshot: &DebugSnapshot, kind: PaletteKind) {
+        ui.strong(format!("{:?}", kind));
+        for palette in 0..PALETTES_PER_KIND {
+            ui.horizontal(|ui| {
+                ui.label(palette.to_string());
+                for color_idx in 0..COLORS_PER_PALETTE {
+                    let (rect, response) =
+                        ui.allocate_exact_size(Vec2::splat(SWATCH_SIZE), Sense::click());
+                    let [r, g, b] = snapshot.palette_color(kind, palette, color_idx);
+                    ui.painter()
+                        .rect_filled(rect, 0.0, Color32::from_rgb(r, g, b));
+                    if self.selected == Some((kind, palette, color_idx)) {
+                        ui.painter()
+                            .rect_stroke(rect, 0.0, Stroke::new(2.0, SELECTION_COLOR));
+                    }
+                    if response.clicked() {
+                        self.selected = Some((kind, palette, color_idx));
+                    }
+                    response.on_hover_text(format!(
+                        "{:?} {} color {}\nRGB555 {:#06x}",
+                        kind,
+                        palette,
+                        color_idx,
+                        snapshot.palette_color_rgb555(kind, palette, color_idx)
+                    ));
+                }
+            });
+        }
+    }
+
+    fn editor_ui(&mut self, ui: &mut egui::Ui, snapshot: &DebugSnapshot) -> Option<GBCCommand> {
+        let Some((kind, palette, color_idx)) = self.selected else {
+            ui.label("Click a color to edit it");
+            return None;
+        };
+
+        let rgb555 = snapshot.palette_color_rgb555(kind, palette, color_idx);
+        let mut channels = [rgb555 & 0x1F, (rgb555 >> 5) & 0x1F, (rgb555 >> 10) & 0x1F];
+        ui.label(format!(
+            "{:?} {} color {}: {:#06x}",
+            kind, palette, color_idx, rgb555
+        ));
+        let mut changed = false;
+        for (channel, name) in channels.iter_mut().zip(["R", "G", "B"]) {
+            changed |= ui
+                .add(egui::Slider::new(channel, 0..=0x1F).text(name))
+                .changed();
+        }
+
+        changed.then(|| GBCCommand::SetPaletteColor {
+            kind,
+            palette,
+            color_idx,
+            rgb555: channels[0] | channels[1] << 5 | channels[2] << 10,
+        })
+    }
+}
@@ -0,0 +1,177 @@
+use std::{fs::File, io::BufWriter};
+
+use eframe::egui::{self, Context};
+
+use gbc_emulator::gbc::{DebugSnapshot, GBCCommand, ProfileReport};
+
+// Rows shown in each profile table
+const TOP_ENTRIES: usize = 20;
+
+/**
+ * Shows the shadow call stack and the cycles counted by the profiler. Profiles can be exported as
+ * folded stacks for flame graph tools
+ */
+pub struct ProfilerViewer {
+    pub open: bool,
+    // Whether the emulator was asked to profile
+    profiling: bool,
+    // Last report received from the emulator
+    report: Option<Box<ProfileReport>>,
+    // Result of the last export
+    export_status: Option<String>,
+}
+
+impl ProfilerViewer {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            profiling: false,
+            report: None,
+            export_status: None,
+        }
+    }
+
+    /**
+     * Forget the profile of an emulator that has stopped
+     */
+    pub fn clear(&mut self) {
+        self.profiling = false;
+        self.report = None;
+    }
+
+    pub fn set_report(&mut self, report: Box<ProfileReport>) {
+        self.report = Some(report);
+    }
+
+    /**
+     * Returns a command to send to the emulator if profiling was started, stopped, reset or
+     * refreshed
+     */
+    pub fn show(&mut self, ctx: &Context, snapshot: Option<&DebugSnapshot>) -> Option<GBCCommand> {
+        let mut open = self.open;
+        let mut command = None;
+        egui::Window::new("Profiler")
+            .open(&mut open)
+            .show(ctx, |ui| match snapshot {
+                Some(snapshot) => {
+                    call_stack_ui(ui, snapshot);
+                    ui.separator();
+                    command = self.controls_ui(ui);
+                    if let Some(ref report) = self.report {
+                        egui::ScrollArea::vertical().show(ui, |ui| report_ui(ui, report));
+                    }
+                }
+                None => {
+                    ui.label("No ROM running");
+                }
+            });
+        self.open = open;
+        command
+    }
+
+    fn controls_ui(&mut self, ui: &mut egui::Ui) -> Option<GBCCommand> {
+        let mut command = None;
+        ui.horizontal(|ui| {
+            if ui.checkbox(&mut self.profiling, "Profile").changed() {
+                command = Some(GBCCommand::SetProfiling(self.profiling));
+            }
+            if ui.button("Reset").clicked() {
+                self.report = None;
+                command = Some(GBCCommand::ResetProfile);
+            }
+            if ui.button("Refresh").clicked() {
+                command = Some(GBCCommand::RequestProfile);
+            }
+            if ui
+                .add_enabled(
+                    self.report.is_some(),
+                    egui::Button::new("Export folded stacks..."),
+                )
+                .clicked()
+            {
+                self.export_status = self.export();
+            }
+        });
+        if let Some(ref status) = self.export_status {
+            ui.label(status);
+        }
+        command
+    }
+
+    /**
+     * Save the last report where the user picks. Returns what happened, or None if cancelled
+     */
+    fn export(&self) -> Option<String> {
+        let report = self.report.as_ref()?;
+        let path = rfd::FileDialog::new()
+            .add_filter("Folded stacks", &["folded"])
+            .set_file_name("profile.folded")
+            .save_file()?;
+        let result = File::create(&path).and_then(|file| report.write_folded(BufWriter::new(file)));
+        Some(match result {
+            Ok(()) => format!("Saved {}", path.display()),
+            Err(e) => format!("Couldn't save {}: {}", path.display(), e),
+        })
+    }
+}
+
+fn call_stack_ui(ui: &mut egui::Ui, snapshot: &DebugSnapshot) {
+    ui.strong("Call stack");
+    if snapshot.call_stack.is_empty() {
+        ui.label("Not in a function");
+        return;
+    }
+    egui::Grid::new("call_stack").striped(true).show(ui, |ui| {
+        ui.strong("Function");
+        ui.strong("Entered by");
+        ui.strong("Returns to");
+        ui.end_row();
+        // Innermost first, like a debugger backtrace
+        for entry in snapshot.call_stack.iter().rev() {
+            ui.monospace(&entry.function);
+            ui.label(format!("{:?}", entry.kind));
+            ui.monospace(&entry.return_addr);
+            ui.end_row();
+        }
+    });
+}
+
+fn report_ui(ui: &mut egui::Ui, report: &ProfileReport) {
+    ui.label(format!("{} T-cycles profiled", report.total_cycles));
+    // Avoid dividing by zero before anything was counted
+    let percent = |cycles: u64| cycles as f64 * 100.0 / report.total_cycles.max(1) as f64;
+
+    ui.strong("Functions");
+    egui::Grid::new("profile_functions")
+        .striped(true)
+        .show(ui, |ui| {
+            ui.strong("Function");
+            ui.strong("Self");
+            ui.strong("Total");
+            ui.end_row();
+            for function in report.functions.iter().take(TOP_ENTRIES) {
+                ui.monospace(&function.name);
+                ui.label(format!("{:.1}%", percent(function.self_cycles)));
+                ui.label(format!("{:.1}%", percent(function.total_cycles)));
+                ui.end_row();
+            }
+        });
+
+    ui.strong("Instructions");
+    egui::Grid::new("profile_instructions")
+        .striped(true)
+        .show(ui, |ui| {
+            ui.strong("Address");
+            ui.strong("Cycles");
+            ui.end_row();
+            for instruction in report.instructions.iter().take(TOP_ENTRIES) {
+                ui.monospace(&instruction.location);
+                ui.label(format!(
+                    "{} ({:.1}%)",
+                    instruction.cycles,
+                    percent(instruction.cycles)
+                ));
+                ui.end_row();
+            }
+        });
+}
@@ -0,0 +1,195 @@
+use eframe::egui::{
+    self, Color32, ColorImage, Context, Rect, Sense, Stroke, TextureHandle, TextureOptions, Vec2,
+};
+
+use gbc_emulator::gbc::{
+    DebugSnapshot, PaletteKind, TileMapArea, GBC_RESOLUTION_X, GBC_RESOLUTION_Y, TILE_MAP_SIZE,
+};
+
+const IMAGE_SIZE: usize = TILE_MAP_SIZE * 8;
+// WX is offset by 7 pixels from the screen coordinate the window starts at
+const WINDOW_X_OFFSET: i32 = 7;
+const VIEWPORT_COLOR: Color32 = Color32::RED;
+const WINDOW_COLOR: Color32 = Color32::GREEN;
+const SELECTION_COLOR: Color32 = Color32::YELLOW;
+
+/**
+ * Shows one of the two 32x32 tile maps with the current viewport and window drawn on top
+ */
+pub struct TileMapViewer {
+    pub open: bool,
+    map_area: TileMapArea,
+    // (x, y) of the clicked tile
+    selected: Option<(usize, usize)>,
+    texture: Option<TextureHandle>,
+}
+
+impl TileMapViewer {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            map_area: TileMapArea::Map0,
+            selected: None,
+            texture: None,
+        }
+    }
+
+    pub fn show(&mut self, ctx: &Context, snapshot: Option<&DebugSnapshot>) {
+        let mut open = self.open;
+        egui::Window::new("Tile Maps")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut self.map_area, TileMapArea::Map0, "0x9800");
+                    ui.radio_value(&mut self.map_area, TileMapArea::Map1, "0x9C00");
+                });
+                match snapshot {
+                    Some(snapshot) => {
+                        self.map_ui(ctx, ui, snapshot);
+                        self.selected_tile_ui(ui, snapshot);
+                    }
+                    None => {
+                        ui.label("No ROM running");
+                    }
+                }
+            });
+        self.open = open;
+    }
+
+    fn map_ui(&mut self, ctx: &Context, ui: &mut egui::Ui, snapshot: &DebugSnapshot) {
+        let image = self.render_map(snapshot);
+        let texture = match self.texture {
+            Some(ref mut texture) => {
+                texture.set(image, TextureOptions::NEAREST);
+                texture
+            }
+            None => {
+                self.texture
+                    .insert(ctx.load_texture("tile_map", image, TextureOptions::NEAREST))
+            }
+        };
+
+        let response =
+            ui.add(egui::Image::new(texture.id(), texture.size_vec2()).sense(Sense::click()));
+        if response.clicked() {
+            if let Some(pos) = response.interact_pointer_pos() {
+                let offset = pos - response.rect.min;
+                let x = (offset.x as usize / 8).min(TILE_MAP_SIZE - 1);
+                let y = (offset.y as usize / 8).min(TILE_MAP_SIZE - 1);
+                self.selected = Some((x, y));
+            }
+        }
+
+        let painter = ui.painter_at(response.rect);
+        let origin = response.rect.min;
+        let lcd_control = snapshot.lcd_control();
+
+        if lcd_control.bg_tile_map_area == self.map_area {
+            // The viewport wraps around the edges of the map, so draw it once per wrapped copy
+            let (scx, scy) = snapshot.scroll();
+            let size = Vec2::new(GBC_RESOLUTION_X as f32, GBC_RESOLUTION_Y as f32);
+            for dx in [0.0, -(IMAGE_SIZE as f32)] {
+                for dy in [0.0, -(IMAGE_SIZE as f32)] {
+                    let min = origin + Vec2::new(scx as f32 + dx, scy as f32 + dy);
+                    painter.rect_stroke(
+                        Rect::from_min_size(min, size),
+                        0.0,
+                        Stroke::new(1.0, VIEWPORT_COLOR),
+                    );
+                }
+            }
+        }
+
+        let (wx, wy) = snapshot.window_position();
+        let window_x = wx as i32 - WINDOW_X_OFFSET;
+        let window_visible = lcd_control.window_enable
+            && window_x < GBC_RESOLUTION_X as i32
+            && wy < GBC_RESOLUTION_Y;
+        if window_visible && lcd_control.window_tile_map_area == self.map_area {
+            // The window always draws its map from the top left corner
+            let size = Vec2::new(
+                (GBC_RESOLUTION_X as i32 - window_x.max(0)) as f32,
+                (GBC_RESOLUTION_Y - wy) as f32,
+            );
+            painter.rect_stroke(
+                Rect::from_min_size(origin, size),
+                0.0,
+                Stroke::new(1.0, WINDOW_COLOR),
+            );
+        }
+
+        if let Some((x, y)) = self.selected {
+            let min = origin + Vec2::new((x * 8) as f32, (y * 8) as f32);
+            painter.rect_stroke(
+                Rect::from_min_size(min, Vec2::splat(8.0)),
+                0.0,
+                Stroke::new(1.0, SELECTION_COLOR),
+            );
+        }
+
+        ui.label(match window_visible {
+            true => format!("Window at ({}, {})", window_x, wy),
+            false => "Window hidden".to_string(),
+        });
+    }
+
+    fn selected_tile_ui(&self, ui: &mut egui::Ui, snapshot: &DebugSnapshot) {
+        let Some((x, y)) = self.selected else {
+            ui.label("Click a tile to inspect it");
+            return;
+        };
+        let entry = snapshot.tile_map_entry(self.map_area, x, y);
+        let attributes = entry.attributes;
+        egui::Grid::new("selected_tile").show(ui, |ui| {
+            ui.label("Position");
+            ui.label(format!("({}, {})", x, y));
+            ui.end_row();
+            ui.label("Map address");
+            ui.label(format!("{:#06x}", entry.map_addr));
+            ui.end_row();
+            ui.label("Tile ID");
+            ui.label(format!("{:#04x}", entry.tile_id));
+            ui.end_row();
+            ui.label("Data address");
+            ui.label(format!(
+                "{:#06x} ({:?})",
+                entry.data_addr, attributes.vram_bank
+            ));
+            ui.end_row();
+            ui.label("Palette");
+            ui.label(format!("BG {}", attributes.palette));
+            ui.end_row();
+            ui.label("Flip");
+            ui.label(format!(
+                "X: {}, Y: {}",
+                attributes.horizontal_flip, attributes.vertical_flip
+            ));
+            ui.end_row();
+            ui.label("BG priority");
+            ui.label(attributes.bg_priority.to_string());
+            ui.end_row();
+        });
+    }
+
+    fn render_map(&self, snapshot: &DebugSnapshot) -> ColorImage {
+        let mut rgb = vec![0; IMAGE_SIZE * IMAGE_SIZE * 3];
+        for tile_y in 0..TILE_MAP_SIZE {
+            for tile_x in 0..TILE_MAP_SIZE {
+                let entry = snapshot.tile_map_entry(self.map_area, tile_x, tile_y);
+                let pixels = snapshot.tile_map_entry_color_indices(&entry);
+                for (i, &color_idx) in pixels.iter().enumerate() {
+                    let color = snapshot.palette_color(
+                        PaletteKind::BG,
+                        entry.attributes.palette,
+                        color_idx,
+                    );
+                    let (x, y) = (tile_x * 8 + i % 8, tile_y * 8 + i / 8);
+                    let offset = (y * IMAGE_SIZE + x) * 3;
+                    rgb[offset..offset + 3].copy_from_slice(&color);
+                }
+            }
+        }
+        ColorImage::from_rgb([IMAGE_SIZE, IMAGE_SIZE], &rgb)
+    }
+}
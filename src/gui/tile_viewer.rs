@@ -0,0 +1,118 @@
+use eframe::egui::{self, ColorImage, Context, TextureHandle, TextureOptions};
+
+use gbc_emulator::gbc::{DebugSnapshot, PaletteKind, PALETTES_PER_KIND, TILES_PER_BANK};
+
+const TILES_PER_ROW: usize = 16;
+const TILE_ROWS: usize = TILES_PER_BANK / TILES_PER_ROW;
+// Both banks are drawn side by side
+const IMAGE_WIDTH: usize = 2 * TILES_PER_ROW * 8;
+const IMAGE_HEIGHT: usize = TILE_ROWS * 8;
+const IMAGE_SCALE: f32 = 2.0;
+
+/**
+ * Shows every tile in both VRAM banks drawn with a selectable palette
+ */
+pub struct TileViewer {
+    pub open: bool,
+    palette_kind: PaletteKind,
+    palette: u8,
+    texture: Option<TextureHandle>,
+}
+
+impl TileViewer {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            palette_kind: PaletteKind::BG,
+            palette: 0,
+            texture: None,
+        }
+    }
+
+    pub fn show(&mut self, ctx: &Context, snapshot: Option<&DebugSnapshot>) {
+        let mut open = self.open;
+        egui::Window::new("VRAM Tiles")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                self.palette_selector_ui(ui);
+                match snapshot {
+                    Some(snapshot) => self.tiles_ui(ctx, ui, snapshot),
+                    None => {
+                        ui.label("No ROM running");
+                    }
+                }
+            });
+        self.open = open;
+    }
+
+    fn palette_selector_ui(&mut self, ui: &mut egui::Ui) {
+        let selected = format!("{:?} {}", self.palette_kind, self.palette);
+        egui::ComboBox::from_label("Palette")
+            .selected_text(selected)
+            .show_ui(ui, |ui| {
+                for kind in [PaletteKind::BG, PaletteKind::OBJ] {
+                    for palette in 0..PALETTES_PER_KIND {
+                        let label = format!("{:?} {}", kind, palette);
+                        let selected = self.palette_kind == kind && self.palette == palette;
+                        if ui.selectable_label(selected, label).clicked() {
+                            self.palette_kind = kind;
+                            self.palette = palette;
+                        }
+                    }
+                }
+            });
+    }
+
+    fn tiles_ui(&mut self, ctx: &Context, ui: &mut egui::Ui, snapshot: &DebugSnapshot) {
+        let image = self.render_tiles(snapshot);
+        let texture = match self.texture {
+            Some(ref mut texture) => {
+                texture.set(image, TextureOptions::NEAREST);
+                texture
+            }
+            None => {
+                self.texture
+                    .insert(ctx.load_texture("vram_tiles", image, TextureOptions::NEAREST))
+            }
+        };
+
+        let size = texture.size_vec2() * IMAGE_SCALE;
+        let response = ui.image(texture.id(), size);
+        if let Some(pos) = response.hover_pos() {
+            let offset = (pos - response.rect.min) / IMAGE_SCALE;
+            let x = (offset.x as usize).min(IMAGE_WIDTH - 1);
+            let y = (offset.y as usize).min(IMAGE_HEIGHT - 1);
+            let bank = x / (TILES_PER_ROW * 8);
+            let tile_idx = (y / 8) * TILES_PER_ROW + (x % (TILES_PER_ROW * 8)) / 8;
+            response.on_hover_text(format!(
+                "Tile {} ({:#05x})\nAddress {:#06x}\nBank {}",
+                tile_idx,
+                tile_idx,
+                DebugSnapshot::tile_addr(tile_idx),
+                bank
+            ));
+        }
+    }
+
+    fn render_tiles(&self, snapshot: &DebugSnapshot) -> ColorImage {
+        let colors: [[u8; 3]; 4] = core::array::from_fn(|color_idx| {
+            snapshot.palette_color(self.palette_kind, self.palette, color_idx as u8)
+        });
+
+        let mut rgb = vec![0; IMAGE_WIDTH * IMAGE_HEIGHT * 3];
+        for bank in 0..2 {
+            for tile_idx in 0..TILES_PER_BANK {
+                let tile_x = bank * TILES_PER_ROW * 8 + (tile_idx % TILES_PER_ROW) * 8;
+                let tile_y = (tile_idx / TILES_PER_ROW) * 8;
+                let pixels = snapshot.tile_color_indices(bank, tile_idx);
+                for (i, &color_idx) in pixels.iter().enumerate() {
+                    let (x, y) = (tile_x + i % 8, tile_y + i / 8);
+                    let offset = (y * IMAGE_WIDTH + x) * 3;
+                    rgb[offset..offset + 3].copy_from_slice(&colors[color_idx as usize]);
+                }
+            }
+        }
+        ColorImage::from_rgb([IMAGE_WIDTH, IMAGE_HEIGHT], &rgb)
+    }
+}
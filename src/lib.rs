@@ -0,0 +1,2 @@
+pub mod gbc;
+mod util;
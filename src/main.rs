@@ -1,62 +1,202 @@
-mod gbc;
+mod cli;
 mod gui;
-mod util;
 
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
 use std::path::PathBuf;
+use std::any::Any;
+use std::process::ExitCode;
+use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::thread;
 use std::thread::JoinHandle;
 
+use clap::Parser;
 use color_eyre::eyre::{eyre, Result};
-use eframe::egui::Context;
+use eframe::egui::{Context, Vec2};
 use egui_extras::RetainedImage;
 use tracing::info_span;
 
-use crate::gbc::GBC;
+use gbc_emulator::gbc::{
+    Buttons, DebugSnapshot, EmulationSpeed, GBCCommand, GBCEvent, GBC, GBC_RESOLUTION_X,
+    GBC_RESOLUTION_Y,
+};
 
-fn main() -> Result<()> {
+use cli::{Cli, HardwareArgs};
+use gui::{
+    CheatManager, DisassemblyViewer, IORegisterViewer, MemoryViewer, OAMViewer, PaletteViewer,
+    ProfilerViewer, TileMapViewer, TileViewer,
+};
+
+// Room around the screen for the menu bar, status bar and panel margins
+const WINDOW_CHROME_SIZE: Vec2 = Vec2::new(16.0, 96.0);
+
+fn main() -> Result<ExitCode> {
     // Log to stdout (if you run with `RUST_LOG=debug`).
     tracing_subscriber::fmt::init();
 
-    let native_options = eframe::NativeOptions::default();
+    let cli = Cli::parse();
+    if let Some(command) = cli.command {
+        return cli::run_command(command, &cli.hardware);
+    }
+
+    let scale = cli.window.scale as f32;
+    let screen_size = Vec2::new(GBC_RESOLUTION_X.into(), GBC_RESOLUTION_Y.into()) * scale;
+    let native_options = eframe::NativeOptions {
+        initial_window_size: Some(screen_size + WINDOW_CHROME_SIZE),
+        fullscreen: cli.window.fullscreen,
+        ..Default::default()
+    };
     eframe::run_native(
         "GBC",
         native_options,
-        Box::new(|_cc| Box::new(App::default())),
+        Box::new(move |cc| {
+            let mut app = App::new(
+                cli.hardware,
+                cli.window.save_dir,
+                cli.window.screenshot_dir,
+                cli.window.screenshot_scale,
+                cli.window.recording_dir,
+                scale,
+            );
+            if let Some(rom) = cli.rom {
+                app.spawn_gbc(rom, &cc.egui_ctx);
+                if cli.window.start_paused {
+                    app.send_gbc_command(GBCCommand::Pause);
+                }
+            }
+            Box::new(app)
+        }),
     )
-    .map_err(|e| eyre!(e.to_string()))
+    .map_err(|e| eyre!(e.to_string()))?;
+    Ok(ExitCode::SUCCESS)
 }
 
 struct App {
     gbc: Option<GBCThread>,
+    // Fault or error reported by the emulator that hasn't been dismissed yet
+    fault: Option<String>,
+    // Speed selected in the menu
+    speed: EmulationSpeed,
+    // Whether the fast forward key is being held
+    fast_forwarding: bool,
+    // Whether the rewind key is being held
+    rewinding: bool,
+    // Joypad buttons held on the keyboard
+    held_buttons: Buttons,
+    // Hardware every opened ROM runs on
+    hardware: HardwareArgs,
+    // Where battery backed cartridge RAM is saved. Not saved if None
+    save_dir: Option<PathBuf>,
+    screenshot_dir: PathBuf,
+    // Screenshot size as a multiple of the native resolution
+    screenshot_scale: u8,
+    recording_dir: PathBuf,
+    // Outcome of the last action worth telling the user about, shown in the status bar
+    status_message: Option<String>,
+    // Screen size as a multiple of the native resolution
+    scale: f32,
+    // Latest memory published for the debug windows
+    debug_snapshot: Option<Box<DebugSnapshot>>,
+    // Whether the emulator was last asked to publish debug snapshots
+    debug_capture: bool,
+    // Whether the emulator was asked to copy memory for the memory viewer
+    memory_capture: bool,
+    tile_viewer: TileViewer,
+    tile_map_viewer: TileMapViewer,
+    oam_viewer: OAMViewer,
+    palette_viewer: PaletteViewer,
+    memory_viewer: MemoryViewer,
+    io_register_viewer: IORegisterViewer,
+    disassembly_viewer: DisassemblyViewer,
+    profiler_viewer: ProfilerViewer,
+    cheat_manager: CheatManager,
 }
 impl App {
-    fn default() -> Self {
-        Self { gbc: None }
+    fn new(
+        hardware: HardwareArgs,
+        save_dir: Option<PathBuf>,
+        screenshot_dir: PathBuf,
+        screenshot_scale: u8,
+        recording_dir: PathBuf,
+        scale: f32,
+    ) -> Self {
+        Self {
+            gbc: None,
+            fault: None,
+            speed: EmulationSpeed::Multiplier(1.0),
+            fast_forwarding: false,
+            rewinding: false,
+            held_buttons: Buttons::default(),
+            hardware,
+            save_dir,
+            screenshot_dir,
+            screenshot_scale,
+            recording_dir,
+            status_message: None,
+            scale,
+            debug_snapshot: None,
+            debug_capture: false,
+            memory_capture: false,
+            tile_viewer: TileViewer::new(),
+            tile_map_viewer: TileMapViewer::new(),
+            oam_viewer: OAMViewer::new(),
+            palette_viewer: PaletteViewer::new(),
+            memory_viewer: MemoryViewer::new(),
+            io_register_viewer: IORegisterViewer::new(),
+            disassembly_viewer: DisassemblyViewer::new(),
+            profiler_viewer: ProfilerViewer::new(),
+            cheat_manager: CheatManager::new(),
+        }
+    }
+
+    fn effective_speed(&self) -> EmulationSpeed {
+        match self.fast_forwarding {
+            true => EmulationSpeed::Uncapped,
+            false => self.speed,
+        }
     }
 
     fn spawn_gbc(&mut self, path: PathBuf, gui_ctx: &Context) {
+        // Only one ROM can be running at a time
+        self.close_gbc();
+        self.cheat_manager.load_for_rom(&path);
+
         let display_buffer = Arc::new(Mutex::new(RetainedImage::from_color_image(
             "start_frame",
             eframe::epaint::ColorImage::example(),
         )));
         let display_buffer_for_gbc_thread = Arc::clone(&display_buffer);
         let gui_ctx_clone = gui_ctx.clone();
+        let (event_sender, events) = mpsc::channel();
+        let (commands, command_receiver) = mpsc::channel();
+        let hardware = self.hardware.clone();
+        let save_dir = self.save_dir.clone();
+        let screenshot_dir = self.screenshot_dir.clone();
+        let recording_dir = self.recording_dir.clone();
 
         let handle = thread::spawn(move || -> Result<()> {
             let span = info_span!("GBC Thread").entered();
 
+            let mut config = hardware.config_for(&path, save_dir.as_deref())?;
+            config.screenshot_dir = screenshot_dir;
+            config.recording_dir = recording_dir;
             let file = File::open(path)?;
             let mut buf_reader = BufReader::new(file);
             let mut rom_data = Vec::new();
             buf_reader.read_to_end(&mut rom_data)?;
-            let mut gbc = GBC::new(rom_data, display_buffer_for_gbc_thread, gui_ctx_clone)?;
-            gbc.run();
-            
+            let mut gbc = GBC::new(
+                rom_data,
+                config,
+                display_buffer_for_gbc_thread,
+                Some(gui_ctx_clone),
+                event_sender,
+                command_receiver,
+            )?;
+            gbc.run()?;
+
             span.exit();
             Ok(())
         });
@@ -64,11 +204,102 @@ impl App {
         self.gbc = Some(GBCThread {
             handle,
             display_buffer,
+            events,
+            commands,
+            paused: false,
+            frame_rate: None,
+            recording: false,
+            movie_recording: false,
+            movie_playing: false,
         });
+        self.send_gbc_command(GBCCommand::SetSpeed(self.effective_speed()));
+        self.send_gbc_command(GBCCommand::SetButtons(self.held_buttons));
+    }
+
+    /**
+     * Stop the running emulator thread (if any) and wait for it to exit
+     */
+    fn close_gbc(&mut self) {
+        if let Some(gbc) = self.gbc.take() {
+            // Thread may have already exited on its own
+            gbc.commands.send(GBCCommand::Shutdown).ok();
+            self.join_gbc_thread(gbc);
+        }
+    }
+
+    /**
+     * Collect the emulator thread if it stopped without being asked to
+     */
+    fn check_gbc_thread(&mut self) {
+        let finished = match self.gbc {
+            Some(ref gbc) => gbc.handle.is_finished(),
+            None => false,
+        };
+        if finished {
+            let gbc = self.gbc.take().unwrap();
+            self.join_gbc_thread(gbc);
+        }
+    }
+
+    fn join_gbc_thread(&mut self, gbc: GBCThread) {
+        // The next emulator thread has to be asked for debug snapshots again
+        self.debug_snapshot = None;
+        self.debug_capture = false;
+        self.memory_capture = false;
+        self.profiler_viewer.clear();
+        self.cheat_manager.clear();
+        match gbc.handle.join() {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                self.fault = Some(format!("The emulator stopped with an error:\n{:?}", e))
+            }
+            Err(panic) => {
+                self.fault = Some(format!(
+                    "The emulator thread panicked:\n{}",
+                    panic_message(panic.as_ref())
+                ))
+            }
+        }
+    }
+
+    fn send_gbc_command(&mut self, command: GBCCommand) {
+        let Some(ref mut gbc) = self.gbc else {
+            return;
+        };
+        match command {
+            GBCCommand::Pause => gbc.paused = true,
+            GBCCommand::Resume => {
+                gbc.paused = false;
+                gbc.frame_rate = None;
+            }
+            _ => {}
+        }
+        // Errors are picked up by check_gbc_thread if the thread has exited
+        gbc.commands.send(command).ok();
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message
+    } else {
+        "Unknown panic"
     }
 }
 
 struct GBCThread {
     handle: JoinHandle<Result<()>>,
     display_buffer: Arc<Mutex<RetainedImage>>,
+    events: Receiver<GBCEvent>,
+    commands: Sender<GBCCommand>,
+    paused: bool,
+    // Last reported (FPS, percentage of native speed)
+    frame_rate: Option<(f32, f32)>,
+    // Whether frames are being recorded
+    recording: bool,
+    // Whether a movie is being recorded or played
+    movie_recording: bool,
+    movie_playing: bool,
 }
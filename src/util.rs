@@ -62,3 +62,41 @@ impl Bytes for u16 {
         self as u8
     }
 }
+
+/**
+ * Stand-in for an entered span when hot path instrumentation is compiled out
+ */
+#[cfg(not(any(debug_assertions, feature = "trace-instrumentation")))]
+pub struct DisabledSpan;
+
+#[cfg(not(any(debug_assertions, feature = "trace-instrumentation")))]
+impl DisabledSpan {
+    pub fn exit(self) {}
+}
+
+/**
+ * debug_span! for code that runs per instruction or per byte. Only built into debug builds or with
+ * the trace-instrumentation feature. Otherwise the arguments aren't evaluated at all. Returns the
+ * entered span.
+ */
+macro_rules! hot_debug_span {
+    ($($args:tt)*) => {{
+        #[cfg(any(debug_assertions, feature = "trace-instrumentation"))]
+        let span = tracing::debug_span!($($args)*).entered();
+        #[cfg(not(any(debug_assertions, feature = "trace-instrumentation")))]
+        let span = $crate::util::DisabledSpan;
+        span
+    }};
+}
+pub(crate) use hot_debug_span;
+
+/**
+ * trace! for code that runs per instruction or per byte. Compiled out like hot_debug_span!
+ */
+macro_rules! hot_trace {
+    ($($args:tt)*) => {
+        #[cfg(any(debug_assertions, feature = "trace-instrumentation"))]
+        tracing::trace!($($args)*)
+    };
+}
+pub(crate) use hot_trace;